@@ -0,0 +1,44 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Patch arbitrary bytes as a device tree.
+//!
+//! Patching must only ever overwrite bytes in place, and patching twice must give the same result
+//! as patching once. Patching must not change the memory range that the blob lists, either. The
+//! `bootargs` never include their terminating NUL.
+
+#![no_main]
+
+use kernel_fuzz::fdt;
+use libfuzzer_sys::fuzz_target;
+
+fn release_addr(reg: u64) -> Option<u64> {
+    if reg < 4 {
+        Some(0xd8 + 8 * reg)
+    } else {
+        None
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let memory = fdt::memory_range(data);
+    if let Ok(Some(bootargs)) = fdt::bootargs(data) {
+        assert!(!bootargs.contains(&0));
+    }
+    let mut blob = data.to_vec();
+
+    let patched = match fdt::patch_cpu_release_addrs(&mut blob, release_addr) {
+        Ok(patched) => patched,
+        Err(_) => return,
+    };
+    assert_eq!(blob.len(), data.len());
+
+    let mut again = blob.clone();
+    assert_eq!(
+        fdt::patch_cpu_release_addrs(&mut again, release_addr),
+        Ok(patched)
+    );
+    assert_eq!(again, blob);
+    assert_eq!(fdt::memory_range(&blob), memory);
+});
@@ -0,0 +1,44 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Parse arbitrary bytes as a staged payload.
+//!
+//! Every accepted plan must only copy from within the image, only write to the payload window and
+//! never write over the staged image it still copies from.
+
+#![no_main]
+
+use kernel_fuzz::{contains, image, protocol, WINDOW};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Stage the image just like the protocol would.
+    let image_addr = match (WINDOW.end - WINDOW.start).checked_sub(data.len()) {
+        Some(_) => (WINDOW.end - data.len()) & !(protocol::STAGING_ALIGN - 1),
+        None => return,
+    };
+    let staged = image_addr..image_addr + data.len();
+
+    let plan = match image::parse(data, image_addr, WINDOW.start, &WINDOW) {
+        Ok(plan) => plan,
+        Err(_) => return,
+    };
+
+    assert!(!plan.segments().is_empty());
+    for segment in plan.segments() {
+        assert!(contains(&(0..data.len()), &segment.file), "{:x?}", segment);
+        assert!(contains(&WINDOW, &segment.dest), "{:x?}", segment);
+        assert!(segment.file.len() <= segment.dest.len(), "{:x?}", segment);
+
+        if plan.format == image::Format::Elf {
+            assert!(
+                segment.dest.end <= staged.start || staged.end <= segment.dest.start,
+                "{:x?} overlaps the staged image",
+                segment
+            );
+        }
+    }
+    assert!(contains(&WINDOW, &plan.span()));
+    assert!(WINDOW.contains(&plan.entry));
+});
@@ -0,0 +1,55 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Feed arbitrary bytes to the loader's protocol state machine.
+//!
+//! Every store must hit the staging area, the staging area must lie within the payload window, and
+//! no more bytes may be stored than the announced size.
+
+#![no_main]
+
+use kernel_fuzz::{contains, protocol, WINDOW};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte shrinks the window, so that small sizes get rejected as well.
+    let (window, stream) = match data.split_first() {
+        Some((&shrink, stream)) => (
+            WINDOW.start..WINDOW.end - usize::from(shrink) * 0x2_0000,
+            stream,
+        ),
+        None => return,
+    };
+
+    let mut receiver = protocol::Receiver::new(window.clone());
+    let mut announced = None;
+    let mut stored = 0;
+
+    for &byte in stream {
+        match receiver.feed(byte) {
+            protocol::Event::SizeAccepted => {
+                let size = u32::from_le_bytes([stream[0], stream[1], stream[2], stream[3]]);
+                announced = Some(size as usize);
+            }
+            protocol::Event::SizeRejected { .. } => assert!(announced.is_none()),
+            protocol::Event::Store { addr, .. } => {
+                assert!(
+                    window.contains(&addr),
+                    "Store to {:#x} outside of the window",
+                    addr
+                );
+                stored += 1;
+                assert!(stored <= announced.unwrap(), "More stores than announced");
+            }
+            protocol::Event::Pending | protocol::Event::Ignored => (),
+        }
+    }
+
+    if let Some(staged) = receiver.staged() {
+        assert!(contains(&window, &staged));
+        assert_eq!(staged.start % protocol::STAGING_ALIGN, 0);
+        assert_eq!(staged.len(), stored);
+        assert_eq!(Some(stored), announced);
+    }
+});
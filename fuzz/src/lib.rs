@@ -0,0 +1,40 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! The loader's parsers, built for the host.
+//!
+//! `kernel::loader` only depends on `core`, so its modules are compiled straight from the kernel's
+//! sources. This keeps the fuzz targets independent of the kernel's pinned toolchain and of its
+//! target-only dependencies. Their `#[test_case]` tests need the kernel's test runner, so the
+//! modules are left out of this crate's own tests.
+
+use std::ops::Range;
+
+#[cfg(not(test))]
+#[path = "../../src/loader/fdt.rs"]
+pub mod fdt;
+
+#[cfg(not(test))]
+#[path = "../../src/loader/image.rs"]
+pub mod image;
+
+#[cfg(not(test))]
+#[path = "../../src/loader/protocol.rs"]
+pub mod protocol;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The Raspberry Pi's payload window: From the default load address up to the relocated loader.
+pub const WINDOW: Range<usize> = 0x8_0000..0x200_0000;
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Check if `inner` lies completely within `outer`.
+pub fn contains(outer: &Range<usize>, inner: &Range<usize>) -> bool {
+    outer.start <= inner.start && inner.end <= outer.end
+}
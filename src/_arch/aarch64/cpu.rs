@@ -1,37 +1,95 @@
-
-
 //! Architectural processor code.
 
-use crate::{bsp, cpu};
+use crate::{
+    bsp, cpu,
+    exception::{self, PrivilegeLevel},
+    hypervisor, memory,
+};
+use core::{ptr, time::Duration};
 use cortex_a::{asm, regs::*};
 
 // =============================================================================
 // BOOT CODE
 // =============================================================================
 
-/// The entry of the `kernel` binary
-///
-/// The function must be named `_start`, because the linker is looking for this exact name
+// Assembly counterpart to this section: `_start()`, the entry of the `kernel` binary.
+global_asm!(include_str!("cpu/boot.S"));
+
+/// The boot core's id, for `_start()`.
+#[no_mangle]
+static BOOT_CORE_ID: u64 = bsp::cpu::BOOT_CORE_ID as u64;
+
+/// Where the boot core continues from `_start()`, on its stack in the binary the firmware loaded.
 ///
 /// # Safety
 ///
-/// - Linker script must ensure to place this function at `0x80_000`.
-
-#[naked]
+/// - Only the boot core may run this, with the device tree address that the firmware passed.
+/// - The binary is not relocated yet, so this must not touch any statics.
 #[no_mangle]
-pub unsafe extern "C" fn _start() -> ! {
-    use crate::relocate;
+unsafe extern "C" fn _start_rust(dtb_addr: usize) -> ! {
+    init_fp();
+    crate::relocate::relocate_self(dtb_addr)
+}
+
+// -----------------------------------------------------------------------------
+// PRIVATE DEFINITIONS
+// -----------------------------------------------------------------------------
 
-    // Expect the boot core to start in EL2
-    if bsp::cpu::BOOT_CORE_ID == cpu::smp::core_id() {
-        SP.set(bsp::cpu::BOOT_CORE_STACK_START);
-        relocate::relocate_self::<u64>()
+/// CPTR_EL2.TFP, which is missing from `cortex_a`: Trap FP/SIMD instructions at EL2 and below.
+const CPTR_EL2_TFP: u64 = 1 << 10;
+
+/// CPACR_EL1.FPEN, which is missing from `cortex_a`: Do not trap FP/SIMD instructions at EL1 and
+/// EL0.
+const CPACR_EL1_FPEN: u64 = 0b11 << 20;
+
+// -----------------------------------------------------------------------------
+// GLOBAL INSTANCES
+// -----------------------------------------------------------------------------
+
+/// The payload gets to use FP/SIMD. Only accessed with volatile accesses, because cores that enter
+/// the payload later read it with their caches off. Turning the MMU off cleans it to DRAM.
+static mut PAYLOAD_FP: bool = false;
+
+/// Where the payload runs. Accessed like `PAYLOAD_FP`.
+static mut PAYLOAD_LEVEL: PrivilegeLevel = PrivilegeLevel::Hypervisor;
+
+// -----------------------------------------------------------------------------
+// PRIVATE CODE
+// -----------------------------------------------------------------------------
+
+/// Trap FP/SIMD at EL2 and below, or not.
+#[inline(always)]
+unsafe fn set_el2_fp_trap(trap: bool) {
+    let mut cptr: u64;
+    llvm_asm!("mrs $0, CPTR_EL2" : "=r"(cptr) ::: "volatile");
+
+    if trap {
+        cptr |= CPTR_EL2_TFP;
     } else {
-        // If not core0, sleep
-        wait_forever();
+        cptr &= !CPTR_EL2_TFP;
     }
+
+    llvm_asm!("msr CPTR_EL2, $0
+               isb"
+        :: "r"(cptr) :: "volatile");
 }
 
+/// Trap FP/SIMD at EL1 and EL0, or not.
+#[inline(always)]
+unsafe fn set_el1_fp_trap(trap: bool) {
+    let mut cpacr: u64;
+    llvm_asm!("mrs $0, CPACR_EL1" : "=r"(cpacr) ::: "volatile");
+
+    if trap {
+        cpacr &= !CPACR_EL1_FPEN;
+    } else {
+        cpacr |= CPACR_EL1_FPEN;
+    }
+
+    llvm_asm!("msr CPACR_EL1, $0
+               isb"
+        :: "r"(cpacr) :: "volatile");
+}
 
 // -----------------------------------------------------------------------------
 // PUBLIC CODE
@@ -39,6 +97,66 @@ pub unsafe extern "C" fn _start() -> ! {
 
 pub use asm::nop;
 
+/// Let the executing core use FP/SIMD at EL2, and the loader's copy routines use the SIMD
+/// registers.
+///
+/// Does not touch any statics through the GOT, so that it can run before the loader is relocated.
+///
+/// # Safety
+///
+/// - The SIMD registers must not hold a payload's state anymore.
+#[inline(always)]
+pub unsafe fn init_fp() {
+    set_el2_fp_trap(false);
+    memory::set_simd_copy(true);
+}
+
+/// Choose whether payloads get to use FP/SIMD. Takes effect for every core that enters the payload
+/// afterwards.
+///
+/// # Safety
+///
+/// - No core may run a payload.
+pub unsafe fn set_payload_fp(enabled: bool) {
+    ptr::write_volatile(&mut PAYLOAD_FP, enabled);
+}
+
+/// Choose the level at which payloads run. Takes effect for every core that enters the payload
+/// through the spin-table afterwards, like the boot core does.
+///
+/// # Safety
+///
+/// - No core may run a payload.
+pub unsafe fn set_payload_level(level: PrivilegeLevel) {
+    ptr::write_volatile(&mut PAYLOAD_LEVEL, level);
+}
+
+/// The level that `set_payload_level()` chose.
+pub fn payload_level() -> PrivilegeLevel {
+    unsafe { ptr::read_volatile(&PAYLOAD_LEVEL) }
+}
+
+/// Hand FP/SIMD over to a payload that is about to be entered at `level` on the executing core.
+///
+/// The loader's copy routines stop using the SIMD registers, whose contents belong to the payload
+/// from now on. Unless `set_payload_fp()` allowed it, FP/SIMD instructions trap at `level`.
+///
+/// # Safety
+///
+/// - The loader must not run anything but exception handlers on any core afterwards.
+pub unsafe fn hand_over_fp(level: PrivilegeLevel) {
+    memory::set_simd_copy(false);
+
+    let enabled = ptr::read_volatile(&PAYLOAD_FP);
+    if level == PrivilegeLevel::Kernel {
+        // EL2 must not trap what EL1 may use. The loader does not use FP/SIMD in its handlers.
+        set_el2_fp_trap(false);
+        set_el1_fp_trap(!enabled);
+    } else {
+        set_el2_fp_trap(!enabled);
+    }
+}
+
 /// Spin for `n` cycles
 #[inline(always)]
 pub fn spin_for_cycles(n: usize) {
@@ -47,7 +165,26 @@ pub fn spin_for_cycles(n: usize) {
     }
 }
 
+/// The time since the system counter started, which is about when the board was powered on.
+///
+/// Does not touch any statics, so that it can run before the loader is relocated.
+#[inline(always)]
+pub fn uptime() -> Duration {
+    const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+    // Left to the firmware, which may not have set it.
+    let frequency = CNTFRQ_EL0.get() as u64;
+    if frequency == 0 {
+        return Duration::from_secs(0);
+    }
+
+    let ticks = CNTPCT_EL0.get();
+    let nanos = (ticks % frequency) * NANOS_PER_SECOND / frequency;
+
+    Duration::new(ticks / frequency, nanos as u32)
+}
 
+/// Pause execution on the core.
 #[inline(always)]
 pub fn wait_forever() -> ! {
     loop {
@@ -55,6 +192,81 @@ pub fn wait_forever() -> ! {
     }
 }
 
+/// Jump to the payload at `entry`, entering it at `level` with the device tree address `dtb_addr` in
+/// x0 and `boot_info_addr` in x1.
+///
+/// `PrivilegeLevel::Hypervisor` keeps executing at the current EL. `PrivilegeLevel::Kernel` drops
+/// from EL2 to EL1h, with the EL1 MMU and caches off, all exceptions masked and the stack pointer
+/// where the boot core's stack was. The counters and timers stay accessible from EL1. EL2 keeps
+/// running on the executing core's stack, to serve the payload's PSCI calls.
+///
+/// FP/SIMD instructions trap at `level`, unless `set_payload_fp()` let the payload use them.
+///
+/// # Safety
+///
+/// - `entry` must point to code that was loaded to be executed at `level`.
+pub unsafe fn jump_to_payload(
+    entry: usize,
+    level: PrivilegeLevel,
+    dtb_addr: usize,
+    boot_info_addr: usize,
+) -> ! {
+    let (current, _) = exception::current_privilege_level();
+
+    hand_over_fp(level);
+
+    if level == PrivilegeLevel::Kernel && current == PrivilegeLevel::Hypervisor {
+        // Enable timer counter registers for EL1.
+        CNTHCTL_EL2.write(CNTHCTL_EL2::EL1PCEN::SET + CNTHCTL_EL2::EL1PCTEN::SET);
+
+        // No offset for reading the counters.
+        CNTVOFF_EL2.set(0);
+
+        // Set EL1 execution state to AArch64.
+        HCR_EL2.write(HCR_EL2::RW::EL1IsAarch64);
+
+        // Put the payload under the stage 2 translation, if the loader guards itself.
+        hypervisor::init_core();
+
+        // Whatever the firmware left in SCTLR_EL1, the payload starts with the MMU and caches off.
+        SCTLR_EL1.modify(
+            SCTLR_EL1::M::Disable + SCTLR_EL1::C::NonCacheable + SCTLR_EL1::I::NonCacheable,
+        );
+
+        // Set up a simulated exception return.
+        //
+        // First, fake a saved program status where all interrupts were masked and SP_EL1 was used
+        // as a stack pointer.
+        SPSR_EL2.write(
+            SPSR_EL2::D::Masked
+                + SPSR_EL2::A::Masked
+                + SPSR_EL2::I::Masked
+                + SPSR_EL2::F::Masked
+                + SPSR_EL2::M::EL1h,
+        );
+
+        // Second, let the link register point to the payload.
+        ELR_EL2.set(entry as u64);
+
+        // Set up SP_EL1 (stack pointer), which will be used by EL1 once we "return" to it.
+        SP_EL1.set(bsp::cpu::BOOT_CORE_STACK_START);
+
+        // Exceptions taken from EL1 must not push their context into the payload, so EL2 continues
+        // on the core's stack above the loader. Then use `eret` to "return" to EL1.
+        let el2_stack = cpu::smp::core_stack_start(cpu::smp::core_id());
+        llvm_asm!("mov sp, $0
+                   eret"
+            :: "r"(el2_stack), "{x0}"(dtb_addr), "{x1}"(boot_info_addr)
+            :: "volatile");
+        unreachable!()
+    }
+
+    // Use black magic to get a function pointer.
+    let payload: extern "C" fn(usize, usize) -> ! = core::mem::transmute(entry as *const ());
+
+    payload(dtb_addr, boot_info_addr)
+}
+
 // // SPDX-License-Identifier: MIT OR Apache-2.0
 // //
 // // Copyright (c) 2018-2020 Andre Richter <andre.o.richter@gmail.com>//
@@ -0,0 +1,132 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Architectural cache maintenance code.
+//!
+//! Maintenance by VA works on the current translation regime and is broadcast to the other cores in
+//! the inner shareable domain. Maintenance by set/way only affects the executing core's caches, and
+//! is meant for turning caches on and off.
+
+use core::ops::Range;
+use cortex_a::barrier;
+
+// Assembly counterpart to this file.
+global_asm!(include_str!("cache.S"));
+
+// -------------------------------------------------------------------------------------------------
+// Private Code
+// -------------------------------------------------------------------------------------------------
+
+/// The smallest data cache line size of all caches the core controls, in bytes.
+#[inline(always)]
+fn dcache_line_size() -> usize {
+    let ctr: u64;
+    unsafe { llvm_asm!("mrs $0, CTR_EL0" : "=r"(ctr) ::: "volatile") };
+
+    // CTR_EL0.DminLine is the log2 of the number of words.
+    4 << ((ctr >> 16) & 0xF)
+}
+
+/// Apply the `dc` operation `$op` to every data cache line that covers `$range`. Needs an unsafe
+/// context.
+macro_rules! dcache_range_op {
+    ($op:tt, $range:expr) => {{
+        let range: Range<usize> = $range;
+        let line_size = dcache_line_size();
+        let mut addr = range.start & !(line_size - 1);
+
+        while addr < range.end {
+            llvm_asm!(concat!("dc ", $op, ", $0") :: "r"(addr) :: "volatile");
+            addr += line_size;
+        }
+
+        dsb();
+    }};
+}
+
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
+/// Clean the data cache lines covering `range` to the Point of Coherence.
+pub fn clean_dcache_range(range: Range<usize>) {
+    unsafe { dcache_range_op!("cvac", range) }
+}
+
+/// Clean and invalidate the data cache lines covering `range`.
+pub fn clean_invalidate_dcache_range(range: Range<usize>) {
+    unsafe { dcache_range_op!("civac", range) }
+}
+
+/// Invalidate the data cache lines covering `range`.
+///
+/// Lines that only partly overlap `range` are invalidated as a whole.
+///
+/// # Safety
+///
+/// - Dirty data in `range` is lost.
+pub unsafe fn invalidate_dcache_range(range: Range<usize>) {
+    dcache_range_op!("ivac", range)
+}
+
+/// Clean all data caches by set/way.
+///
+/// # Safety
+///
+/// - Only valid for the core's own caches. Other cores may dirty lines again at any time.
+pub unsafe fn clean_dcache_all() {
+    // Provided by cache.S.
+    extern "C" {
+        fn __dcache_clean_all();
+    }
+
+    __dcache_clean_all();
+}
+
+/// Clean and invalidate all data caches by set/way.
+///
+/// # Safety
+///
+/// - Only valid for the core's own caches. Other cores may dirty lines again at any time.
+pub unsafe fn clean_invalidate_dcache_all() {
+    // Provided by cache.S.
+    extern "C" {
+        fn __dcache_clean_invalidate_all();
+    }
+
+    __dcache_clean_invalidate_all();
+}
+
+/// Invalidate all data caches by set/way.
+///
+/// # Safety
+///
+/// - All dirty data is lost, including the caller's stack.
+pub unsafe fn invalidate_dcache_all() {
+    // Provided by cache.S.
+    extern "C" {
+        fn __dcache_invalidate_all();
+    }
+
+    __dcache_invalidate_all();
+}
+
+/// Invalidate the whole instruction cache to the Point of Unification.
+#[inline(always)]
+pub fn invalidate_icache_all() {
+    unsafe { llvm_asm!("ic iallu" :::: "volatile") };
+    dsb();
+}
+
+/// Data synchronization barrier.
+#[inline(always)]
+pub fn dsb() {
+    unsafe { barrier::dsb(barrier::SY) };
+}
+
+/// Instruction synchronization barrier.
+#[inline(always)]
+pub fn isb() {
+    unsafe { barrier::isb(barrier::SY) };
+}
@@ -2,13 +2,230 @@
 //
 // Copyright (c) 2018-2020 Andre Richter <andre.o.richter@gmail.com>
 
-//! Architectural processor code.
+//! Architectural symmetric multiprocessing.
+//!
+//! The secondary cores are parked on the BSP's spin-table, following the `spin-table` enable method
+//! that Linux understands. Getting there takes two steps:
+//!
+//! 1. From `_start()`, the secondaries wait for their release address to become non-zero. They are
+//!    still running from where the firmware loaded the loader, which is the payload window, and on
+//!    their stacks there.
+//! 2. Once relocated, the boot core writes the address of the relocated `secondary_park()` to the
+//!    release addresses. The secondaries switch to their stacks in the relocated loader, clear
+//!    their release address as an acknowledgement, and wait for the payload to release them.
+//!
+//! If the firmware's ARM stub parked the secondaries on the same spin-table instead, they skip the
+//! first step.
+//!
+//! A payload that releases a parked core through the spin-table gets it at the same level as the
+//! boot core, see `cpu::set_payload_level()`. Parked cores are also what the PSCI `CPU_ON` call
+//! starts, by releasing them into `cpu_on_entry()`. `CPU_OFF` parks the calling core again.
 
-use cortex_a::regs::*;
+use crate::{bsp, cpu, exception, exception::PrivilegeLevel, psci};
+use core::ptr;
+use cortex_a::{asm, barrier, regs::*};
 
-// =============================================================================
-// PUBLIC CODE
-// =============================================================================
+// -------------------------------------------------------------------------------------------------
+// Private Definitions
+// -------------------------------------------------------------------------------------------------
+
+/// The PSCI view of a core. Every core only ever changes its own state, except for `CPU_ON`, which
+/// moves an `Off` core to `OnPending`.
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum CoreState {
+    /// The core never checked in.
+    Absent,
+    Off,
+    OnPending,
+    On,
+}
+
+/// What a core released by `CPU_ON` is to do.
+#[derive(Clone, Copy)]
+struct CpuOnArgs {
+    entry: u64,
+    context_id: u64,
+}
+
+/// PSCI core power control on top of the parking.
+struct SpinTableCpuPower;
+
+// -------------------------------------------------------------------------------------------------
+// Global instances
+// -------------------------------------------------------------------------------------------------
+
+// Only ever accessed with volatile accesses. Once a payload runs, all cores have their caches off
+// at EL2, so they see the same values.
+static mut CORE_STATES: [CoreState; bsp::cpu::NUM_CORES] = [CoreState::Absent; bsp::cpu::NUM_CORES];
+static mut CPU_ON_ARGS: [CpuOnArgs; bsp::cpu::NUM_CORES] = [CpuOnArgs {
+    entry: 0,
+    context_id: 0,
+}; bsp::cpu::NUM_CORES];
+
+static CPU_POWER: SpinTableCpuPower = SpinTableCpuPower;
+
+// -------------------------------------------------------------------------------------------------
+// Boot code
+// -------------------------------------------------------------------------------------------------
+
+// Assembly counterpart to this file: `__secondary_start()` and `secondary_park()`, which set up the
+// core's stack and continue in the functions below.
+global_asm!(include_str!("smp.S"));
+
+extern "C" {
+    /// Where the cores wait for the payload. Abandons whatever the core was running before.
+    fn secondary_park() -> !;
+}
+
+/// The first thing the secondary cores do from `_start()`: Wait for the boot core to hand them over
+/// to the relocated loader.
+///
+/// # Safety
+///
+/// - Only to be called by `__secondary_start()`, on the core's stack in the binary the firmware
+///   loaded.
+#[no_mangle]
+unsafe extern "C" fn secondary_start_rust(core_id: usize) -> ! {
+    // Not relocated yet, so the GOT still holds the link-time addresses. The release addresses are
+    // absolute anyway.
+    wait_for_release(release_addr(core_id))
+}
+
+/// Where the cores wait for the payload, once `secondary_park()` set up their stack in the
+/// relocated loader.
+///
+/// The caches are off on the waiting cores, so their view of the release address is DRAM.
+///
+/// # Safety
+///
+/// - Only to be called by `secondary_park()`.
+#[no_mangle]
+unsafe extern "C" fn secondary_park_rust(core_id: usize) -> ! {
+    // Let the boot core know that this core made it.
+    ptr::write_volatile(release_addr(core_id), 0);
+    barrier_dsb();
+
+    let entry = wait_for_entry(release_addr(core_id));
+    if core_state(core_id) == CoreState::OnPending {
+        // Released to `cpu_on_entry()`.
+        enter(entry)
+    }
+
+    // Released by the payload through the spin-table. The core enters the payload at the same
+    // level as the boot core, and under the stage 2 translation as well if the loader is guarded.
+    exception::handling_init();
+    set_core_state(core_id, CoreState::On);
+
+    cpu::jump_to_payload(entry as usize, cpu::payload_level(), 0, 0)
+}
+
+// -------------------------------------------------------------------------------------------------
+// Private Code
+// -------------------------------------------------------------------------------------------------
+
+/// The spin-table release address of the core `core_id`.
+#[inline(always)]
+fn release_addr(core_id: usize) -> *mut u64 {
+    // Every core up to `NUM_CORES` has a release address.
+    bsp::cpu::spin_table_release_addr(core_id as u64).unwrap() as *mut u64
+}
+
+/// The release address as a range, for cache maintenance.
+fn release_addr_range(core_id: usize) -> core::ops::Range<usize> {
+    let addr = release_addr(core_id) as usize;
+
+    addr..addr + 8
+}
+
+fn core_state(core_id: usize) -> CoreState {
+    unsafe { ptr::read_volatile(&CORE_STATES[core_id]) }
+}
+
+fn set_core_state(core_id: usize, state: CoreState) {
+    unsafe { ptr::write_volatile(&mut CORE_STATES[core_id], state) };
+    barrier_dsb();
+}
+
+#[inline(always)]
+fn barrier_dsb() {
+    unsafe { barrier::dsb(barrier::SY) };
+}
+
+/// The core id of the MPIDR value `mpidr`, if it names one of the cores.
+fn core_id_of(mpidr: u64) -> Option<usize> {
+    const AFF_MASK: u64 = 0xff_00ff_ffff;
+
+    let core_id = (mpidr & AFF_MASK) as usize;
+    if core_id < bsp::cpu::NUM_CORES {
+        Some(core_id)
+    } else {
+        None
+    }
+}
+
+/// Hand the parked core `core_id` over to `addr`.
+fn release(core_id: usize, addr: u64) {
+    unsafe { ptr::write_volatile(release_addr(core_id), addr) };
+
+    // Parked cores read DRAM directly, so the write must not stay in the cache.
+    cpu::cache::clean_dcache_range(release_addr_range(core_id));
+    asm::sev();
+}
+
+/// Wait until `release_addr` reads non-zero, and return the address that was written there.
+#[inline(always)]
+unsafe fn wait_for_entry(release_addr: *mut u64) -> u64 {
+    loop {
+        let entry = ptr::read_volatile(release_addr);
+        if entry != 0 {
+            break entry;
+        }
+
+        asm::wfe();
+    }
+}
+
+/// Jump to `entry`, with x0 to x3 cleared.
+#[inline(always)]
+unsafe fn enter(entry: u64) -> ! {
+    llvm_asm!("br $0"
+        :: "r"(entry), "{x0}"(0u64), "{x1}"(0u64), "{x2}"(0u64), "{x3}"(0u64)
+        :: "volatile");
+
+    unreachable!()
+}
+
+/// Wait until `release_addr` reads non-zero and jump to the address that was written there, with x0
+/// to x3 cleared.
+///
+/// # Safety
+///
+/// - Must not be inlined into code that the jump target may overwrite.
+#[inline(always)]
+unsafe fn wait_for_release(release_addr: *mut u64) -> ! {
+    enter(wait_for_entry(release_addr))
+}
+
+/// Where a core that was started with PSCI `CPU_ON` is released to.
+unsafe extern "C" fn cpu_on_entry() -> ! {
+    let core_id: usize = core_id();
+    let args = ptr::read_volatile(&CPU_ON_ARGS[core_id]);
+
+    // HVCs from this core must find the loader's exception vectors.
+    exception::handling_init();
+    set_core_state(core_id, CoreState::On);
+
+    cpu::jump_to_payload(
+        args.entry as usize,
+        PrivilegeLevel::Kernel,
+        args.context_id as usize,
+        0,
+    )
+}
+
+// -------------------------------------------------------------------------------------------------
+// Public Code
+// -------------------------------------------------------------------------------------------------
 
 /// Return the executing core's id
 #[inline(always)]
@@ -19,3 +236,100 @@ where
     const CORE_MASK: u64 = 0b11;
     T::from((MPIDR_EL1.get() & CORE_MASK) as u8)
 }
+
+/// The initial stack pointer of the core `core_id` once it left the boot code.
+#[inline(always)]
+pub fn core_stack_start(core_id: usize) -> u64 {
+    extern "C" {
+        static __core_stacks_start: usize;
+        static __core_stacks_end: usize;
+    }
+
+    let (start, end): (usize, usize) = unsafe {
+        (
+            &__core_stacks_start as *const _ as _,
+            &__core_stacks_end as *const _ as _,
+        )
+    };
+    let stack_size = (end - start) / bsp::cpu::NUM_CORES;
+
+    (start + (core_id + 1) * stack_size) as u64
+}
+
+/// Move all secondary cores to `secondary_park()`, where they wait on the spin-table until the
+/// payload releases them.
+///
+/// Returns the number of cores that checked in. Cores that do not within a short time are
+/// assumed to be absent.
+pub fn park_secondary_cores() -> usize {
+    // How often to look at a core's release address before giving up on it.
+    const ACK_POLLS: usize = 1_000_000;
+
+    let park_addr = secondary_park as *const () as u64;
+    let secondaries = || (0..bsp::cpu::NUM_CORES).filter(|i| *i != bsp::cpu::BOOT_CORE_ID);
+
+    set_core_state(bsp::cpu::BOOT_CORE_ID, CoreState::On);
+    for core_id in secondaries() {
+        release(core_id, park_addr);
+    }
+
+    let mut parked = 0;
+    for core_id in secondaries() {
+        for _ in 0..ACK_POLLS {
+            cpu::cache::clean_invalidate_dcache_range(release_addr_range(core_id));
+
+            if unsafe { ptr::read_volatile(release_addr(core_id)) } == 0 {
+                set_core_state(core_id, CoreState::Off);
+                parked += 1;
+                break;
+            }
+        }
+    }
+
+    parked
+}
+
+/// Return a reference to the PSCI core power control.
+pub fn cpu_power() -> &'static impl psci::interface::CpuPower {
+    &CPU_POWER
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl psci::interface::CpuPower for SpinTableCpuPower {
+    fn cpu_on(&self, target: u64, entry: u64, context_id: u64) -> Result<(), psci::Error> {
+        let core_id = core_id_of(target).ok_or(psci::Error::InvalidParameters)?;
+
+        match core_state(core_id) {
+            CoreState::Absent => return Err(psci::Error::InvalidParameters),
+            CoreState::OnPending => return Err(psci::Error::OnPending),
+            CoreState::On => return Err(psci::Error::AlreadyOn),
+            CoreState::Off => (),
+        }
+
+        unsafe { ptr::write_volatile(&mut CPU_ON_ARGS[core_id], CpuOnArgs { entry, context_id }) };
+        set_core_state(core_id, CoreState::OnPending);
+        release(core_id, cpu_on_entry as *const () as u64);
+
+        Ok(())
+    }
+
+    fn cpu_off(&self) -> psci::Error {
+        set_core_state(core_id(), CoreState::Off);
+
+        unsafe { secondary_park() }
+    }
+
+    fn affinity_info(&self, target: u64) -> Result<psci::AffinityState, psci::Error> {
+        let core_id = core_id_of(target).ok_or(psci::Error::InvalidParameters)?;
+
+        match core_state(core_id) {
+            CoreState::Absent => Err(psci::Error::InvalidParameters),
+            CoreState::Off => Ok(psci::AffinityState::Off),
+            CoreState::OnPending => Ok(psci::AffinityState::OnPending),
+            CoreState::On => Ok(psci::AffinityState::On),
+        }
+    }
+}
@@ -0,0 +1,473 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Architectural synchronous and asynchronous exception handling.
+//!
+//! The loader runs at EL2, so the vector table is installed in `VBAR_EL2`. The exceptions it
+//! handles all come from a payload at EL1:
+//!
+//! - `hvc #0` is taken as a PSCI call.
+//! - `hvc #RECALL_HVC_IMMEDIATE`, stage 2 faults and UART break FIQs hand control back to the
+//!   loader, if it guards itself (see `hypervisor`).
+//!
+//! Every other exception ends in a panic with a report of the CPU state. The loader's own exceptions
+//! are handled on a stack of their own, so that a stack overflow into the guard page is reported as
+//! well.
+
+use crate::{
+    bsp, cpu,
+    exception::PrivilegeLevel,
+    hypervisor::{self, AccessKind, ExitReason},
+    psci,
+};
+use core::{cell::UnsafeCell, fmt};
+use cortex_a::{barrier, regs::*};
+use register::LocalRegisterCopy;
+
+// Assembly counterpart to this file.
+global_asm!(include_str!("exception.S"));
+
+// -------------------------------------------------------------------------------------------------
+// Private definitions
+// -------------------------------------------------------------------------------------------------
+
+/// The exception context as it is stored on the stack on exception entry.
+#[repr(C)]
+struct ExceptionContext {
+    /// General Purpose Registers x0 to x29.
+    gpr: [u64; 30],
+
+    /// The link register, aka x30.
+    lr: u64,
+
+    /// Exception link register. The program counter at the time the exception happened.
+    elr_el2: u64,
+
+    /// Saved program status.
+    spsr_el2: u32,
+}
+
+/// Everything that is reported about an exception.
+struct Report<'a> {
+    origin: &'static str,
+    esr_el2: u32,
+    far_el2: u64,
+    context: &'a ExceptionContext,
+}
+
+/// Exception Syndrome Register - EL2. Missing from `cortex_a`.
+mod esr_el2 {
+    /// Exception Class, bits [31:26].
+    pub fn ec(esr: u32) -> u32 {
+        esr >> 26
+    }
+
+    /// Instruction Specific Syndrome, bits [24:0].
+    pub fn iss(esr: u32) -> u32 {
+        esr & 0x1FF_FFFF
+    }
+
+    /// The immediate of an HVC, bits [15:0].
+    pub fn hvc_imm(esr: u32) -> u16 {
+        esr as u16
+    }
+
+    /// For Data Aborts: Write not Read, bit 6.
+    pub fn wnr(esr: u32) -> bool {
+        esr & (1 << 6) != 0
+    }
+
+    /// Read the register.
+    pub fn get() -> u32 {
+        let value: u64;
+        unsafe { llvm_asm!("mrs $0, ESR_EL2" : "=r"(value) ::: "volatile") };
+
+        value as u32
+    }
+}
+
+/// Hypervisor IPA Fault Address Register - EL2. Missing from `cortex_a`.
+mod hpfar_el2 {
+    /// The page of the intermediate physical address of a stage 2 fault, bits [39:4] holding
+    /// address bits [51:12].
+    pub fn fault_page(hpfar: u64) -> u64 {
+        (hpfar & 0xFF_FFFF_FFF0) << 8
+    }
+
+    /// Read the register.
+    pub fn get() -> u64 {
+        let value: u64;
+        unsafe { llvm_asm!("mrs $0, HPFAR_EL2" : "=r"(value) ::: "volatile") };
+
+        value
+    }
+}
+
+/// Exception classes the loader handles.
+mod ec {
+    pub const HVC64: u32 = 0b01_0110;
+    pub const INSTRUCTION_ABORT_LOWER_EL: u32 = 0b10_0000;
+    pub const DATA_ABORT_LOWER_EL: u32 = 0b10_0100;
+}
+
+// -------------------------------------------------------------------------------------------------
+// Private code
+// -------------------------------------------------------------------------------------------------
+
+/// Human readable name of an exception class.
+fn exception_class_name(ec: u32) -> &'static str {
+    match ec {
+        0b00_0000 => "Unknown reason",
+        0b00_0001 => "Trapped WFI or WFE",
+        0b00_0111 => "Trapped SIMD/FP access",
+        0b00_1110 => "Illegal execution state",
+        0b01_0101 => "SVC in AArch64",
+        0b01_0110 => "HVC in AArch64",
+        0b01_0111 => "SMC in AArch64",
+        0b01_1000 => "Trapped MSR, MRS or system instruction",
+        0b10_0000 => "Instruction Abort, lower EL",
+        0b10_0001 => "Instruction Abort, current EL",
+        0b10_0010 => "PC alignment fault",
+        0b10_0100 => "Data Abort, lower EL",
+        0b10_0101 => "Data Abort, current EL",
+        0b10_0110 => "SP alignment fault",
+        0b10_1100 => "Trapped floating point exception",
+        0b10_1111 => "SError interrupt",
+        0b11_0000 | 0b11_0001 => "Breakpoint",
+        0b11_0010 | 0b11_0011 => "Software step",
+        0b11_0100 | 0b11_0101 => "Watchpoint",
+        0b11_1100 => "BRK instruction",
+        _ => "N/A",
+    }
+}
+
+/// Human readable name of an Instruction or Data Abort's fault status code.
+fn fault_status_name(fsc: u32) -> &'static str {
+    match fsc {
+        0b00_0000..=0b00_0011 => "Address size fault",
+        0b00_0100..=0b00_0111 => "Translation fault",
+        0b00_1001..=0b00_1011 => "Access flag fault",
+        0b00_1101..=0b00_1111 => "Permission fault",
+        0b01_0000 => "Synchronous External abort",
+        0b10_0001 => "Alignment fault",
+        0b11_0000 => "TLB conflict abort",
+        _ => "N/A",
+    }
+}
+
+fn print_esr(f: &mut fmt::Formatter, esr: u32) -> fmt::Result {
+    let ec = esr_el2::ec(esr);
+    let iss = esr_el2::iss(esr);
+
+    writeln!(f, "ESR_EL2: {:#010x}", esr)?;
+    writeln!(
+        f,
+        "      Exception Class         (EC) : {:#x} - {}",
+        ec,
+        exception_class_name(ec)
+    )?;
+    writeln!(f, "      Instr Specific Syndrome (ISS): {:#x}", iss)?;
+
+    // Instruction and Data Aborts.
+    if let 0b10_0000 | 0b10_0001 | 0b10_0100 | 0b10_0101 = ec {
+        let fsc = iss & 0b11_1111;
+        write!(
+            f,
+            "      Fault Status Code            : {:#x} - {}",
+            fsc,
+            fault_status_name(fsc)
+        )?;
+
+        // The first four fault kinds encode the translation table level in the low bits.
+        if fsc < 0b01_0000 {
+            write!(f, ", level {}", fsc & 0b11)?;
+        }
+        writeln!(f)?;
+
+        if ec & 0b100 != 0 {
+            let wnr = if esr_el2::wnr(esr) { "write" } else { "read" };
+            writeln!(f, "      Access                       : {}", wnr)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn print_spsr(f: &mut fmt::Formatter, spsr: u32) -> fmt::Result {
+    let spsr: LocalRegisterCopy<u32, SPSR_EL2::Register> = LocalRegisterCopy::new(spsr);
+
+    let to_flag_str = |x| -> _ {
+        if x {
+            "Set"
+        } else {
+            "Not set"
+        }
+    };
+    let to_mask_str = |x| -> _ {
+        if x {
+            "Masked"
+        } else {
+            "Unmasked"
+        }
+    };
+
+    writeln!(f, "SPSR_EL2: {:#010x}", spsr.get())?;
+    writeln!(f, "      Flags:")?;
+    for &(name, field) in &[
+        ("Negative (N)", SPSR_EL2::N),
+        ("Zero     (Z)", SPSR_EL2::Z),
+        ("Carry    (C)", SPSR_EL2::C),
+        ("Overflow (V)", SPSR_EL2::V),
+    ] {
+        writeln!(
+            f,
+            "            {}: {}",
+            name,
+            to_flag_str(spsr.is_set(field))
+        )?;
+    }
+
+    writeln!(f, "      Exception handling state:")?;
+    for &(name, field) in &[
+        ("Debug  (D)", SPSR_EL2::D),
+        ("SError (A)", SPSR_EL2::A),
+        ("IRQ    (I)", SPSR_EL2::I),
+        ("FIQ    (F)", SPSR_EL2::F),
+    ] {
+        writeln!(
+            f,
+            "            {}: {}",
+            name,
+            to_mask_str(spsr.is_set(field))
+        )?;
+    }
+
+    writeln!(
+        f,
+        "      Illegal Execution State (IL): {}",
+        to_flag_str(spsr.is_set(SPSR_EL2::IL))
+    )?;
+
+    let mode = match spsr.read_as_enum(SPSR_EL2::M) {
+        Some(SPSR_EL2::M::Value::EL0t) => "EL0t",
+        Some(SPSR_EL2::M::Value::EL1t) => "EL1t",
+        Some(SPSR_EL2::M::Value::EL1h) => "EL1h",
+        Some(SPSR_EL2::M::Value::EL2t) => "EL2t",
+        Some(SPSR_EL2::M::Value::EL2h) => "EL2h",
+        None => "N/A",
+    };
+    writeln!(f, "      Mode (M): {}", mode)
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let context = self.context;
+
+        writeln!(f, "Origin: {}", self.origin)?;
+        print_esr(f, self.esr_el2)?;
+        writeln!(f, "FAR_EL2: {:#018x}", self.far_el2)?;
+        print_spsr(f, context.spsr_el2)?;
+        writeln!(f, "ELR_EL2: {:#018x}", context.elr_el2)?;
+        writeln!(f)?;
+        writeln!(f, "General purpose register:")?;
+
+        let alternating = |x| -> _ {
+            if x % 2 == 0 {
+                "   "
+            } else {
+                "\n"
+            }
+        };
+
+        // Print two registers per line.
+        for (i, reg) in context.gpr.iter().enumerate() {
+            write!(f, "      x{: <2}: {: >#018x}{}", i, reg, alternating(i))?;
+        }
+        write!(f, "      lr : {:#018x}", context.lr)
+    }
+}
+
+/// Report the exception and stop. The panic handler prints through `panic_console_out()`, so the
+/// report gets out even if the exception hit while the console was locked.
+fn default_exception_handler(origin: &'static str, e: &ExceptionContext) -> ! {
+    let report = Report {
+        origin,
+        esr_el2: esr_el2::get(),
+        far_el2: FAR_EL2.get(),
+        context: e,
+    };
+
+    panic!("\n\nCPU Exception!\n{}", report);
+}
+
+//--------------------------------------------------------------------------------------------------
+// Current, EL0
+//--------------------------------------------------------------------------------------------------
+
+#[no_mangle]
+unsafe extern "C" fn current_el0_synchronous(e: &mut ExceptionContext) {
+    default_exception_handler("Current EL with SP0, synchronous", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn current_el0_irq(e: &mut ExceptionContext) {
+    default_exception_handler("Current EL with SP0, IRQ", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn current_el0_serror(e: &mut ExceptionContext) {
+    default_exception_handler("Current EL with SP0, SError", e);
+}
+
+//--------------------------------------------------------------------------------------------------
+// Current, ELx
+//--------------------------------------------------------------------------------------------------
+
+#[no_mangle]
+unsafe extern "C" fn current_elx_synchronous(e: &mut ExceptionContext) {
+    default_exception_handler("Current EL with SPx, synchronous", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn current_elx_irq(e: &mut ExceptionContext) {
+    default_exception_handler("Current EL with SPx, IRQ", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn current_elx_serror(e: &mut ExceptionContext) {
+    default_exception_handler("Current EL with SPx, SError", e);
+}
+
+//--------------------------------------------------------------------------------------------------
+// Lower, AArch64
+//--------------------------------------------------------------------------------------------------
+
+#[no_mangle]
+unsafe extern "C" fn lower_aarch64_synchronous(e: &mut ExceptionContext) {
+    let esr = esr_el2::get();
+
+    // Another core handed control back to the loader, which this core follows.
+    if hypervisor::is_recalling() {
+        hypervisor::join_recall()
+    }
+
+    match esr_el2::ec(esr) {
+        // `ELR_EL2` already points behind the `hvc`.
+        ec::HVC64 if esr_el2::hvc_imm(esr) == 0 => {
+            e.gpr[0] = psci::dispatch(
+                e.gpr[0],
+                [e.gpr[1], e.gpr[2], e.gpr[3]],
+                cpu::smp::cpu_power(),
+                bsp::psci::system_power(),
+            )
+        }
+        ec::HVC64
+            if esr_el2::hvc_imm(esr) == hypervisor::RECALL_HVC_IMMEDIATE
+                && hypervisor::is_guarding() =>
+        {
+            hypervisor::recall(ExitReason::HypervisorCall)
+        }
+        ec::INSTRUCTION_ABORT_LOWER_EL | ec::DATA_ABORT_LOWER_EL if hypervisor::is_guarding() => {
+            let kind = if esr_el2::ec(esr) == ec::INSTRUCTION_ABORT_LOWER_EL {
+                AccessKind::InstructionFetch
+            } else if esr_el2::wnr(esr) {
+                AccessKind::Write
+            } else {
+                AccessKind::Read
+            };
+
+            hypervisor::recall(ExitReason::Stage2Fault {
+                core_id: cpu::smp::core_id(),
+                pc: e.elr_el2,
+                addr: hpfar_el2::fault_page(hpfar_el2::get()) | (FAR_EL2.get() & 0xFFF),
+                kind,
+            })
+        }
+        _ => default_exception_handler("Lower EL, AArch64, synchronous", e),
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn lower_aarch64_irq(e: &mut ExceptionContext) {
+    default_exception_handler("Lower EL, AArch64, IRQ", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn lower_aarch64_fiq(e: &mut ExceptionContext) {
+    if bsp::hypervisor::take_uart_break() && hypervisor::is_guarding() {
+        hypervisor::recall(ExitReason::UartBreak)
+    }
+
+    default_exception_handler("Lower EL, AArch64, FIQ", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn lower_aarch64_serror(e: &mut ExceptionContext) {
+    default_exception_handler("Lower EL, AArch64, SError", e);
+}
+
+//--------------------------------------------------------------------------------------------------
+// Lower, AArch32
+//--------------------------------------------------------------------------------------------------
+
+#[no_mangle]
+unsafe extern "C" fn lower_aarch32_synchronous(e: &mut ExceptionContext) {
+    default_exception_handler("Lower EL, AArch32, synchronous", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn lower_aarch32_irq(e: &mut ExceptionContext) {
+    default_exception_handler("Lower EL, AArch32, IRQ", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn lower_aarch32_serror(e: &mut ExceptionContext) {
+    default_exception_handler("Lower EL, AArch32, SError", e);
+}
+
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
+/// The processing element's current privilege level.
+pub fn current_privilege_level() -> (PrivilegeLevel, &'static str) {
+    let el = CurrentEL.read_as_enum(CurrentEL::EL);
+    match el {
+        Some(CurrentEL::EL::Value::EL2) => (PrivilegeLevel::Hypervisor, "EL2"),
+        Some(CurrentEL::EL::Value::EL1) => (PrivilegeLevel::Kernel, "EL1"),
+        Some(CurrentEL::EL::Value::EL0) => (PrivilegeLevel::User, "EL0"),
+        _ => (PrivilegeLevel::Unknown, "Unknown"),
+    }
+}
+
+/// Install the exception vector table, and the executing core's exception stack.
+///
+/// # Safety
+///
+/// - Changes the hardware's exception handling. Call once per core, early during init.
+pub unsafe fn handling_init() {
+    // Provided by exception.S.
+    extern "Rust" {
+        static __exception_vector_start: UnsafeCell<()>;
+    }
+
+    // Provided by the linker script.
+    extern "C" {
+        static __exception_stacks_start: usize;
+        static __exception_stacks_end: usize;
+    }
+
+    let vbar = __exception_vector_start.get() as u64;
+    llvm_asm!("msr VBAR_EL2, $0" :: "r"(vbar) :: "volatile");
+
+    // Where `SWITCH_TO_EXCEPTION_STACK` finds the end of the executing core's exception stack.
+    let start = &__exception_stacks_start as *const _ as usize;
+    let end = &__exception_stacks_end as *const _ as usize;
+    let stack_size = (end - start) / bsp::cpu::NUM_CORES;
+    let stack_end = start + (cpu::smp::core_id::<usize>() + 1) * stack_size;
+    llvm_asm!("msr TPIDR_EL2, $0" :: "r"(stack_end) :: "volatile");
+
+    // Force VBAR update to complete before next instruction.
+    barrier::isb(barrier::SY);
+}
@@ -0,0 +1,53 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Architectural asynchronous exception handling.
+
+use cortex_a::regs::*;
+
+// -------------------------------------------------------------------------------------------------
+// Private definitions
+// -------------------------------------------------------------------------------------------------
+
+/// Immediate values for `msr DAIFSet/DAIFClr`.
+mod daif_bits {
+    pub const IRQ: u8 = 0b0010;
+    pub const FIQ: u8 = 0b0001;
+}
+
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
+/// Mask IRQs and FIQs on the executing core.
+#[inline(always)]
+pub fn local_irq_mask() {
+    unsafe {
+        llvm_asm!("msr DAIFSet, $0"
+            :
+            : "n"(daif_bits::IRQ | daif_bits::FIQ)
+            : "memory"
+            : "volatile");
+    }
+}
+
+/// Mask IRQs and FIQs on the executing core and return the previous state of the `DAIF` register.
+#[inline(always)]
+pub fn local_irq_mask_save() -> u32 {
+    let saved = DAIF.get();
+    local_irq_mask();
+
+    saved
+}
+
+/// Restore the `DAIF` register to a state saved by `local_irq_mask_save()`.
+///
+/// Unmasks IRQs and FIQs only if they were unmasked when the state was saved.
+#[inline(always)]
+pub fn local_irq_restore(saved: u32) {
+    // The clobber keeps the compiler from moving memory accesses of the critical section past the
+    // point where interrupts are unmasked again.
+    unsafe { llvm_asm!("" ::: "memory" : "volatile") };
+    DAIF.set(saved);
+}
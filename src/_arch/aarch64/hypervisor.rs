@@ -0,0 +1,193 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Architectural thin hypervisor.
+//!
+//! Once the payload runs, the loader only executes in exception handlers at EL2, with the MMU and
+//! caches off. The state below is therefore only accessed with volatile accesses. The exception is
+//! `RECALLING`, which cores that recall at the same time race for, so it is taken with an atomic
+//! swap.
+
+use crate::{bsp, cpu, hypervisor::ExitReason, memory, memory::mmu::interface::Stage2};
+use core::{
+    ops::Range,
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use cortex_a::{barrier, regs::*};
+
+// -------------------------------------------------------------------------------------------------
+// Private Definitions
+// -------------------------------------------------------------------------------------------------
+
+/// HCR_EL2.FMO, which is missing from `cortex_a`: Route physical FIQs to EL2.
+const HCR_EL2_FMO: u64 = 1 << 3;
+
+// -------------------------------------------------------------------------------------------------
+// Global instances
+// -------------------------------------------------------------------------------------------------
+
+/// Payload cores run under the stage 2 translation.
+static mut GUARDING: bool = false;
+
+/// The loader took over again. Payload cores park.
+static RECALLING: AtomicBool = AtomicBool::new(false);
+
+/// UART breaks arrive as FIQs.
+static mut UART_BREAK_FIQ: bool = false;
+
+/// Why the loader took over, for the boot core to report. Written by the core that won the recall,
+/// after it set `RECALLING`.
+static mut RECALL_REASON: Option<ExitReason> = None;
+
+// -------------------------------------------------------------------------------------------------
+// Private Code
+// -------------------------------------------------------------------------------------------------
+
+fn get(flag: &'static bool) -> bool {
+    unsafe { ptr::read_volatile(flag) }
+}
+
+fn set(flag: &'static mut bool, value: bool) {
+    unsafe {
+        ptr::write_volatile(flag, value);
+        barrier::dsb(barrier::SY);
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Public Code
+// -------------------------------------------------------------------------------------------------
+
+/// Guard `loader` from the next payload. Takes effect for every core that enters the payload at
+/// EL1 afterwards.
+///
+/// # Safety
+///
+/// - No core may run a payload.
+pub unsafe fn guard(loader: &Range<usize>) -> Result<(), &'static str> {
+    memory::mmu::stage2().init(loader)?;
+    set(&mut GUARDING, true);
+
+    Ok(())
+}
+
+/// Let the cores of the next payload run, instead of parking them like the previous payload's. Called
+/// right before the next payload is entered, guarded or not.
+///
+/// # Safety
+///
+/// - No core may run a payload.
+pub unsafe fn end_recall() {
+    ptr::write_volatile(&mut RECALL_REASON, None);
+    RECALLING.store(false, Ordering::SeqCst);
+    barrier::dsb(barrier::SY);
+}
+
+/// Let a break on the console UART hand control back to the loader. Only the core `core_id` gets
+/// to see the break, which should be the one that enters the payload.
+///
+/// # Safety
+///
+/// - No core may run a payload.
+pub unsafe fn recall_on_uart_break(core_id: usize) -> Result<(), &'static str> {
+    bsp::hypervisor::enable_uart_break_fiq(core_id)?;
+    set(&mut UART_BREAK_FIQ, true);
+
+    Ok(())
+}
+
+/// Put the executing core under the stage 2 translation, if the loader is guarded. Called right
+/// before a payload is entered at EL1.
+///
+/// # Safety
+///
+/// - Changes the hardware's global state.
+pub unsafe fn init_core() {
+    if !get(&GUARDING) {
+        return;
+    }
+
+    memory::mmu::stage2().enable();
+
+    if get(&UART_BREAK_FIQ) {
+        HCR_EL2.set(HCR_EL2.get() | HCR_EL2_FMO);
+    }
+}
+
+/// Check if payload cores run under the stage 2 translation.
+pub fn is_guarding() -> bool {
+    get(unsafe { &GUARDING })
+}
+
+/// Check if the loader took over again, and the payload's cores are to park.
+pub fn is_recalling() -> bool {
+    RECALLING.load(Ordering::SeqCst)
+}
+
+/// Hand control back to the loader's request loop.
+///
+/// The stage 2 translation is revoked for all cores, so that each of them traps on its next memory
+/// access and calls `join_recall()`. The loader then resumes on the boot core, on the boot core's
+/// stack. Only the first recall counts, any further ones just join it.
+///
+/// # Safety
+///
+/// - Only to be called from an exception handler that was entered from the payload.
+pub unsafe fn recall(reason: ExitReason) -> ! {
+    // Exactly one core finds `RECALLING` unset, even if several recall at the same time.
+    if !RECALLING.swap(true, Ordering::SeqCst) {
+        ptr::write_volatile(&mut RECALL_REASON, Some(reason));
+        set(&mut GUARDING, false);
+
+        // Fence off the other cores first, before the payload's memory is overwritten.
+        memory::mmu::stage2().revoke();
+    }
+
+    join_recall()
+}
+
+/// Follow a recall on the executing core: The boot core hands control back to the loader's request
+/// loop, the other cores park.
+///
+/// # Safety
+///
+/// - Only to be called from an exception handler that was entered from the payload, once
+///   `is_recalling()`.
+pub unsafe fn join_recall() -> ! {
+    use crate::psci::interface::CpuPower;
+
+    memory::mmu::stage2().disable();
+
+    if cpu::smp::core_id::<usize>() != bsp::cpu::BOOT_CORE_ID {
+        // Parks the core for good, unless a payload starts it again.
+        cpu::smp::cpu_power().cpu_off();
+        cpu::wait_forever()
+    }
+
+    // The core that won the recall may not have written why yet.
+    while recall_reason().is_none() {
+        cpu::nop();
+    }
+
+    if get(&UART_BREAK_FIQ) {
+        HCR_EL2.set(HCR_EL2.get() & !HCR_EL2_FMO);
+        bsp::hypervisor::disable_uart_break_fiq();
+        set(&mut UART_BREAK_FIQ, false);
+    }
+
+    // Continue on the boot core's stack, like before the payload ran, instead of on the stack that
+    // the payload's exceptions are handled on. Nothing on the boot core's stack is of use anymore.
+    llvm_asm!("mov sp, $0
+               b   loader_resume"
+        :: "r"(bsp::memory::boot_stack().end) :: "volatile");
+
+    unreachable!()
+}
+
+/// Why the loader took over. Only meaningful once `is_recalling()`, and `None` until the core that
+/// recalled wrote it.
+pub fn recall_reason() -> Option<ExitReason> {
+    unsafe { ptr::read_volatile(&RECALL_REASON) }
+}
@@ -0,0 +1,60 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Architectural memory primitives.
+//!
+//! `memory.S` provides `memcpy()`, `memmove()` and `memset()`. They take precedence over the weak
+//! ones of `compiler_builtins`, so that the compiler's own copies use them as well.
+
+// Assembly counterpart to this file.
+global_asm!(include_str!("memory.S"));
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+extern "C" {
+    fn memmove(dest: *mut u8, src: *const u8, count: usize) -> *mut u8;
+    fn memset(dest: *mut u8, value: i32, count: usize) -> *mut u8;
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Allow or forbid the copy routines to use the SIMD registers.
+///
+/// Does not touch any statics through the GOT, so that it can run before the loader is relocated.
+///
+/// # Safety
+///
+/// - The SIMD registers must not hold anybody else's state while they are allowed.
+#[inline(always)]
+pub unsafe fn set_simd_copy(allowed: bool) {
+    llvm_asm!("adrp x9, __memcpy_simd
+               strb $0, [x9, #:lo12:__memcpy_simd]"
+        :: "r"(allowed as u32) : "x9", "memory" : "volatile");
+}
+
+/// Copy `count` bytes from `src` to `dest`. The two may overlap.
+///
+/// Does not touch any statics, so that it can run before the loader is relocated.
+///
+/// # Safety
+///
+/// - Both ranges must be valid memory.
+#[inline(always)]
+pub unsafe fn copy(dest: *mut u8, src: *const u8, count: usize) {
+    memmove(dest, src, count);
+}
+
+/// Set `count` bytes at `dest` to `value`. Zeroing uses DC ZVA while the MMU is on.
+///
+/// # Safety
+///
+/// - The range must be valid memory. Device memory must not be zeroed while the MMU is on.
+#[inline(always)]
+pub unsafe fn fill(dest: *mut u8, value: u8, count: usize) {
+    memset(dest, value as i32, count);
+}
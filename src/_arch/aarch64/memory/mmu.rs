@@ -0,0 +1,728 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2018-2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Memory Management Unit Driver.
+//!
+//! The loader runs at EL2, so it is the EL2 translation regime that is set up here. The address
+//! space is identity mapped with a 4 KiB granule, using 2 MiB blocks: one lvl1 table with up to four
+//! entries, each pointing to a lvl2 table that covers 1 GiB. Only the block with the BSP's guard
+//! page is split into 4 KiB pages by a lvl3 table, so that the guard page alone can stay unmapped.
+//!
+//! The tables live in `.bss`, which means the MMU can only be turned on once the loader is relocated
+//! and `runtime_init()` has run.
+//!
+//! The stage 2 tables for a payload at EL1 have the same shape. Their walk starts at lvl1 as well,
+//! covering a 4 GiB intermediate physical address space.
+
+use crate::{bsp, cpu, memory};
+use core::{convert, ops::Range};
+use cortex_a::{barrier, regs::*};
+use register::{cpu::RegisterReadWrite, register_bitfields, FieldValue};
+
+// Assembly counterpart to this file.
+global_asm!(include_str!("mmu.S"));
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+// The EL2 registers are missing from `cortex_a`. Where the EL1 counterpart has the same layout, its
+// bitfields are reused.
+register_bitfields! {u64,
+    /// Translation Control Register - EL2, for when HCR_EL2.E2H is 0.
+    TCR_EL2 [
+        /// Physical Address Size.
+        PS OFFSET(16) NUMBITS(3) [
+            Bits_32 = 0b000,
+            Bits_36 = 0b001,
+            Bits_40 = 0b010
+        ],
+
+        /// Granule size for TTBR0_EL2.
+        TG0 OFFSET(14) NUMBITS(2) [
+            KiB_4 = 0b00,
+            KiB_64 = 0b01,
+            KiB_16 = 0b10
+        ],
+
+        /// Shareability attribute for memory associated with translation table walks.
+        SH0 OFFSET(12) NUMBITS(2) [
+            None = 0b00,
+            Outer = 0b10,
+            Inner = 0b11
+        ],
+
+        /// Outer cacheability attribute for memory associated with translation table walks.
+        ORGN0 OFFSET(10) NUMBITS(2) [
+            NonCacheable = 0b00,
+            WriteBack_ReadAlloc_WriteAlloc_Cacheable = 0b01
+        ],
+
+        /// Inner cacheability attribute for memory associated with translation table walks.
+        IRGN0 OFFSET(8) NUMBITS(2) [
+            NonCacheable = 0b00,
+            WriteBack_ReadAlloc_WriteAlloc_Cacheable = 0b01
+        ],
+
+        /// The size offset of the memory region addressed by TTBR0_EL2. The region size is
+        /// 2^(64-T0SZ) bytes.
+        T0SZ OFFSET(0) NUMBITS(6) [],
+
+        /// Reserved, must be one.
+        RES1 OFFSET(23) NUMBITS(1) [],
+
+        /// Reserved, must be one.
+        RES1_HI OFFSET(31) NUMBITS(1) []
+    ],
+
+    /// System Control Register - EL2.
+    SCTLR_EL2 [
+        /// Instruction access Cacheability control.
+        I OFFSET(12) NUMBITS(1) [
+            NonCacheable = 0,
+            Cacheable = 1
+        ],
+
+        /// Cacheability control, for data accesses.
+        C OFFSET(2) NUMBITS(1) [
+            NonCacheable = 0,
+            Cacheable = 1
+        ],
+
+        /// MMU enable for EL2 stage 1 address translation.
+        M OFFSET(0) NUMBITS(1) [
+            Disable = 0,
+            Enable = 1
+        ]
+    ]
+}
+
+register_bitfields! {u64,
+    /// Virtualization Translation Control Register - EL2.
+    VTCR_EL2 [
+        /// Physical Address Size.
+        PS OFFSET(16) NUMBITS(3) [
+            Bits_32 = 0b000,
+            Bits_36 = 0b001,
+            Bits_40 = 0b010
+        ],
+
+        /// Granule size for VTTBR_EL2.
+        TG0 OFFSET(14) NUMBITS(2) [
+            KiB_4 = 0b00,
+            KiB_64 = 0b01,
+            KiB_16 = 0b10
+        ],
+
+        /// Shareability attribute for memory associated with translation table walks.
+        SH0 OFFSET(12) NUMBITS(2) [
+            None = 0b00,
+            Outer = 0b10,
+            Inner = 0b11
+        ],
+
+        /// Outer cacheability attribute for memory associated with translation table walks.
+        ORGN0 OFFSET(10) NUMBITS(2) [
+            NonCacheable = 0b00,
+            WriteBack_ReadAlloc_WriteAlloc_Cacheable = 0b01
+        ],
+
+        /// Inner cacheability attribute for memory associated with translation table walks.
+        IRGN0 OFFSET(8) NUMBITS(2) [
+            NonCacheable = 0b00,
+            WriteBack_ReadAlloc_WriteAlloc_Cacheable = 0b01
+        ],
+
+        /// Starting level of the stage 2 table walk. The meaning depends on the granule.
+        SL0 OFFSET(6) NUMBITS(2) [
+            Granule4KiBLevel1 = 0b01
+        ],
+
+        /// The size offset of the memory region addressed by VTTBR_EL2. The region size is
+        /// 2^(64-T0SZ) bytes.
+        T0SZ OFFSET(0) NUMBITS(6) [],
+
+        /// Reserved, must be one.
+        RES1 OFFSET(31) NUMBITS(1) []
+    ]
+}
+
+macro_rules! el2_register {
+    ($name:ident, $reg:ident, $register:ty, $asm_name:tt) => {
+        struct $reg;
+
+        impl RegisterReadWrite<u64, $register> for $reg {
+            #[inline]
+            fn get(&self) -> u64 {
+                let value;
+                unsafe { llvm_asm!(concat!("mrs $0, ", $asm_name) : "=r"(value) ::: "volatile") };
+                value
+            }
+
+            #[inline]
+            fn set(&self, value: u64) {
+                unsafe { llvm_asm!(concat!("msr ", $asm_name, ", $0") :: "r"(value) :: "volatile") }
+            }
+        }
+
+        static $name: $reg = $reg;
+    };
+}
+
+el2_register!(MAIR_EL2_REG, MairEl2, MAIR_EL1::Register, "MAIR_EL2");
+el2_register!(TTBR0_EL2_REG, Ttbr0El2, TTBR0_EL1::Register, "TTBR0_EL2");
+el2_register!(TCR_EL2_REG, TcrEl2, TCR_EL2::Register, "TCR_EL2");
+el2_register!(SCTLR_EL2_REG, SctlrEl2, SCTLR_EL2::Register, "SCTLR_EL2");
+el2_register!(VTCR_EL2_REG, VtcrEl2, VTCR_EL2::Register, "VTCR_EL2");
+el2_register!(VTTBR_EL2_REG, VttbrEl2, TTBR0_EL1::Register, "VTTBR_EL2");
+
+// A table descriptor, as per ARMv8-A Architecture Reference Manual Figure D5-15.
+register_bitfields! {u64,
+    STAGE1_TABLE_DESCRIPTOR [
+        /// Physical address of the next table descriptor (lvl2 or lvl3).
+        NEXT_LEVEL_TABLE_ADDR_4KiB OFFSET(12) NUMBITS(36) [], // [47:12]
+
+        TYPE  OFFSET(1) NUMBITS(1) [
+            Block = 0,
+            Table = 1
+        ],
+
+        VALID OFFSET(0) NUMBITS(1) [
+            False = 0,
+            True = 1
+        ]
+    ]
+}
+
+// A lvl2 block descriptor, as per ARMv8-A Architecture Reference Manual Figure D5-17. A lvl3 page
+// descriptor only differs in `TYPE`, which is `Table` for pages, and in the output address.
+register_bitfields! {u64,
+    STAGE1_BLOCK_DESCRIPTOR [
+        /// Execute-never. Called UXN in regimes with two privilege levels.
+        XN       OFFSET(54) NUMBITS(1) [
+            False = 0,
+            True = 1
+        ],
+
+        /// Physical address of the 2 MiB block.
+        OUTPUT_ADDR_2MiB OFFSET(21) NUMBITS(27) [], // [47:21]
+
+        /// Physical address of the 4 KiB page.
+        OUTPUT_ADDR_4KiB OFFSET(12) NUMBITS(36) [], // [47:12]
+
+        /// Access flag.
+        AF       OFFSET(10) NUMBITS(1) [
+            False = 0,
+            True = 1
+        ],
+
+        /// Shareability field.
+        SH       OFFSET(8) NUMBITS(2) [
+            OuterShareable = 0b10,
+            InnerShareable = 0b11
+        ],
+
+        /// Access Permissions. With a single privilege level, AP[1] is RES1.
+        AP       OFFSET(6) NUMBITS(2) [
+            RW_EL2 = 0b01,
+            RO_EL2 = 0b11
+        ],
+
+        /// Memory attributes index into the MAIR_EL2 register.
+        AttrIndx OFFSET(2) NUMBITS(3) [],
+
+        TYPE     OFFSET(1) NUMBITS(1) [
+            Block = 0,
+            Table = 1
+        ],
+
+        VALID    OFFSET(0) NUMBITS(1) [
+            False = 0,
+            True = 1
+        ]
+    ]
+}
+
+// A lvl2 stage 2 block descriptor, as per ARMv8-A Architecture Reference Manual Figure D5-17.
+register_bitfields! {u64,
+    STAGE2_BLOCK_DESCRIPTOR [
+        /// Execute-never.
+        XN       OFFSET(54) NUMBITS(1) [
+            False = 0,
+            True = 1
+        ],
+
+        /// Physical address of the 2 MiB block.
+        OUTPUT_ADDR_2MiB OFFSET(21) NUMBITS(27) [], // [47:21]
+
+        /// Access flag.
+        AF       OFFSET(10) NUMBITS(1) [
+            False = 0,
+            True = 1
+        ],
+
+        /// Shareability field.
+        SH       OFFSET(8) NUMBITS(2) [
+            OuterShareable = 0b10,
+            InnerShareable = 0b11
+        ],
+
+        /// Stage 2 Access Permissions.
+        S2AP     OFFSET(6) NUMBITS(2) [
+            RO = 0b01,
+            RW = 0b11
+        ],
+
+        /// Stage 2 memory attributes. Combined with the stage 1 attributes, the stricter wins.
+        MemAttr  OFFSET(2) NUMBITS(4) [
+            Device_nGnRE = 0b0001,
+            Normal_WriteBack = 0b1111
+        ],
+
+        TYPE     OFFSET(1) NUMBITS(1) [
+            Block = 0,
+            Table = 1
+        ],
+
+        VALID    OFFSET(0) NUMBITS(1) [
+            False = 0,
+            True = 1
+        ]
+    ]
+}
+
+/// HCR_EL2.VM, which is missing from `cortex_a`: Stage 2 translation for EL1 and EL0.
+const HCR_EL2_VM: u64 = 1 << 0;
+
+const ONE_GIB_SHIFT: usize = 30;
+const TWO_MIB_SHIFT: usize = 21;
+const FOUR_KIB_SHIFT: usize = 12;
+
+/// Enough lvl2 tables to map 4 GiB.
+const NUM_LVL2_TABLES: usize = 4;
+
+/// Big monolithic struct for storing the translation tables. Individual levels must be 4 KiB aligned,
+/// hence the "reverse" order of appearance.
+#[repr(C)]
+#[repr(align(4096))]
+struct FixedSizeTranslationTable {
+    /// Page descriptors, covering 2 MiB each.
+    lvl2: [[u64; 512]; NUM_LVL2_TABLES],
+
+    /// Table descriptors, covering 1 GiB each.
+    lvl1: [u64; 512],
+}
+
+/// A lvl3 table, with page descriptors covering 4 KiB each.
+#[repr(C)]
+#[repr(align(4096))]
+struct PageTable([u64; 512]);
+
+/// Constants for indexing the MAIR_EL2.
+mod mair {
+    pub const DEVICE: u64 = 0;
+    pub const NORMAL: u64 = 1;
+}
+
+/// Memory Management Unit type.
+struct MemoryManagementUnit;
+
+/// Stage 2 translation type.
+struct Stage2Translation;
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+/// The translation tables.
+///
+/// # Safety
+///
+/// - Supposed to land in `.bss`. Therefore, ensure that they boil down to all "0" entries.
+static mut TABLES: FixedSizeTranslationTable = FixedSizeTranslationTable {
+    lvl2: [[0; 512]; NUM_LVL2_TABLES],
+    lvl1: [0; 512],
+};
+
+/// The lvl3 table for the block that holds the guard page.
+///
+/// # Safety
+///
+/// - Supposed to land in `.bss`. Therefore, ensure that it boils down to all "0" entries.
+static mut GUARD_PAGE_TABLE: PageTable = PageTable([0; 512]);
+
+/// The stage 2 translation tables.
+///
+/// # Safety
+///
+/// - Supposed to land in `.bss`. Therefore, ensure that they boil down to all "0" entries.
+static mut STAGE2_TABLES: FixedSizeTranslationTable = FixedSizeTranslationTable {
+    lvl2: [[0; 512]; NUM_LVL2_TABLES],
+    lvl1: [0; 512],
+};
+
+static MMU: MemoryManagementUnit = MemoryManagementUnit;
+static STAGE2: Stage2Translation = Stage2Translation;
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl FixedSizeTranslationTable {
+    /// The memory the tables occupy.
+    fn span(&self) -> Range<usize> {
+        let start = self as *const _ as usize;
+
+        start..start + core::mem::size_of::<Self>()
+    }
+}
+
+/// Convert the kernel's generic memory attributes to HW-specific attributes of the MMU.
+impl convert::From<memory::mmu::AttributeFields>
+    for FieldValue<u64, STAGE1_BLOCK_DESCRIPTOR::Register>
+{
+    fn from(attribute_fields: memory::mmu::AttributeFields) -> Self {
+        // Memory attributes.
+        let mut desc = match attribute_fields.mem_attributes {
+            memory::mmu::MemAttributes::CacheableDRAM => {
+                STAGE1_BLOCK_DESCRIPTOR::SH::InnerShareable
+                    + STAGE1_BLOCK_DESCRIPTOR::AttrIndx.val(mair::NORMAL)
+            }
+            memory::mmu::MemAttributes::Device => {
+                STAGE1_BLOCK_DESCRIPTOR::SH::OuterShareable
+                    + STAGE1_BLOCK_DESCRIPTOR::AttrIndx.val(mair::DEVICE)
+            }
+        };
+
+        // Access Permissions.
+        desc += match attribute_fields.acc_perms {
+            memory::mmu::AccessPermissions::ReadOnly => STAGE1_BLOCK_DESCRIPTOR::AP::RO_EL2,
+            memory::mmu::AccessPermissions::ReadWrite => STAGE1_BLOCK_DESCRIPTOR::AP::RW_EL2,
+        };
+
+        // Execute Never.
+        desc += if attribute_fields.execute_never {
+            STAGE1_BLOCK_DESCRIPTOR::XN::True
+        } else {
+            STAGE1_BLOCK_DESCRIPTOR::XN::False
+        };
+
+        desc
+    }
+}
+
+/// Convert the kernel's generic memory attributes to HW-specific stage 2 attributes.
+impl convert::From<memory::mmu::AttributeFields>
+    for FieldValue<u64, STAGE2_BLOCK_DESCRIPTOR::Register>
+{
+    fn from(attribute_fields: memory::mmu::AttributeFields) -> Self {
+        // Memory attributes.
+        let mut desc = match attribute_fields.mem_attributes {
+            memory::mmu::MemAttributes::CacheableDRAM => {
+                STAGE2_BLOCK_DESCRIPTOR::SH::InnerShareable
+                    + STAGE2_BLOCK_DESCRIPTOR::MemAttr::Normal_WriteBack
+            }
+            memory::mmu::MemAttributes::Device => {
+                STAGE2_BLOCK_DESCRIPTOR::SH::OuterShareable
+                    + STAGE2_BLOCK_DESCRIPTOR::MemAttr::Device_nGnRE
+            }
+        };
+
+        // Access Permissions.
+        desc += match attribute_fields.acc_perms {
+            memory::mmu::AccessPermissions::ReadOnly => STAGE2_BLOCK_DESCRIPTOR::S2AP::RO,
+            memory::mmu::AccessPermissions::ReadWrite => STAGE2_BLOCK_DESCRIPTOR::S2AP::RW,
+        };
+
+        // Execute Never.
+        desc += if attribute_fields.execute_never {
+            STAGE2_BLOCK_DESCRIPTOR::XN::True
+        } else {
+            STAGE2_BLOCK_DESCRIPTOR::XN::False
+        };
+
+        desc
+    }
+}
+
+/// Create a table descriptor pointing to the lvl2 or lvl3 table at `next_lvl_table_addr`.
+fn table_descriptor(next_lvl_table_addr: usize) -> u64 {
+    let shifted = next_lvl_table_addr >> 12;
+
+    (STAGE1_TABLE_DESCRIPTOR::VALID::True
+        + STAGE1_TABLE_DESCRIPTOR::TYPE::Table
+        + STAGE1_TABLE_DESCRIPTOR::NEXT_LEVEL_TABLE_ADDR_4KiB.val(shifted as u64))
+    .value
+}
+
+/// Create a block descriptor that maps the 2 MiB at `output_addr`.
+fn block_descriptor(output_addr: usize, attribute_fields: memory::mmu::AttributeFields) -> u64 {
+    let shifted = output_addr >> TWO_MIB_SHIFT;
+
+    (STAGE1_BLOCK_DESCRIPTOR::VALID::True
+        + STAGE1_BLOCK_DESCRIPTOR::AF::True
+        + attribute_fields.into()
+        + STAGE1_BLOCK_DESCRIPTOR::TYPE::Block
+        + STAGE1_BLOCK_DESCRIPTOR::OUTPUT_ADDR_2MiB.val(shifted as u64))
+    .value
+}
+
+/// Create a lvl3 page descriptor that maps the 4 KiB at `output_addr`.
+fn page_descriptor(output_addr: usize, attribute_fields: memory::mmu::AttributeFields) -> u64 {
+    let shifted = output_addr >> FOUR_KIB_SHIFT;
+
+    (STAGE1_BLOCK_DESCRIPTOR::VALID::True
+        + STAGE1_BLOCK_DESCRIPTOR::AF::True
+        + attribute_fields.into()
+        + STAGE1_BLOCK_DESCRIPTOR::TYPE::Table
+        + STAGE1_BLOCK_DESCRIPTOR::OUTPUT_ADDR_4KiB.val(shifted as u64))
+    .value
+}
+
+/// Create a stage 2 block descriptor that maps the 2 MiB at `output_addr`.
+fn stage2_block_descriptor(
+    output_addr: usize,
+    attribute_fields: memory::mmu::AttributeFields,
+) -> u64 {
+    let shifted = output_addr >> TWO_MIB_SHIFT;
+
+    (STAGE2_BLOCK_DESCRIPTOR::VALID::True
+        + STAGE2_BLOCK_DESCRIPTOR::AF::True
+        + attribute_fields.into()
+        + STAGE2_BLOCK_DESCRIPTOR::TYPE::Block
+        + STAGE2_BLOCK_DESCRIPTOR::OUTPUT_ADDR_2MiB.val(shifted as u64))
+    .value
+}
+
+/// Setup function for the MAIR_EL2 register.
+fn set_up_mair() {
+    // Define the memory types being mapped.
+    MAIR_EL2_REG.write(
+        // Attribute 1 - Cacheable normal DRAM.
+        MAIR_EL1::Attr1_Normal_Outer::WriteBack_NonTransient_ReadWriteAlloc +
+        MAIR_EL1::Attr1_Normal_Inner::WriteBack_NonTransient_ReadWriteAlloc +
+
+        // Attribute 0 - Device.
+        MAIR_EL1::Attr0_Device::nonGathering_nonReordering_EarlyWriteAck,
+    );
+}
+
+/// Fill `tables` from the BSP's memory layout, with the block descriptors that `descriptor` creates
+/// from a block's address and attributes.
+///
+/// Blocks above the layout's last address are left invalid.
+fn populate_tables<F>(
+    tables: &mut FixedSizeTranslationTable,
+    descriptor: F,
+) -> Result<(), &'static str>
+where
+    F: Fn(usize, memory::mmu::AttributeFields) -> u64,
+{
+    let layout = bsp::memory::mmu::virt_mem_layout();
+
+    for (l2_nr, l2_table) in tables.lvl2.iter_mut().enumerate() {
+        for (block_nr, entry) in l2_table.iter_mut().enumerate() {
+            let virt_addr = (l2_nr << ONE_GIB_SHIFT) + (block_nr << TWO_MIB_SHIFT);
+
+            if virt_addr > layout.max_virt_addr_inclusive() {
+                *entry = 0;
+                continue;
+            }
+
+            let attribute_fields = layout.virt_addr_properties(virt_addr)?;
+            *entry = descriptor(virt_addr, attribute_fields);
+        }
+
+        tables.lvl1[l2_nr] = if (l2_nr << ONE_GIB_SHIFT) <= layout.max_virt_addr_inclusive() {
+            table_descriptor(l2_table.as_ptr() as usize)
+        } else {
+            0
+        };
+    }
+
+    Ok(())
+}
+
+/// Split the 2 MiB block that holds the page at `guard_addr` into pages, which are mapped like the
+/// block was, except for the guard page itself.
+///
+/// # Safety
+///
+/// - The MMU must be off, the block is not replaced following the break-before-make rules.
+unsafe fn unmap_guard_page(guard_addr: usize) -> Result<(), &'static str> {
+    let layout = bsp::memory::mmu::virt_mem_layout();
+    let block_addr = guard_addr & !((1 << TWO_MIB_SHIFT) - 1);
+    let attribute_fields = layout.virt_addr_properties(block_addr)?;
+
+    for (page_nr, entry) in GUARD_PAGE_TABLE.0.iter_mut().enumerate() {
+        let page_addr = block_addr + (page_nr << FOUR_KIB_SHIFT);
+
+        *entry = if page_addr >> FOUR_KIB_SHIFT == guard_addr >> FOUR_KIB_SHIFT {
+            0
+        } else {
+            page_descriptor(page_addr, attribute_fields)
+        };
+    }
+
+    let l2_nr = block_addr >> ONE_GIB_SHIFT;
+    let block_nr = (block_addr >> TWO_MIB_SHIFT) & 0x1ff;
+    TABLES.lvl2[l2_nr][block_nr] = table_descriptor(GUARD_PAGE_TABLE.0.as_ptr() as usize);
+
+    Ok(())
+}
+
+/// Configure various settings of stage 1 of the EL2 translation regime.
+fn configure_translation_control() {
+    // 4 GiB of address space, which starts the table walk at lvl1.
+    let t0sz = (64 - (NUM_LVL2_TABLES << ONE_GIB_SHIFT).trailing_zeros()) as u64;
+
+    TCR_EL2_REG.write(
+        TCR_EL2::RES1_HI.val(1)
+            + TCR_EL2::RES1.val(1)
+            + TCR_EL2::PS::Bits_36
+            + TCR_EL2::TG0::KiB_4
+            + TCR_EL2::SH0::Inner
+            + TCR_EL2::ORGN0::WriteBack_ReadAlloc_WriteAlloc_Cacheable
+            + TCR_EL2::IRGN0::WriteBack_ReadAlloc_WriteAlloc_Cacheable
+            + TCR_EL2::T0SZ.val(t0sz),
+    );
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Return a reference to the MMU.
+pub fn mmu() -> &'static impl memory::mmu::interface::MMU {
+    &MMU
+}
+
+/// Return a reference to the stage 2 translation.
+pub fn stage2() -> &'static impl memory::mmu::interface::Stage2 {
+    &STAGE2
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl memory::mmu::interface::MMU for MemoryManagementUnit {
+    unsafe fn init(&self) -> Result<(), &'static str> {
+        // Fail early if translation granule is not supported. Both RPis support it, though.
+        if !ID_AA64MMFR0_EL1.matches_all(ID_AA64MMFR0_EL1::TGran4::Supported) {
+            return Err("4 KiB translation granule not supported in HW");
+        }
+
+        // Prepare the memory attribute indirection register.
+        set_up_mair();
+
+        // Populate translation tables.
+        populate_tables(&mut TABLES, block_descriptor)?;
+        unmap_guard_page(bsp::memory::mmu::guard_page())?;
+
+        // Set the "Translation Table Base Register".
+        TTBR0_EL2_REG.write(TTBR0_EL1::BADDR.val(TABLES.lvl1.as_ptr() as u64 >> 1));
+
+        configure_translation_control();
+
+        // Whatever the firmware might have left in the caches and TLBs must not show up once they
+        // are turned on.
+        cpu::cache::clean_invalidate_dcache_all();
+        cpu::cache::invalidate_icache_all();
+        llvm_asm!("tlbi alle2" :::: "volatile");
+        cpu::cache::dsb();
+
+        // Switch the MMU on.
+        //
+        // First, force all previous changes to be seen before the MMU is enabled.
+        barrier::isb(barrier::SY);
+
+        // Enable the MMU and turn on data and instruction caching.
+        SCTLR_EL2_REG
+            .modify(SCTLR_EL2::M::Enable + SCTLR_EL2::C::Cacheable + SCTLR_EL2::I::Cacheable);
+
+        // Force MMU init to complete before next instruction.
+        barrier::isb(barrier::SY);
+
+        Ok(())
+    }
+
+    unsafe fn disable(&self) {
+        // Provided by mmu.S.
+        extern "C" {
+            fn __mmu_disable();
+        }
+
+        __mmu_disable();
+    }
+}
+
+impl memory::mmu::interface::Stage2 for Stage2Translation {
+    unsafe fn init(&self, hidden: &Range<usize>) -> Result<(), &'static str> {
+        let block_size = 1 << TWO_MIB_SHIFT;
+
+        populate_tables(&mut STAGE2_TABLES, |addr, attribute_fields| {
+            if addr < hidden.end && hidden.start < addr + block_size {
+                0
+            } else {
+                stage2_block_descriptor(addr, attribute_fields)
+            }
+        })?;
+
+        // The tables are walked from DRAM, whatever the state of the loader's caches is.
+        cpu::cache::clean_dcache_range(STAGE2_TABLES.span());
+
+        Ok(())
+    }
+
+    unsafe fn enable(&self) {
+        // Same 4 GiB of address space as stage 1, which starts the table walk at lvl1.
+        let t0sz = (64 - (NUM_LVL2_TABLES << ONE_GIB_SHIFT).trailing_zeros()) as u64;
+
+        VTCR_EL2_REG.write(
+            VTCR_EL2::RES1.val(1)
+                + VTCR_EL2::PS::Bits_36
+                + VTCR_EL2::TG0::KiB_4
+                + VTCR_EL2::SH0::Inner
+                + VTCR_EL2::ORGN0::WriteBack_ReadAlloc_WriteAlloc_Cacheable
+                + VTCR_EL2::IRGN0::WriteBack_ReadAlloc_WriteAlloc_Cacheable
+                + VTCR_EL2::SL0::Granule4KiBLevel1
+                + VTCR_EL2::T0SZ.val(t0sz),
+        );
+
+        // VMID 0, the only guest there is.
+        VTTBR_EL2_REG.write(TTBR0_EL1::BADDR.val(STAGE2_TABLES.lvl1.as_ptr() as u64 >> 1));
+
+        // Nothing that EL1 translated before may survive.
+        barrier::isb(barrier::SY);
+        llvm_asm!("tlbi vmalls12e1" :::: "volatile");
+        cpu::cache::dsb();
+
+        HCR_EL2.set(HCR_EL2.get() | HCR_EL2_VM);
+        barrier::isb(barrier::SY);
+    }
+
+    unsafe fn revoke(&self) {
+        for entry in STAGE2_TABLES.lvl1.iter_mut() {
+            core::ptr::write_volatile(entry, 0);
+        }
+        cpu::cache::clean_dcache_range(STAGE2_TABLES.span());
+
+        // Broadcast to all cores in the inner shareable domain.
+        llvm_asm!("dsb ish
+                   tlbi vmalls12e1is
+                   dsb ish"
+            :::: "volatile");
+        barrier::isb(barrier::SY);
+    }
+
+    unsafe fn disable(&self) {
+        HCR_EL2.set(HCR_EL2.get() & !HCR_EL2_VM);
+        barrier::isb(barrier::SY);
+
+        llvm_asm!("tlbi vmalls12e1" :::: "volatile");
+        cpu::cache::dsb();
+        barrier::isb(barrier::SY);
+    }
+}
@@ -0,0 +1,62 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Architectural semihosting code.
+//!
+//! On AArch64, a semihosting call is `hlt #0xF000` with the operation number in `x0` and a pointer
+//! to the parameter block in `x1`.
+
+use crate::cpu;
+
+// -------------------------------------------------------------------------------------------------
+// Private definitions
+// -------------------------------------------------------------------------------------------------
+
+/// Operation number of `SYS_EXIT`.
+const SYS_EXIT: u64 = 0x18;
+
+/// Reason code for a normal application exit. The exit code is passed as the subcode.
+const ADP_STOPPED_APPLICATION_EXIT: u64 = 0x2_0026;
+
+/// Parameter block of `SYS_EXIT` for 64 bit callers.
+#[repr(C)]
+struct ExitParameterBlock {
+    reason: u64,
+    subcode: u64,
+}
+
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
+/// Exit QEMU with the given exit code.
+///
+/// If QEMU was not started with `-semihosting`, or this runs on real hardware, the core is parked
+/// instead.
+pub fn exit(code: u32) -> ! {
+    let block = ExitParameterBlock {
+        reason: ADP_STOPPED_APPLICATION_EXIT,
+        subcode: u64::from(code),
+    };
+
+    unsafe {
+        llvm_asm!("hlt #0xF000"
+                  :
+                  : "{x0}"(SYS_EXIT), "{x1}"(&block as *const _ as u64)
+                  : "memory"
+                  : "volatile");
+    }
+
+    cpu::wait_forever()
+}
+
+/// Exit QEMU with exit code 0.
+pub fn exit_success() -> ! {
+    exit(0)
+}
+
+/// Exit QEMU with exit code 1.
+pub fn exit_failure() -> ! {
+    exit(1)
+}
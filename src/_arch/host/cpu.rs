@@ -0,0 +1,33 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Host stand-in for the architectural processor code.
+//!
+//! Only used when the library is compiled for the host, e.g. for driver unit tests. Provides what
+//! the drivers need from `cpu` without touching any real hardware.
+
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
+/// Do nothing for one cycle.
+#[inline(always)]
+pub fn nop() {
+    core::sync::atomic::spin_loop_hint();
+}
+
+/// Spin for `n` cycles
+#[inline(always)]
+pub fn spin_for_cycles(n: usize) {
+    for _ in 0..n {
+        nop();
+    }
+}
+
+/// Pause execution on the core.
+pub fn wait_forever() -> ! {
+    loop {
+        std::thread::park();
+    }
+}
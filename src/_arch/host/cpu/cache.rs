@@ -0,0 +1,63 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Host stand-in for the architectural cache maintenance code.
+//!
+//! The host keeps its caches coherent by itself, so there is nothing to do beyond ordering memory
+//! accesses.
+
+use core::{ops::Range, sync::atomic};
+
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
+/// Clean the data cache lines covering `range` to the Point of Coherence.
+pub fn clean_dcache_range(_range: Range<usize>) {}
+
+/// Clean and invalidate the data cache lines covering `range`.
+pub fn clean_invalidate_dcache_range(_range: Range<usize>) {}
+
+/// Invalidate the data cache lines covering `range`.
+///
+/// # Safety
+///
+/// - Dirty data in `range` is lost.
+pub unsafe fn invalidate_dcache_range(_range: Range<usize>) {}
+
+/// Clean all data caches by set/way.
+///
+/// # Safety
+///
+/// - Only valid for the core's own caches.
+pub unsafe fn clean_dcache_all() {}
+
+/// Clean and invalidate all data caches by set/way.
+///
+/// # Safety
+///
+/// - Only valid for the core's own caches.
+pub unsafe fn clean_invalidate_dcache_all() {}
+
+/// Invalidate all data caches by set/way.
+///
+/// # Safety
+///
+/// - All dirty data is lost.
+pub unsafe fn invalidate_dcache_all() {}
+
+/// Invalidate the whole instruction cache.
+pub fn invalidate_icache_all() {}
+
+/// Data synchronization barrier.
+#[inline(always)]
+pub fn dsb() {
+    atomic::fence(atomic::Ordering::SeqCst);
+}
+
+/// Instruction synchronization barrier.
+#[inline(always)]
+pub fn isb() {
+    atomic::compiler_fence(atomic::Ordering::SeqCst);
+}
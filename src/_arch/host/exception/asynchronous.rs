@@ -0,0 +1,25 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Host stand-in for the architectural asynchronous exception handling.
+//!
+//! The host has no interrupts to mask, so masking is a no-op.
+
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
+/// Mask IRQs and FIQs on the executing core.
+#[inline(always)]
+pub fn local_irq_mask() {}
+
+/// Mask IRQs and FIQs on the executing core and return the previous state.
+#[inline(always)]
+pub fn local_irq_mask_save() -> u32 {
+    0
+}
+
+/// Restore a state saved by `local_irq_mask_save()`.
+#[inline(always)]
+pub fn local_irq_restore(_saved: u32) {}
@@ -0,0 +1,29 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Host stand-in for the architectural memory primitives.
+
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
+/// Copy `count` bytes from `src` to `dest`. The two may overlap.
+///
+/// # Safety
+///
+/// - Both ranges must be valid memory.
+#[inline(always)]
+pub unsafe fn copy(dest: *mut u8, src: *const u8, count: usize) {
+    core::ptr::copy(src, dest, count);
+}
+
+/// Set `count` bytes at `dest` to `value`.
+///
+/// # Safety
+///
+/// - The range must be valid memory.
+#[inline(always)]
+pub unsafe fn fill(dest: *mut u8, value: u8, count: usize) {
+    core::ptr::write_bytes(dest, value, count);
+}
@@ -0,0 +1,228 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Boot info.
+//!
+//! What the loader found out, handed to the payload. Right before the payload is entered, the
+//! loader writes a `BootInfo` to `bsp::memory::boot_info_region()` and passes its address in x1,
+//! next to the device tree's in x0. Linux kernel `Image`s get zero in x1 instead, as their boot
+//! protocol demands.
+//!
+//! The layout only ever grows at the end. A payload checks `magic`, and that `version` is at least
+//! the one that introduced the fields it reads. Strings are NUL-terminated, and cut off if they do
+//! not fit.
+
+use crate::memory::map::{self, MemoryMap};
+use core::mem;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// What `BootInfo::magic` holds: "MLBOOTIN" in memory.
+pub const MAGIC: u64 = u64::from_le_bytes(*b"MLBOOTIN");
+
+/// The version of the layout.
+pub const VERSION: u32 = 1;
+
+/// A region of the memory map.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct BootInfoRegion {
+    /// The first address.
+    pub start: u64,
+
+    /// The first address after the region.
+    pub end: u64,
+
+    /// What the region is used for, a `memory::map::RegionKind` as `u32`.
+    pub kind: u32,
+
+    /// Zero.
+    pub reserved: u32,
+}
+
+/// The boot info itself.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct BootInfo {
+    /// `MAGIC`.
+    pub magic: u64,
+
+    /// `VERSION`.
+    pub version: u32,
+
+    /// The size of the structure in bytes.
+    pub size: u32,
+
+    /// The board's name, from `bsp::board_name()`.
+    pub board_name: [u8; 32],
+
+    /// The loader's version.
+    pub loader_version: [u8; 16],
+
+    /// The address of the device tree, or zero if none is forwarded.
+    pub dtb_addr: u64,
+
+    /// The console UART's base address.
+    pub uart_base: u64,
+
+    /// The console's baud rate.
+    pub uart_baud: u32,
+
+    /// The number of valid entries in `regions`.
+    pub num_regions: u32,
+
+    /// The RAM's first address.
+    pub ram_start: u64,
+
+    /// The first address after the RAM.
+    pub ram_end: u64,
+
+    /// The regions of the memory map, sorted by address. Whatever they leave of the RAM is free.
+    pub regions: [BootInfoRegion; map::MAX_REGIONS],
+
+    /// The first address the payload was loaded to.
+    pub payload_start: u64,
+
+    /// The first address after the loaded payload.
+    pub payload_end: u64,
+
+    /// The CRC-32 of the payload, as it was received.
+    pub payload_crc32: u32,
+
+    /// Zero.
+    pub reserved: u32,
+
+    /// The kernel command line, from the device tree's `/chosen/bootargs`.
+    pub cmdline: [u8; 1024],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Copy `s` into `dest`, cutting it off so that a terminating NUL always fits.
+fn copy_str(dest: &mut [u8], s: &[u8]) {
+    let len = s.len().min(dest.len() - 1);
+
+    dest[..len].copy_from_slice(&s[..len]);
+    for byte in dest[len..].iter_mut() {
+        *byte = 0;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl BootInfo {
+    /// Create an instance with only `magic`, `version` and `size` filled in.
+    pub const fn new() -> Self {
+        Self {
+            magic: MAGIC,
+            version: VERSION,
+            size: mem::size_of::<Self>() as u32,
+            board_name: [0; 32],
+            loader_version: [0; 16],
+            dtb_addr: 0,
+            uart_base: 0,
+            uart_baud: 0,
+            num_regions: 0,
+            ram_start: 0,
+            ram_end: 0,
+            regions: [BootInfoRegion {
+                start: 0,
+                end: 0,
+                kind: 0,
+                reserved: 0,
+            }; map::MAX_REGIONS],
+            payload_start: 0,
+            payload_end: 0,
+            payload_crc32: 0,
+            reserved: 0,
+            cmdline: [0; 1024],
+        }
+    }
+
+    /// Set `board_name`.
+    pub fn set_board_name(&mut self, name: &str) {
+        copy_str(&mut self.board_name, name.as_bytes());
+    }
+
+    /// Set `loader_version`.
+    pub fn set_loader_version(&mut self, version: &str) {
+        copy_str(&mut self.loader_version, version.as_bytes());
+    }
+
+    /// Set `cmdline`.
+    pub fn set_cmdline(&mut self, cmdline: &[u8]) {
+        copy_str(&mut self.cmdline, cmdline);
+    }
+
+    /// Set the RAM and the regions from `memory_map`.
+    pub fn set_memory_map(&mut self, memory_map: &MemoryMap) {
+        let ram = memory_map.ram();
+        self.ram_start = ram.start as u64;
+        self.ram_end = ram.end as u64;
+
+        let regions = memory_map.regions();
+        for (entry, region) in self.regions.iter_mut().zip(regions) {
+            *entry = BootInfoRegion {
+                start: region.start as u64,
+                end: region.end as u64,
+                kind: region.kind as u32,
+                reserved: 0,
+            };
+        }
+        self.num_regions = regions.len() as u32;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::map::RegionKind;
+
+    /// Strings that are too long lose their end, but keep the NUL.
+    #[test_case]
+    fn strings_are_cut_off() {
+        let mut info = BootInfo::new();
+
+        info.set_loader_version("0.1.0");
+        assert_eq!(&info.loader_version[..6], b"0.1.0\0");
+
+        info.set_loader_version("0123456789abcdefghij");
+        assert_eq!(&info.loader_version, b"0123456789abcde\0");
+    }
+
+    /// The memory map is copied over region by region.
+    #[test_case]
+    fn memory_map_is_copied() {
+        let mut memory_map = MemoryMap::new();
+        memory_map.reset(0..0x1000);
+        memory_map
+            .reserve("A", RegionKind::Payload, 0x100..0x200)
+            .unwrap();
+
+        let mut info = BootInfo::new();
+        info.set_memory_map(&memory_map);
+
+        assert_eq!(info.ram_end, 0x1000);
+        assert_eq!(info.num_regions, 1);
+        assert_eq!(
+            info.regions[0],
+            BootInfoRegion {
+                start: 0x100,
+                end: 0x200,
+                kind: 6,
+                reserved: 0,
+            }
+        );
+    }
+}
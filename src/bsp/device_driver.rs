@@ -1,5 +1,6 @@
 #[cfg(any(feature = "bsp_rpi3", feature = "bsp_rpi4"))]
 mod bcm;
+mod common;
 
 #[cfg(any(feature = "bsp_rpi3", feature = "bsp_rpi4"))]
-pub use bcm::*;
\ No newline at end of file
+pub use bcm::*;
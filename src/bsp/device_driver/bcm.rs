@@ -1,7 +1,11 @@
 //! BCM driver top level.
 
+mod bcm2837_fiq_control;
 mod bcm2xxx_gpio;
 mod bcm2xxx_pl011_uart;
+mod bcm2xxx_watchdog;
 
+pub use bcm2837_fiq_control::*;
 pub use bcm2xxx_gpio::*;
 pub use bcm2xxx_pl011_uart::*;
+pub use bcm2xxx_watchdog::*;
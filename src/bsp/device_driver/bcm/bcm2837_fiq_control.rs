@@ -0,0 +1,147 @@
+//! FIQ routing of the BCM2837.
+//!
+//! Any one of the peripheral interrupts can be selected as the FIQ source in the legacy interrupt
+//! controller, which takes it away from the IRQ line. The core-local interrupt controller then
+//! decides which core gets the FIQ.
+
+use crate::{
+    bsp::device_driver::common::{mmio::*, MMIODerefWrapper},
+    synchronization::NullLock,
+};
+use register::{register_bitfields, register_structs};
+
+// -------------------------------------------------------------------------------------------------
+// Private Definitions
+// -------------------------------------------------------------------------------------------------
+
+register_bitfields! {
+    u32,
+
+    /// FIQ Control
+    FIQ_CONTROL [
+        /// Route the FIQ source to the FIQ line.
+        ENABLE OFFSET(7) NUMBITS(1) [],
+
+        /// The FIQ source. Peripheral interrupts 0 to 63 keep their numbers.
+        SOURCE OFFSET(0) NUMBITS(7) []
+    ],
+
+    /// GPU Interrupts Routing
+    GPU_INT_ROUTING [
+        /// The core that gets the peripheral FIQ.
+        FIQ_CORE OFFSET(2) NUMBITS(2) [],
+
+        /// The core that gets the peripheral IRQs.
+        IRQ_CORE OFFSET(0) NUMBITS(2) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    InterruptControllerRegisterBlock {
+        (0x00 => _reserved1),
+        (0x0C => FIQ_CONTROL: ReadWrite<u32, FIQ_CONTROL::Register>),
+        (0x10 => @END),
+    }
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    LocalRegisterBlock {
+        (0x00 => _reserved1),
+        (0x0C => GPU_INT_ROUTING: ReadWrite<u32, GPU_INT_ROUTING::Register>),
+        (0x10 => @END),
+    }
+}
+
+struct FiqControlInner {
+    interrupt_controller: MMIODerefWrapper<InterruptControllerRegisterBlock>,
+    local: MMIODerefWrapper<LocalRegisterBlock>,
+}
+
+// -------------------------------------------------------------------------------------------------
+// Public Definitions
+// -------------------------------------------------------------------------------------------------
+
+/// Representation of the FIQ routing.
+pub struct FiqControl {
+    inner: NullLock<FiqControlInner>,
+}
+
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
+impl FiqControl {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide the correct base addresses of the legacy interrupt
+    ///   controller's registers and the core-local interrupt controller.
+    pub const unsafe fn new(interrupt_controller_base_addr: usize, local_base_addr: usize) -> Self {
+        Self {
+            inner: NullLock::new(FiqControlInner {
+                interrupt_controller: MMIODerefWrapper::new(interrupt_controller_base_addr),
+                local: MMIODerefWrapper::new(local_base_addr),
+            }),
+        }
+    }
+
+    /// Deliver the peripheral interrupt `irq` as an FIQ to the core `core_id`.
+    pub fn route_to_core(&self, irq: u32, core_id: usize) {
+        let mut r = &self.inner;
+        r.lock(|inner| {
+            inner
+                .local
+                .GPU_INT_ROUTING
+                .modify(GPU_INT_ROUTING::FIQ_CORE.val(core_id as u32));
+            inner
+                .interrupt_controller
+                .FIQ_CONTROL
+                .write(FIQ_CONTROL::ENABLE::SET + FIQ_CONTROL::SOURCE.val(irq));
+        })
+    }
+
+    /// Give the FIQ source back to the IRQ line.
+    pub fn disable(&self) {
+        let mut r = &self.inner;
+        r.lock(|inner| inner.interrupt_controller.FIQ_CONTROL.set(0))
+    }
+}
+
+use crate::synchronization::interface::Mutex;
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use crate::bsp::device_driver::common::simulated_mmio::{Access::*, SimulatedMemory};
+
+    /// The core is picked before the source is enabled, keeping the IRQ routing.
+    #[test_case]
+    fn route_to_core_register_sequence() {
+        let interrupt_controller =
+            SimulatedMemory::new(core::mem::size_of::<InterruptControllerRegisterBlock>());
+        let mut local = SimulatedMemory::new(core::mem::size_of::<LocalRegisterBlock>());
+        local.set_u32(0x0C, 0b01);
+        let fiq = unsafe { FiqControl::new(interrupt_controller.base_addr(), local.base_addr()) };
+
+        fiq.route_to_core(57, 2);
+
+        assert_eq!(
+            local.take_log(),
+            [
+                Read { addr: 0x0C, value: 0b01 },
+                Write { addr: 0x0C, value: 0b1001 },
+            ]
+        );
+        assert_eq!(
+            interrupt_controller.take_log(),
+            [Write { addr: 0x0C, value: 0xB9 }]
+        );
+    }
+}
@@ -1,9 +1,12 @@
 
 //! GPIO Driver.
 
-use crate::{cpu, driver, synchronization::NullLock};
-use core::ops;
-use register::{mmio::*, register_bitfields, register_structs};
+use crate::{
+    bsp::device_driver::common::{mmio::*, MMIODerefWrapper},
+    cpu, driver,
+    synchronization::NullLock,
+};
+use register::{register_bitfields, register_structs};
 
 // -------------------------------------------------------------------------------------------------
 // Private Definitions
@@ -62,8 +65,11 @@ register_structs! {
     }
 }
 
+/// Abstraction for the associated MMIO registers.
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
 struct GPIOInner {
-    base_addr: usize,
+    registers: Registers,
 }
 
 // -------------------------------------------------------------------------------------------------
@@ -79,22 +85,14 @@ pub struct GPIO {
 // Private code
 // -------------------------------------------------------------------------------------------------
 
-impl ops::Deref for GPIOInner {
-    type Target = RegisterBlock;
-
-    fn deref(&self) -> &Self::Target {
-        unsafe { &*self.ptr() }
-    }
-}
-
 impl GPIOInner {
-    const fn new(base_addr: usize) -> Self {
-        Self { base_addr }
-    }
-
-    /// Return a pointer to the associated MMIO register block.
-    fn ptr(&self) -> *const RegisterBlock {
-        self.base_addr as *const _
+    /// # Safety
+    ///
+    /// - The user must ensure to provide the correct `base_addr`.
+    const unsafe fn new(base_addr: usize) -> Self {
+        Self {
+            registers: Registers::new(base_addr),
+        }
     }
 }
 
@@ -121,17 +119,20 @@ impl GPIO {
         r.lock(|inner| {
             // Map to pins
             inner
+                .registers
                 .GPFSEL1
                 .modify(GPFSEL1::FSEL14::AltFunc0 + GPFSEL1::FSEL15::AltFunc0);
 
                 // Enable pins 14 and 15
-                inner.GPPUD.set(0);
+                inner.registers.GPPUD.set(0);
                 cpu::spin_for_cycles(150);
 
-                inner.GPPUDCLK0.write(GPPUDCLK0::PUDCLK14::AssertClock + GPPUDCLK0::PUDCLK15::AssertClock);
+                inner.registers.GPPUDCLK0.write(
+                    GPPUDCLK0::PUDCLK14::AssertClock + GPPUDCLK0::PUDCLK15::AssertClock,
+                );
                 cpu::spin_for_cycles(150);
 
-                inner.GPPUDCLK0.set(0);
+                inner.registers.GPPUDCLK0.set(0);
         })
     }
 }
@@ -147,4 +148,52 @@ impl driver::interface::DeviceDriver for GPIO {
     fn compatible(&self) -> &str {
         "BCM GPIO"
     }
-}
\ No newline at end of file
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use crate::bsp::device_driver::common::simulated_mmio::{Access::*, SimulatedMemory};
+
+    /// Pins 14 and 15 must be switched to ALT0, followed by the pull-up/down clock sequence.
+    #[test_case]
+    fn map_pl011_uart_register_sequence() {
+        let mem = SimulatedMemory::new(core::mem::size_of::<RegisterBlock>());
+        let gpio = unsafe { GPIO::new(mem.base_addr()) };
+
+        gpio.map_pl011_uart();
+
+        assert_eq!(
+            mem.take_log(),
+            [
+                Read { addr: 0x04, value: 0 },
+                Write { addr: 0x04, value: 0x0002_4000 },
+                Write { addr: 0x94, value: 0 },
+                Write { addr: 0x98, value: 0x0000_C000 },
+                Write { addr: 0x98, value: 0 },
+            ]
+        );
+    }
+
+    /// The function select of the other pins in GPFSEL1 must be left alone.
+    #[test_case]
+    fn map_pl011_uart_preserves_other_pins() {
+        let mut mem = SimulatedMemory::new(core::mem::size_of::<RegisterBlock>());
+        mem.set_u32(0x04, 0xFFFF_FFFF);
+        let gpio = unsafe { GPIO::new(mem.base_addr()) };
+
+        gpio.map_pl011_uart();
+
+        assert_eq!(
+            mem.take_log()[1],
+            Write {
+                addr: 0x04,
+                value: 0xFFFE_4FFF
+            }
+        );
+    }
+}
@@ -1,8 +1,12 @@
 //! PL011 UART driver.
 
-use crate::{console, cpu, driver, synchronization::NullLock};
-use core::{fmt, ops};
-use register::{mmio::*, register_bitfields, register_structs};
+use crate::{
+    bsp::device_driver::common::{mmio::*, MMIODerefWrapper},
+    console, cpu, driver,
+    synchronization::IRQSafeLock,
+};
+use core::fmt;
+use register::{register_bitfields, register_structs};
 
 // ------------------------- Private definitions ---------------------------------------------------
 
@@ -97,8 +101,25 @@ register_bitfields! {
         ]
     ],
 
+    /// Interrupt Mask Set/Clear Register
+    IMSC [
+        /// Break error interrupt mask. A read returns the current mask for the UARTBEINTR
+        /// interrupt. On a write of 1, the mask of the interrupt is set.
+        BEIM OFFSET(9) NUMBITS(1) []
+    ],
+
+    /// Masked Interrupt Status Register
+    MIS [
+        /// Break error masked interrupt status. Returns the masked interrupt state of the
+        /// UARTBEINTR interrupt.
+        BEMIS OFFSET(9) NUMBITS(1) []
+    ],
+
     /// Interrupt Clear Register
     ICR [
+        /// Break error interrupt clear. Clears the UARTBEINTR interrupt.
+        BEIC OFFSET(9) NUMBITS(1) [],
+
         /// Meta field for all pending interrupts
         ALL OFFSET(0) NUMBITS(11) []
     ]
@@ -122,13 +143,19 @@ register_structs! {
         (0x2c => LCRH: WriteOnly<u32, LCRH::Register>),
         (0x30 => CR: WriteOnly<u32, CR::Register>),
         (0x34 => _reserved3),
+        (0x38 => IMSC: ReadWrite<u32, IMSC::Register>),
+        (0x3c => _reserved4),
+        (0x40 => MIS: ReadOnly<u32, MIS::Register>),
         (0x44 => ICR: WriteOnly<u32, ICR::Register>),
         (0x48 => @END),
     }
 }
 
+/// Abstraction for the associated MMIO registers.
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
 pub struct PL011UartInner {
-    base_addr: usize,
+    registers: Registers,
     chars_written: usize,
     chars_read: usize,
 }
@@ -138,28 +165,11 @@ pub use PL011UartInner as PanicUart;
 
 // Representation of the UART
 pub struct PL011Uart {
-    inner: NullLock<PL011UartInner>,
+    inner: IRQSafeLock<PL011UartInner>,
 }
 
 // ----------------------------------- PUBLIC CODE -------------------------------------------------
 
-/// Deref to RegisterBlock.
-///
-/// Allows writing
-/// ```
-/// self.DR.read()
-/// ```
-/// instead of something along the lines of
-/// ```
-/// unsafe { (*PL011UartInner::ptr()).DR.read() }
-impl ops::Deref for PL011UartInner {
-    type Target = RegisterBlock;
-
-    fn deref(&self) -> &Self::Target {
-        unsafe { &*self.ptr() }
-    }
-}
-
 impl PL011UartInner {
     /// Create an instance.
     ///
@@ -168,7 +178,7 @@ impl PL011UartInner {
     /// - The user must ensure to provide the correct `base_addr`.
     pub const unsafe fn new(base_addr: usize) -> Self {
         Self {
-            base_addr,
+            registers: Registers::new(base_addr),
             chars_written: 0,
             chars_read: 0,
         }
@@ -181,31 +191,28 @@ impl PL011UartInner {
     /// firmware).
     pub fn init(&mut self) {
         // Turn if off temporarily
-        self.CR.set(0);
+        self.registers.CR.set(0);
 
-        self.ICR.write(ICR::ALL::CLEAR);
-        self.IBRD.write(IBRD::IBRD.val(13));
-        self.FBRD.write(FBRD::FBRD.val(2));
-        self.LCRH
+        self.registers.ICR.write(ICR::ALL::CLEAR);
+        self.registers.IBRD.write(IBRD::IBRD.val(13));
+        self.registers.FBRD.write(FBRD::FBRD.val(2));
+        self.registers
+            .LCRH
             .write(LCRH::WLEN::EightBit + LCRH::FEN::FifosEnabled); // 8NI + FIFO on
-        self.CR
+        self.registers
+            .CR
             .write(CR::UARTEN::Enabled + CR::TXE::Enabled + CR::RXE::Enabled);
     }
 
-    /// Return a pointer to the register block
-    fn ptr(&self) -> *const RegisterBlock {
-        self.base_addr as *const _
-    }
-
     /// Send a character
     fn write_char(&mut self, c: char) {
         // Spin while TX FIFO full is set, waiting for an empty slot
-        while self.FR.matches_all(FR::TXFF::SET) {
+        while self.registers.FR.matches_all(FR::TXFF::SET) {
             cpu::nop();
         }
 
         // Write the character to the buffer
-        self.DR.set(c as u32);
+        self.registers.DR.set(c as u32);
         self.chars_written += 1;
     }
 }
@@ -235,9 +242,37 @@ impl PL011Uart {
     /// - The user must ensure to provide the correct `base_addr`.
     pub const unsafe fn new(base_addr: usize) -> Self {
         Self {
-            inner: NullLock::new(PL011UartInner::new(base_addr)),
+            inner: IRQSafeLock::new(PL011UartInner::new(base_addr)),
         }
     }
+
+    /// Raise the UART's interrupt when a break is received, and for nothing else.
+    pub fn enable_break_interrupt(&self) {
+        let mut r = &self.inner;
+        r.lock(|inner| {
+            inner.registers.ICR.write(ICR::BEIC::SET);
+            inner.registers.IMSC.write(IMSC::BEIM::SET);
+        })
+    }
+
+    /// Mask all of the UART's interrupts.
+    pub fn disable_break_interrupt(&self) {
+        let mut r = &self.inner;
+        r.lock(|inner| inner.registers.IMSC.set(0))
+    }
+
+    /// Check if a break was received, and acknowledge it if so.
+    pub fn take_break(&self) -> bool {
+        let mut r = &self.inner;
+        r.lock(|inner| {
+            if !inner.registers.MIS.is_set(MIS::BEMIS) {
+                return false;
+            }
+
+            inner.registers.ICR.write(ICR::BEIC::SET);
+            true
+        })
+    }
 }
 
 // ------------------------------ OS INTERFACE CODE ------------------------------------------------
@@ -276,7 +311,7 @@ impl console::interface::Write for PL011Uart {
         // Spin until TX FIFO empty is set
         let mut r = &self.inner;
         r.lock(|inner| {
-            while !inner.FR.matches_all(FR::TXFE::SET) {
+            while !inner.registers.FR.matches_all(FR::TXFE::SET) {
                 cpu::nop();
             }
         })
@@ -289,12 +324,12 @@ impl console::interface::Read for PL011Uart {
 
         r.lock(|inner| {
             // Spin while RX FIFO empty is set
-            while inner.FR.matches_all(FR::RXFE::SET) {
+            while inner.registers.FR.matches_all(FR::RXFE::SET) {
                 cpu::nop();
             }
 
             // Read one character
-            inner.DR.get() as u8 as char
+            inner.registers.DR.get() as u8 as char
         })
     }
 
@@ -302,8 +337,8 @@ impl console::interface::Read for PL011Uart {
         let mut r = &self.inner;
         r.lock(|inner| {
             // Read from the RX FIFO until it is indicating empty.
-            while !inner.FR.matches_all(FR::RXFE::SET) {
-                inner.DR.get();
+            while !inner.registers.FR.matches_all(FR::RXFE::SET) {
+                inner.registers.DR.get();
             }
         })
     }
@@ -320,3 +355,106 @@ impl console::interface::Statistics for PL011Uart {
         r.lock(|inner| inner.chars_read)
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use crate::bsp::device_driver::common::simulated_mmio::{Access::*, SimulatedMemory};
+    use console::interface::{Read, Write};
+
+    fn simulated_uart() -> SimulatedMemory {
+        SimulatedMemory::new(core::mem::size_of::<RegisterBlock>())
+    }
+
+    /// The UART must be disabled while baud rate and line control are programmed, and enabled last.
+    #[test_case]
+    fn init_register_sequence() {
+        let mem = simulated_uart();
+        let mut uart = unsafe { PL011UartInner::new(mem.base_addr()) };
+
+        uart.init();
+
+        assert_eq!(
+            mem.take_log(),
+            [
+                Write { addr: 0x30, value: 0 },
+                Write { addr: 0x44, value: 0 },
+                Write { addr: 0x24, value: 13 },
+                Write { addr: 0x28, value: 2 },
+                Write { addr: 0x2c, value: 0x70 },
+                Write { addr: 0x30, value: 0x301 },
+            ]
+        );
+    }
+
+    /// A character is only written after checking that the TX FIFO has room.
+    #[test_case]
+    fn write_char_checks_tx_fifo() {
+        let mem = simulated_uart();
+        let uart = unsafe { PL011Uart::new(mem.base_addr()) };
+
+        uart.write_char('A');
+
+        assert_eq!(
+            mem.take_log(),
+            [
+                Read { addr: 0x18, value: 0 },
+                Write { addr: 0x00, value: 0x41 },
+            ]
+        );
+        assert_eq!(console::interface::Statistics::chars_written(&uart), 1);
+    }
+
+    /// Only the break interrupt is unmasked, after clearing a stale one, and taking a break
+    /// acknowledges it.
+    #[test_case]
+    fn break_interrupt() {
+        let mut mem = simulated_uart();
+        let uart = unsafe { PL011Uart::new(mem.base_addr()) };
+
+        uart.enable_break_interrupt();
+        assert_eq!(
+            mem.take_log(),
+            [
+                Write { addr: 0x44, value: 0x200 },
+                Write { addr: 0x38, value: 0x200 },
+            ]
+        );
+
+        assert!(!uart.take_break());
+        mem.set_u32(0x40, 0x200);
+        assert!(uart.take_break());
+        assert_eq!(
+            mem.take_log(),
+            [
+                Read { addr: 0x40, value: 0 },
+                Read { addr: 0x40, value: 0x200 },
+                Write { addr: 0x44, value: 0x200 },
+            ]
+        );
+    }
+
+    /// A character is only read after checking that the RX FIFO is not empty.
+    #[test_case]
+    fn read_char_checks_rx_fifo() {
+        let mut mem = simulated_uart();
+        mem.set_u32(0x00, u32::from(b'x'));
+        let uart = unsafe { PL011Uart::new(mem.base_addr()) };
+
+        assert_eq!(uart.read_char(), 'x');
+        assert_eq!(
+            mem.take_log(),
+            [
+                Read { addr: 0x18, value: 0 },
+                Read {
+                    addr: 0x00,
+                    value: u64::from(b'x')
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! Power management watchdog driver.
+//!
+//! The watchdog is the only way to reset the SoC. Halting works the same way: the firmware checks
+//! the reset status partition after the reset, and stays halted if it reads the halt indicator.
+
+use crate::{
+    bsp::device_driver::common::{mmio::*, MMIODerefWrapper},
+    driver,
+    synchronization::NullLock,
+};
+use register::{register_bitfields, register_structs};
+
+// -------------------------------------------------------------------------------------------------
+// Private Definitions
+// -------------------------------------------------------------------------------------------------
+
+register_bitfields! {
+    u32,
+
+    /// Reset Control
+    RSTC [
+        /// Every write must carry the password.
+        PASSWD OFFSET(24) NUMBITS(8) [
+            Magic = 0x5a
+        ],
+
+        /// What happens when the watchdog times out.
+        WRCFG OFFSET(4) NUMBITS(2) [
+            Clear = 0b00,
+            FullReset = 0b10
+        ]
+    ],
+
+    /// Reset Status
+    RSTS [
+        /// Every write must carry the password.
+        PASSWD OFFSET(24) NUMBITS(8) [
+            Magic = 0x5a
+        ],
+
+        /// The partition to boot from, spread over the even bits 0 to 10. Partition 63 is what the
+        /// firmware takes as the request to halt.
+        PARTITION OFFSET(0) NUMBITS(11) [
+            Halt = 0x555
+        ]
+    ],
+
+    /// Watchdog
+    WDOG [
+        /// Every write must carry the password.
+        PASSWD OFFSET(24) NUMBITS(8) [
+            Magic = 0x5a
+        ],
+
+        /// Ticks until the watchdog times out, in units of 16 µs.
+        TIME OFFSET(0) NUMBITS(20) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => _reserved1),
+        (0x1C => RSTC: ReadWrite<u32, RSTC::Register>),
+        (0x20 => RSTS: ReadWrite<u32, RSTS::Register>),
+        (0x24 => WDOG: ReadWrite<u32, WDOG::Register>),
+        (0x28 => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+struct WatchdogInner {
+    registers: Registers,
+}
+
+/// Watchdog ticks until the reset. Short, but long enough for the writes to settle.
+const RESET_TICKS: u32 = 10;
+
+// -------------------------------------------------------------------------------------------------
+// Public Definitions
+// -------------------------------------------------------------------------------------------------
+
+/// Representation of the power management watchdog.
+pub struct Watchdog {
+    inner: NullLock<WatchdogInner>,
+}
+
+// -------------------------------------------------------------------------------------------------
+// Private code
+// -------------------------------------------------------------------------------------------------
+
+impl WatchdogInner {
+    /// # Safety
+    ///
+    /// - The user must ensure to provide the correct `base_addr`.
+    const unsafe fn new(base_addr: usize) -> Self {
+        Self {
+            registers: Registers::new(base_addr),
+        }
+    }
+
+    /// Let the watchdog run out shortly, resetting the whole SoC.
+    fn reset(&mut self) {
+        self.registers
+            .WDOG
+            .write(WDOG::PASSWD::Magic + WDOG::TIME.val(RESET_TICKS));
+        self.registers
+            .RSTC
+            .modify(RSTC::PASSWD::Magic + RSTC::WRCFG::FullReset);
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
+impl Watchdog {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide the correct `base_addr`.
+    pub const unsafe fn new(base_addr: usize) -> Self {
+        Self {
+            inner: NullLock::new(WatchdogInner::new(base_addr)),
+        }
+    }
+
+    /// Reset the SoC. The reset happens a few ticks after the call returns.
+    pub fn reset(&self) {
+        let mut r = &self.inner;
+        r.lock(|inner| inner.reset())
+    }
+
+    /// Reset the SoC and ask the firmware to stay halted. The reset happens a few ticks after the
+    /// call returns.
+    pub fn halt(&self) {
+        let mut r = &self.inner;
+        r.lock(|inner| {
+            inner
+                .registers
+                .RSTS
+                .modify(RSTS::PASSWD::Magic + RSTS::PARTITION::Halt);
+            inner.reset();
+        })
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// OS Interface Code
+// -------------------------------------------------------------------------------------------------
+
+use crate::synchronization::interface::Mutex;
+
+impl driver::interface::DeviceDriver for Watchdog {
+    fn compatible(&self) -> &str {
+        "BCM Watchdog"
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use crate::bsp::device_driver::common::simulated_mmio::{Access::*, SimulatedMemory};
+
+    /// The watchdog must be armed before the full reset is configured, keeping the other bits of
+    /// RSTC.
+    #[test_case]
+    fn reset_register_sequence() {
+        let mut mem = SimulatedMemory::new(core::mem::size_of::<RegisterBlock>());
+        mem.set_u32(0x1C, 0x0000_0F3F);
+        let watchdog = unsafe { Watchdog::new(mem.base_addr()) };
+
+        watchdog.reset();
+
+        assert_eq!(
+            mem.take_log(),
+            [
+                Write {
+                    addr: 0x24,
+                    value: 0x5A00_000A
+                },
+                Read {
+                    addr: 0x1C,
+                    value: 0x0000_0F3F
+                },
+                Write {
+                    addr: 0x1C,
+                    value: 0x5A00_0F2F
+                },
+            ]
+        );
+    }
+
+    /// Halting marks partition 63 before resetting.
+    #[test_case]
+    fn halt_marks_partition() {
+        let mem = SimulatedMemory::new(core::mem::size_of::<RegisterBlock>());
+        let watchdog = unsafe { Watchdog::new(mem.base_addr()) };
+
+        watchdog.halt();
+
+        let log = mem.take_log();
+        assert_eq!(
+            log[1],
+            Write {
+                addr: 0x20,
+                value: 0x5A00_0555
+            }
+        );
+        assert_eq!(
+            log[2],
+            Write {
+                addr: 0x24,
+                value: 0x5A00_000A
+            }
+        );
+    }
+}
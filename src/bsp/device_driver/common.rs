@@ -2,14 +2,42 @@
 
 use core::{marker::PhantomData, ops};
 
+#[cfg(not(target_os = "none"))]
+pub mod simulated_mmio;
 
+// -------------------------------------------------------------------------------------------------
+// Public definitions
+// -------------------------------------------------------------------------------------------------
+
+/// The MMIO register types used in the drivers' `register_structs!`.
+///
+/// On the board, these are the real thing from the `register` crate. On the host, they are swapped
+/// for simulated registers that record every access, so that drivers can be unit tested for the
+/// register sequences they produce.
+pub mod mmio {
+    #[cfg(target_os = "none")]
+    pub use register::mmio::{ReadOnly, ReadWrite, WriteOnly};
+
+    #[cfg(not(target_os = "none"))]
+    pub use super::simulated_mmio::{ReadOnly, ReadWrite, WriteOnly};
+}
+
+/// Gives typed access to a device's MMIO register block at `base_addr`.
 pub struct MMIODerefWrapper<T> {
     base_addr: usize,
     phantom: PhantomData<T>,
 }
 
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
 impl<T> MMIODerefWrapper<T> {
-    /// Create an instance
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide the correct `base_addr`.
     pub const unsafe fn new(base_addr: usize) -> Self {
         Self {
             base_addr,
@@ -23,11 +51,20 @@ impl<T> MMIODerefWrapper<T> {
     }
 }
 
-impl ops::Deref for MMIODerefWrapper<T> {
+/// Deref to the register block.
+///
+/// Allows writing
+/// ```
+/// self.registers.DR.read()
+/// ```
+/// instead of something along the lines of
+/// ```
+/// unsafe { (*self.registers.ptr()).DR.read() }
+/// ```
+impl<T> ops::Deref for MMIODerefWrapper<T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
         unsafe { &*self.ptr() }
     }
 }
-
@@ -0,0 +1,212 @@
+//! Simulated MMIO for host-side driver tests.
+//!
+//! Drop-in replacements for `register::mmio::{ReadOnly, ReadWrite, WriteOnly}`. The registers are
+//! backed by ordinary memory (see [`SimulatedMemory`]) instead of a device, and every access is
+//! appended to a per-thread log. A test points a driver at a `SimulatedMemory`, calls into it, and
+//! then compares the log against the register sequence the hardware expects.
+
+use register::{Field, FieldValue, IntLike, RegisterLongName};
+use std::{cell::RefCell, cell::UnsafeCell, marker::PhantomData};
+
+// -------------------------------------------------------------------------------------------------
+// Public definitions
+// -------------------------------------------------------------------------------------------------
+
+/// A single register access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    /// `value` was read from the register at `addr`.
+    Read { addr: usize, value: u64 },
+
+    /// `value` was written to the register at `addr`.
+    Write { addr: usize, value: u64 },
+}
+
+/// Zeroed memory standing in for a device's register block.
+pub struct SimulatedMemory {
+    mem: Vec<u64>,
+}
+
+/// Read-only register.
+#[repr(transparent)]
+pub struct ReadOnly<T: IntLike, R: RegisterLongName = ()> {
+    value: UnsafeCell<T>,
+    associated_register: PhantomData<R>,
+}
+
+/// Write-only register.
+#[repr(transparent)]
+pub struct WriteOnly<T: IntLike, R: RegisterLongName = ()> {
+    value: UnsafeCell<T>,
+    associated_register: PhantomData<R>,
+}
+
+/// Read-write register.
+#[repr(transparent)]
+pub struct ReadWrite<T: IntLike, R: RegisterLongName = ()> {
+    value: UnsafeCell<T>,
+    associated_register: PhantomData<R>,
+}
+
+// -------------------------------------------------------------------------------------------------
+// Private code
+// -------------------------------------------------------------------------------------------------
+
+std::thread_local! {
+    static LOG: RefCell<Vec<Access>> = RefCell::new(Vec::new());
+}
+
+fn record(access: Access) {
+    LOG.with(|log| log.borrow_mut().push(access));
+}
+
+fn read<T: IntLike + Into<u64>>(cell: &UnsafeCell<T>) -> T {
+    let value = unsafe { core::ptr::read_volatile(cell.get()) };
+    record(Access::Read {
+        addr: cell.get() as usize,
+        value: value.into(),
+    });
+
+    value
+}
+
+fn write<T: IntLike + Into<u64>>(cell: &UnsafeCell<T>, value: T) {
+    record(Access::Write {
+        addr: cell.get() as usize,
+        value: value.into(),
+    });
+    unsafe { core::ptr::write_volatile(cell.get(), value) };
+}
+
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
+impl Access {
+    fn addr(&self) -> usize {
+        match *self {
+            Access::Read { addr, .. } | Access::Write { addr, .. } => addr,
+        }
+    }
+
+    fn rebase(self, base: usize) -> Self {
+        match self {
+            Access::Read { addr, value } => Access::Read {
+                addr: addr - base,
+                value,
+            },
+            Access::Write { addr, value } => Access::Write {
+                addr: addr - base,
+                value,
+            },
+        }
+    }
+}
+
+impl SimulatedMemory {
+    /// Create `size` bytes of zeroed register memory.
+    pub fn new(size: usize) -> Self {
+        Self {
+            mem: vec![0; (size + 7) / 8],
+        }
+    }
+
+    /// The address to hand to a driver's `new()`.
+    pub fn base_addr(&self) -> usize {
+        self.mem.as_ptr() as usize
+    }
+
+    /// Preset a 32 bit register without recording an access, e.g. to simulate a status flag.
+    pub fn set_u32(&mut self, offset: usize, value: u32) {
+        assert!(offset + 4 <= self.mem.len() * 8);
+        unsafe { core::ptr::write_volatile((self.base_addr() + offset) as *mut u32, value) };
+    }
+
+    /// Remove and return all recorded accesses to this memory, with addresses as offsets from
+    /// `base_addr()`.
+    pub fn take_log(&self) -> Vec<Access> {
+        let base = self.base_addr();
+        let end = base + self.mem.len() * 8;
+
+        LOG.with(|log| {
+            let mut log = log.borrow_mut();
+            let (ours, others): (Vec<Access>, Vec<Access>) = log
+                .drain(..)
+                .partition(|a| (base..end).contains(&a.addr()));
+            *log = others;
+
+            ours.into_iter().map(|a| a.rebase(base)).collect()
+        })
+    }
+}
+
+impl<T: IntLike + Into<u64>, R: RegisterLongName> ReadOnly<T, R> {
+    /// Get the raw register value.
+    pub fn get(&self) -> T {
+        read(&self.value)
+    }
+
+    /// Read the value of the given field.
+    pub fn read(&self, field: Field<T, R>) -> T {
+        field.read(self.get())
+    }
+
+    /// Check if one or more bits in a field are set.
+    pub fn is_set(&self, field: Field<T, R>) -> bool {
+        field.is_set(self.get())
+    }
+
+    /// Check if all specified parts of a field match.
+    pub fn matches_all(&self, field: FieldValue<T, R>) -> bool {
+        field.matches_all(self.get())
+    }
+}
+
+impl<T: IntLike + Into<u64>, R: RegisterLongName> WriteOnly<T, R> {
+    /// Set the raw register value.
+    pub fn set(&self, value: T) {
+        write(&self.value, value)
+    }
+
+    /// Write the value of one or more fields, overwriting the other fields with zero.
+    pub fn write(&self, field: FieldValue<T, R>) {
+        self.set(field.value)
+    }
+}
+
+impl<T: IntLike + Into<u64>, R: RegisterLongName> ReadWrite<T, R> {
+    /// Get the raw register value.
+    pub fn get(&self) -> T {
+        read(&self.value)
+    }
+
+    /// Set the raw register value.
+    pub fn set(&self, value: T) {
+        write(&self.value, value)
+    }
+
+    /// Read the value of the given field.
+    pub fn read(&self, field: Field<T, R>) -> T {
+        field.read(self.get())
+    }
+
+    /// Write the value of one or more fields, overwriting the other fields with zero.
+    pub fn write(&self, field: FieldValue<T, R>) {
+        self.set(field.value)
+    }
+
+    /// Write the value of one or more fields, leaving the other fields unchanged.
+    pub fn modify(&self, field: FieldValue<T, R>) {
+        self.set(field.modify(self.get()))
+    }
+
+    /// Check if one or more bits in a field are set.
+    pub fn is_set(&self, field: Field<T, R>) -> bool {
+        field.is_set(self.get())
+    }
+
+    /// Check if all specified parts of a field match.
+    pub fn matches_all(&self, field: FieldValue<T, R>) -> bool {
+        field.matches_all(self.get())
+    }
+}
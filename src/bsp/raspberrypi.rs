@@ -7,7 +7,9 @@
 pub mod console;
 pub mod cpu;
 pub mod driver;
+pub mod hypervisor;
 pub mod memory;
+pub mod psci;
 
 
 // ---------------------------------- Global instances ---------------------------------------------
@@ -21,6 +23,18 @@ static PL011_UART: device_driver::PL011Uart = unsafe {
     device_driver::PL011Uart::new(memory::map::mmio::PL011_UART_BASE)
 };
 
+static WATCHDOG: device_driver::Watchdog = unsafe {
+    device_driver::Watchdog::new(memory::map::mmio::WATCHDOG_BASE)
+};
+
+#[cfg(feature = "bsp_rpi3")]
+static FIQ_CONTROL: device_driver::FiqControl = unsafe {
+    device_driver::FiqControl::new(
+        memory::map::mmio::IRQ_CTRL_BASE,
+        memory::map::mmio::LOCAL_IRQ_CTRL_BASE,
+    )
+};
+
 // ------------------------------------ Public code ------------------------------------------------
 
 /// Board indentification
@@ -4,6 +4,15 @@ use super::memory;
 use crate::{bsp::device_driver, console};
 use core::fmt;
 
+// -------------------------------------------------------------------------------------------------
+// Public definitions
+// -------------------------------------------------------------------------------------------------
+
+/// The console UART's base address.
+pub const UART_BASE: usize = memory::map::mmio::PL011_UART_BASE;
+
+/// The console's baud rate, as the UART driver sets it up.
+pub const BAUD_RATE: u32 = 230_400;
 
 // -------------------------------------------------------------------------------------------------
 // Public code
@@ -21,10 +30,23 @@ pub unsafe fn panic_console_out() -> impl fmt::Write {
     uart
 }
 
+/// Return a reference to the console.
 pub fn console() -> &'static impl console::interface::All {
     &super::PL011_UART
 }
 
+/// Minimal code needed to bring up the console in QEMU (for testing only).
+///
+/// QEMU's PL011 does not need the baud rate and pin setup of the real hardware, but initializing
+/// the driver keeps the statistics and FIFO state the same as on the board.
+#[cfg(feature = "test_build")]
+pub fn qemu_bring_up_console() {
+    use crate::driver::interface::DeviceDriver;
+
+    // The UART driver's init cannot fail.
+    let _ = super::PL011_UART.init();
+}
+
 // use crate::{console, synchronization, synchronization::NullLock};
 // use core::fmt;
 
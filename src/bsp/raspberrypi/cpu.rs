@@ -7,8 +7,33 @@
 /// Used by `arch` code to find the early boot core
 pub const BOOT_CORE_ID: usize = 0;
 
-/// The early boot core's stach address
+/// Where the stack of a payload that is entered at EL1 starts, right below its default load address
 pub const BOOT_CORE_STACK_START: u64 = 0x80_000;
 
 /// The address on which the Raspberry firmware loads every binary by default
-pub const BOARD_DEFAULT_LOAD_ADDRESS: usize = 0x80_000;
\ No newline at end of file
+pub const BOARD_DEFAULT_LOAD_ADDRESS: usize = 0x80_000;
+
+/// The number of cores.
+pub const NUM_CORES: usize = 4;
+
+/// Start of the spin-table through which the secondary cores are released.
+///
+/// Each core has a 64 bit release address at `SPIN_TABLE_START + 8 * core_id`, which is where the
+/// firmware's own ARM stubs put it as well. A secondary core waits until its release address reads
+/// non-zero, and then jumps to the address that was written there.
+pub const SPIN_TABLE_START: usize = 0xd8;
+
+// -----------------------------------------------------------------------------
+// Public Code
+// -----------------------------------------------------------------------------
+
+/// The spin-table release address of the core with the given MPIDR affinity value.
+pub fn spin_table_release_addr(mpidr: u64) -> Option<u64> {
+    let core_id = (mpidr & 0xff) as usize;
+
+    if core_id >= NUM_CORES {
+        return None;
+    }
+
+    Some((SPIN_TABLE_START + 8 * core_id) as u64)
+}
@@ -9,7 +9,7 @@ use crate::driver;
 
 /// Device Driver Manager Type
 pub struct BSPDriverManager {
-    device_drivers: [&'static (dyn DeviceDriver + Sync); 2],
+    device_drivers: [&'static (dyn DeviceDriver + Sync); 3],
 }
 
 // -------------------------------------------------------------------------------------------------
@@ -17,7 +17,7 @@ pub struct BSPDriverManager {
 // -------------------------------------------------------------------------------------------------
 
 static BSP_DRIVER_MANAGER: BSPDriverManager = BSPDriverManager {
-    device_drivers: [&super::GPIO, &super::PL011_UART],
+    device_drivers: [&super::GPIO, &super::PL011_UART, &super::WATCHDOG],
 };
 
 // -------------------------------------------------------------------------------------------------
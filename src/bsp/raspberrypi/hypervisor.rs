@@ -0,0 +1,56 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! BSP support for the thin hypervisor.
+//!
+//! A break on the console UART is delivered as an FIQ, which the hypervisor takes at EL2 while the
+//! payload runs. This needs the legacy interrupt controller of the Raspberry Pi 3. The Raspberry
+//! Pi 4's firmware switches to the GIC, which is not supported.
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The PL011 UART's peripheral interrupt.
+#[cfg(feature = "bsp_rpi3")]
+const PL011_UART_IRQ: u32 = 57;
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Deliver a break on the console UART as an FIQ to the core `core_id`.
+///
+/// The UART raises no other interrupts afterwards, and a payload that programs the UART's
+/// interrupts itself takes this away again.
+pub fn enable_uart_break_fiq(core_id: usize) -> Result<(), &'static str> {
+    #[cfg(feature = "bsp_rpi3")]
+    {
+        super::PL011_UART.enable_break_interrupt();
+        super::FIQ_CONTROL.route_to_core(PL011_UART_IRQ, core_id);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "bsp_rpi4")]
+    {
+        let _ = core_id;
+
+        Err("Needs the legacy interrupt controller")
+    }
+}
+
+/// Stop delivering UART breaks as FIQs.
+pub fn disable_uart_break_fiq() {
+    #[cfg(feature = "bsp_rpi3")]
+    {
+        super::FIQ_CONTROL.disable();
+        super::PL011_UART.disable_break_interrupt();
+    }
+}
+
+/// Check if the console UART received a break, and acknowledge it if so.
+pub fn take_uart_break() -> bool {
+    super::PL011_UART.take_break()
+}
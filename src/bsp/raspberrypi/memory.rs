@@ -1,5 +1,9 @@
 //! BSP Memory Management.
 
+pub mod mmu;
+
+use core::ops::Range;
+
 // -------------------------------------------------------------------------------------------------
 // Public definitions
 // -------------------------------------------------------------------------------------------------
@@ -7,8 +11,32 @@
 /// The board's memory map
 #[rustfmt::skip]
 pub(super) mod map {
+    /// The last address that is mapped: DRAM and devices, nothing above.
+    #[cfg(feature = "bsp_rpi3")]
+    pub const END_INCLUSIVE:                usize =         mmio::END_INCLUSIVE;
+    #[cfg(feature = "bsp_rpi4")]
+    pub const END_INCLUSIVE:                usize =         0xFFFF_FFFF;
+
     pub const GPIO_OFFSET:                  usize =         0x0020_0000;
     pub const UART_OFFSET:                  usize =         0x0020_1000;
+    pub const WATCHDOG_OFFSET:              usize =         0x0010_0000;
+    #[cfg(feature = "bsp_rpi3")]
+    pub const IRQ_CTRL_OFFSET:              usize =         0x0000_B200;
+
+    /// The firmware's ARM stubs and the spin-table.
+    pub const FIRMWARE_END:                 usize =         0x0000_0100;
+
+    /// The boot info that is handed to the payload. Far enough below the payload window that the
+    /// stack of a payload entered at EL1 does not reach it. The firmware may put the device tree
+    /// here, which then takes precedence.
+    pub const BOOT_INFO_START:              usize =         0x0000_1000;
+    pub const BOOT_INFO_END:                usize =         0x0000_2000;
+
+    /// The VideoCore's share of the RAM ends here. It starts where the ARM's ends.
+    #[cfg(feature = "bsp_rpi3")]
+    pub const VIDEOCORE_END:                usize =         mmio::BASE;
+    #[cfg(feature = "bsp_rpi4")]
+    pub const VIDEOCORE_END:                usize =         0x4000_0000;
 
     /// Physical devices
     #[cfg(feature = "bsp_rpi3")]
@@ -18,6 +46,10 @@ pub(super) mod map {
         pub const BASE:                     usize =         0x3F00_0000;
         pub const GPIO_BASE:                usize = BASE +  GPIO_OFFSET;
         pub const PL011_UART_BASE:          usize = BASE +  UART_OFFSET;
+        pub const WATCHDOG_BASE:            usize = BASE +  WATCHDOG_OFFSET;
+        pub const IRQ_CTRL_BASE:            usize = BASE +  IRQ_CTRL_OFFSET;
+        pub const LOCAL_IRQ_CTRL_BASE:      usize =         0x4000_0000;
+        pub const END_INCLUSIVE:            usize =         0x4000_FFFF;
     }
 
     #[cfg(feature = "bsp_rpi4")]
@@ -27,5 +59,199 @@ pub(super) mod map {
         pub const BASE:                     usize =         0xFE00_0000;
         pub const GPIO_BASE:                usize = BASE +  GPIO_OFFSET;
         pub const PL011_UART_BASE:          usize = BASE +  UART_OFFSET;
+        pub const WATCHDOG_BASE:            usize = BASE +  WATCHDOG_OFFSET;
+        pub const END_INCLUSIVE:            usize =         0xFF84_FFFF;
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Private code
+// -------------------------------------------------------------------------------------------------
+
+/// The device tree at `dtb_addr`, if there is one.
+///
+/// # Safety
+///
+/// - `dtb_addr` must be zero, or point to readable memory.
+#[cfg(target_os = "none")]
+unsafe fn device_tree(dtb_addr: usize) -> Option<&'static [u8]> {
+    use crate::loader::fdt;
+
+    if dtb_addr == 0 {
+        return None;
+    }
+
+    let header = core::slice::from_raw_parts(dtb_addr as *const u8, fdt::HEADER_PREFIX_SIZE);
+    let total_size = fdt::total_size(header).ok()?;
+    dtb_addr.checked_add(total_size)?;
+
+    Some(core::slice::from_raw_parts(
+        dtb_addr as *const u8,
+        total_size,
+    ))
+}
+
+/// The RAM that the device tree at `dtb_addr` lists. Must not touch any statics.
+#[cfg(target_os = "none")]
+fn device_tree_ram(dtb_addr: usize) -> Option<Range<usize>> {
+    let blob = unsafe { device_tree(dtb_addr)? };
+    let ram = crate::loader::fdt::memory_range(blob).ok().flatten()?;
+
+    Some(ram.start as usize..ram.end as usize)
+}
+
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
+/// Where the loader relocates itself to, given the device tree at `dtb_addr` and the `size` of the
+/// loader including its stacks.
+///
+/// That is right below the end of the RAM, or below the device tree if the firmware put it there,
+/// which leaves the payload window as large as possible. The loader starts and ends on 2 MiB
+/// boundaries, the granularity of the MMU, so that nothing else shares a block with it.
+///
+/// Returns `None` if the device tree does not tell the RAM's size. Runs before the loader is
+/// relocated, so it must not touch any statics.
+#[cfg(target_os = "none")]
+pub fn relocation_target(dtb_addr: usize, size: usize) -> Option<usize> {
+    const BLOCK_MASK: usize = !((1 << 21) - 1);
+
+    let mut top = device_tree_ram(dtb_addr)?.end;
+    if dtb_addr > super::cpu::BOARD_DEFAULT_LOAD_ADDRESS {
+        top = top.min(dtb_addr);
+    }
+
+    Some((top & BLOCK_MASK).checked_sub(size)? & BLOCK_MASK)
+}
+
+/// The RAM a payload may be loaded into.
+///
+/// Starts at the address the firmware would have loaded the payload to and ends where the relocated
+/// loader begins. A payload that is entered at EL1 finds its stack growing downwards from the
+/// window's start.
+#[cfg(target_os = "none")]
+pub fn payload_window() -> Range<usize> {
+    extern "C" {
+        static __binary_start: usize;
+    }
+
+    let binary_start_addr: usize = unsafe { &__binary_start as *const _ as _ };
+
+    super::cpu::BOARD_DEFAULT_LOAD_ADDRESS..binary_start_addr
+}
+
+/// The RAM the relocated loader occupies: the binary, the stacks of all cores, the heap and the boot
+/// core's stack.
+#[cfg(target_os = "none")]
+pub fn loader_range() -> Range<usize> {
+    extern "C" {
+        static __binary_start: usize;
+        static __loader_end: usize;
+    }
+
+    unsafe { &__binary_start as *const _ as usize..&__loader_end as *const _ as usize }
+}
+
+/// The RAM set aside for the loader's heap.
+#[cfg(target_os = "none")]
+pub fn heap_region() -> Range<usize> {
+    extern "C" {
+        static __heap_start: usize;
+        static __heap_end: usize;
+    }
+
+    unsafe { &__heap_start as *const _ as usize..&__heap_end as *const _ as usize }
+}
+
+/// The boot core's stack, which grows downwards from its end.
+#[cfg(target_os = "none")]
+pub fn boot_stack() -> Range<usize> {
+    extern "C" {
+        static __boot_stack_start: usize;
+        static __boot_stack_end: usize;
+    }
+
+    unsafe { &__boot_stack_start as *const _ as usize..&__boot_stack_end as *const _ as usize }
+}
+
+/// The page below the boot core's stack, which stays unmapped.
+#[cfg(target_os = "none")]
+pub fn boot_stack_guard() -> Range<usize> {
+    extern "C" {
+        static __boot_stack_guard_start: usize;
+    }
+
+    let start: usize = unsafe { &__boot_stack_guard_start as *const _ as _ };
+
+    start..boot_stack().start
+}
+
+/// Where the boot info for the payload goes.
+pub fn boot_info_region() -> Range<usize> {
+    map::BOOT_INFO_START..map::BOOT_INFO_END
+}
+
+/// Fill the loader's memory map with the RAM and everything in it that the loader knows of.
+///
+/// The RAM is what the device tree at `dtb_addr` lists. Without one, all that is known for sure is
+/// that the RAM reaches up to the end of the loader.
+///
+/// Every region is reserved, even after one could not be. `failed` is called with the name of each
+/// region that could not be, and why. The boot info comes last and is left out without a failure if
+/// anything else is in its way, so that payloads go without it instead.
+#[cfg(target_os = "none")]
+pub fn init_memory_map(
+    dtb_addr: usize,
+    mut failed: impl FnMut(&'static str, crate::memory::map::Error),
+) {
+    use crate::memory::map::{self as memory_map, RegionKind};
+
+    extern "C" {
+        static __binary_start: usize;
+        static __binary_end: usize;
+        static __core_stacks_start: usize;
+        static __core_stacks_end: usize;
+        static __exception_stacks_start: usize;
+        static __exception_stacks_end: usize;
     }
+
+    let (binary, core_stacks, exception_stacks) = unsafe {
+        (
+            &__binary_start as *const _ as usize..&__binary_end as *const _ as usize,
+            &__core_stacks_start as *const _ as usize..&__core_stacks_end as *const _ as usize,
+            &__exception_stacks_start as *const _ as usize
+                ..&__exception_stacks_end as *const _ as usize,
+        )
+    };
+    let ram = device_tree_ram(dtb_addr).unwrap_or(0..loader_range().end);
+    let dtb = match unsafe { device_tree(dtb_addr) } {
+        Some(blob) => dtb_addr..dtb_addr + blob.len(),
+        None => 0..0,
+    };
+
+    #[rustfmt::skip]
+    let regions = [
+        ("ARM stubs and spin-table", RegionKind::Firmware,   0..map::FIRMWARE_END),
+        ("Loader",                   RegionKind::Loader,     binary),
+        ("Core stacks",              RegionKind::Loader,     core_stacks),
+        ("Exception stacks",         RegionKind::Loader,     exception_stacks),
+        ("Heap",                     RegionKind::Loader,     heap_region()),
+        ("Boot core stack",          RegionKind::Loader,     boot_stack_guard().start..boot_stack().end),
+        ("VideoCore",                RegionKind::VideoCore,  ram.end..map::VIDEOCORE_END),
+        ("Device MMIO",              RegionKind::Mmio,       map::mmio::BASE..map::mmio::END_INCLUSIVE + 1),
+        ("Device tree",              RegionKind::DeviceTree, dtb),
+    ];
+
+    memory_map::kernel_map().lock(|kernel_map| {
+        kernel_map.reset(ram.clone());
+
+        for (name, kind, range) in regions.iter().cloned() {
+            if let Err(e) = kernel_map.reserve(name, kind, range) {
+                failed(name, e);
+            }
+        }
+
+        let _ = kernel_map.reserve("Boot info", RegionKind::BootInfo, boot_info_region());
+    })
 }
@@ -0,0 +1,44 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2018-2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! BSP Memory Management Unit.
+
+use super::map as memory_map;
+use crate::memory::mmu::*;
+use core::ops::RangeInclusive;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The address space is identity mapped, and everything that is not listed here is DRAM.
+pub static LAYOUT: KernelVirtualLayout = KernelVirtualLayout::new(
+    memory_map::END_INCLUSIVE,
+    &[RangeDescriptor {
+        name: "Device MMIO",
+        virtual_range: || {
+            RangeInclusive::new(memory_map::mmio::BASE, memory_map::mmio::END_INCLUSIVE)
+        },
+        attribute_fields: AttributeFields {
+            mem_attributes: MemAttributes::Device,
+            acc_perms: AccessPermissions::ReadWrite,
+            execute_never: true,
+        },
+    }],
+);
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Return the address space configuration.
+pub fn virt_mem_layout() -> &'static KernelVirtualLayout {
+    &LAYOUT
+}
+
+/// The page that is left unmapped, so that overflowing the boot core's stack faults.
+#[cfg(target_os = "none")]
+pub fn guard_page() -> usize {
+    super::boot_stack_guard().start
+}
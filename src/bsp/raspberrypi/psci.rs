@@ -0,0 +1,46 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! BSP Power State Coordination Interface.
+
+use crate::{cpu, psci};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Resets and halts through the watchdog.
+struct SystemPower;
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static SYSTEM_POWER: SystemPower = SystemPower;
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Return a reference to the system power control.
+pub fn system_power() -> &'static impl psci::interface::SystemPower {
+    &SYSTEM_POWER
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl psci::interface::SystemPower for SystemPower {
+    fn system_off(&self) -> ! {
+        // There is no power switch. The firmware halts after the reset instead.
+        super::WATCHDOG.halt();
+        cpu::wait_forever()
+    }
+
+    fn system_reset(&self) -> ! {
+        super::WATCHDOG.reset();
+        cpu::wait_forever()
+    }
+}
@@ -4,9 +4,15 @@
 
 //! Processor code.
 
-#[cfg(target_arch = "aarch64")]
+#[cfg(all(target_arch = "aarch64", target_os = "none"))]
 #[path = "_arch/aarch64/cpu.rs"]
 mod arch_cpu;
+
+#[cfg(not(target_os = "none"))]
+#[path = "_arch/host/cpu.rs"]
+mod arch_cpu;
+
 pub use arch_cpu::*;
 
+pub mod cache;
 pub mod smp;
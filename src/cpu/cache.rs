@@ -0,0 +1,20 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Cache maintenance.
+//!
+//! Code that was written through the data cache must be cleaned to the Point of Coherence, and the
+//! instruction cache invalidated, before it can be executed. The same goes for anything that is
+//! handed over to an observer that does not look into the caches, like a payload that starts with
+//! its caches off.
+
+#[cfg(all(target_arch = "aarch64", target_os = "none"))]
+#[path = "../_arch/aarch64/cpu/cache.rs"]
+mod arch_cpu_cache;
+
+#[cfg(not(target_os = "none"))]
+#[path = "../_arch/host/cpu/cache.rs"]
+mod arch_cpu_cache;
+
+pub use arch_cpu_cache::*;
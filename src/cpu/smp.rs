@@ -1,6 +1,11 @@
 //! Symmetric multiprocessing.
 
-#[cfg(target_arch = "aarch64")]
+#[cfg(all(target_arch = "aarch64", target_os = "none"))]
 #[path = "../_arch/aarch64/cpu/smp.rs"]
 mod arch_cpu_smp;
+
+#[cfg(not(target_os = "none"))]
+#[path = "../_arch/host/cpu/smp.rs"]
+mod arch_cpu_smp;
+
 pub use arch_cpu_smp::*;
\ No newline at end of file
@@ -0,0 +1,15 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Asynchronous exception handling.
+
+#[cfg(all(target_arch = "aarch64", target_os = "none"))]
+#[path = "../_arch/aarch64/exception/asynchronous.rs"]
+mod arch_exception_async;
+
+#[cfg(not(target_os = "none"))]
+#[path = "../_arch/host/exception/asynchronous.rs"]
+mod arch_exception_async;
+
+pub use arch_exception_async::*;
@@ -0,0 +1,103 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Thin hypervisor that keeps the loader resident while a payload runs.
+//!
+//! With `loader::protocol::FLAG_GUARD_LOADER`, the payload runs at EL1 under a stage 2 translation
+//! that leaves out the loader (see `memory::mmu::interface::Stage2`). The loader's request loop
+//! takes over again when the payload
+//!
+//! - accesses the loader, which is reported as a stage 2 fault,
+//! - executes `hvc #RECALL_HVC_IMMEDIATE`, or
+//! - the host sends a break on the console UART, where the `BSP` supports it.
+//!
+//! The stage 2 translation is then revoked for all cores, so that they trap on their next memory
+//! access. The loader resumes on the boot core, even if another core recalled it, and the cores the
+//! payload started through PSCI park. A core that sleeps in `wfi` for good stays where it is.
+
+#[cfg(all(target_arch = "aarch64", target_os = "none"))]
+#[path = "_arch/aarch64/hypervisor.rs"]
+mod arch_hypervisor;
+
+#[cfg(all(target_arch = "aarch64", target_os = "none"))]
+pub use arch_hypervisor::*;
+
+use core::fmt;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The immediate of the `hvc` with which a payload hands control back to the loader. "ML" in
+/// ASCII.
+pub const RECALL_HVC_IMMEDIATE: u16 = 0x4d4c;
+
+/// The kind of access that faulted.
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AccessKind {
+    InstructionFetch,
+    Read,
+    Write,
+}
+
+/// Why the payload gave control back to the loader.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExitReason {
+    /// The payload executed `hvc #RECALL_HVC_IMMEDIATE`.
+    HypervisorCall,
+
+    /// The host sent a break on the console UART.
+    UartBreak,
+
+    /// The payload accessed memory that it has no access to.
+    Stage2Fault {
+        /// The core that faulted.
+        core_id: usize,
+
+        /// Where the faulting instruction is, as seen by the payload.
+        pc: u64,
+
+        /// The physical address that was accessed.
+        addr: u64,
+
+        /// What the access was.
+        kind: AccessKind,
+    },
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Display for AccessKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            AccessKind::InstructionFetch => "Instruction fetch",
+            AccessKind::Read => "Read",
+            AccessKind::Write => "Write",
+        };
+
+        f.write_str(s)
+    }
+}
+
+impl fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitReason::HypervisorCall => write!(f, "Recalled by the payload"),
+            ExitReason::UartBreak => write!(f, "Break on the console"),
+            ExitReason::Stage2Fault {
+                core_id,
+                pc,
+                addr,
+                kind,
+            } => write!(
+                f,
+                "Stage 2 fault on core {}: {} of {:#x} at pc {:#x}",
+                core_id, kind, addr, pc
+            ),
+        }
+    }
+}
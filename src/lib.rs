@@ -0,0 +1,210 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2018-2020 Andre Richter <andre.o.richter@gmail.com>
+
+// Rust embedded logo for `make doc`.
+#![doc(html_logo_url = "https://git.io/JeGIp")]
+
+//! The `kernel` library.
+//!
+//! Used by `main.rs` to compose the final kernel binary.
+//!
+//! # Code organization and architecture
+//!
+//! The code is divided into different *modules*, each representing a typical **subsystem** of the
+//! `kernel`. Top-level module files of subsystems reside directly in the `src` folder. For example,
+//! `src/memory.rs` contains code that is concerned with all things memory management.
+//!
+//! ## Visibility of processor architecture code
+//!
+//! Some of the `kernel`'s subsystems depend on low-level code that is specific to the target
+//! processor architecture. For each supported processor architecture, there exists a subfolder in
+//! `src/_arch`, for example, `src/_arch/aarch64`.
+//!
+//! The architecture folders mirror the subsystem modules laid out in `src`. For example,
+//! architectural code that belongs to the `kernel`'s memory subsystem (`src/memory.rs`) would go
+//! into `src/_arch/aarch64/memory.rs`. The latter file is directly included and re-exported in
+//! `src/memory.rs`, so that the architectural code parts are transparent with respect to the code's
+//! module organization. That means a public function `foo()` defined in
+//! `src/_arch/aarch64/memory.rs` would be reachable as `crate::memory::foo()` only.
+//!
+//! The `_` in `_arch` denotes that this folder is not part of the standard module hierarchy.
+//! Rather, it's contents are conditionally pulled into respective files using the `#[path =
+//! "_arch/xxx/yyy.rs"]` attribute.
+//!
+//! ## BSP code
+//!
+//! `BSP` stands for Board Support Package. `BSP` code is organized under `src/bsp.rs` and contains
+//! target board specific definitions and functions. These are things such as the board's memory map
+//! or instances of drivers for devices that are featured on the respective board.
+//!
+//! Just like processor architecture code, the `BSP` code's module structure tries to mirror the
+//! `kernel`'s subsystem modules, but there is no transparent re-exporting this time. That means
+//! whatever is provided must be called starting from the `bsp` namespace, e.g.
+//! `bsp::driver::driver_manager()`.
+//!
+//! ## Kernel interfaces
+//!
+//! Both `arch` and `bsp` contain code that is conditionally compiled depending on the actual target
+//! and board for which the kernel is compiled. For example, the `interrupt controller` hardware of
+//! the `Raspberry Pi 3` and the `Raspberry Pi 4` is different, but we want the rest of the `kernel`
+//! code to play nicely with any of the two without much hassle.
+//!
+//! In order to provide a clean abstraction between `arch`, `bsp` and `generic kernel code`,
+//! `interface` traits are provided *whenever possible* and *where it makes sense*. They are defined
+//! in the respective subsystem module and help to enforce the idiom of *program to an interface,
+//! not an implementation*. For example, there will be a common IRQ handling interface which the two
+//! different interrupt controller `drivers` of both Raspberrys will implement, and only export the
+//! interface to the rest of the `kernel`.
+//!
+//! ```
+//!         +-------------------+
+//!         | Interface (Trait) |
+//!         |                   |
+//!         +--+-------------+--+
+//!            ^             ^
+//!            |             |
+//!            |             |
+//! +----------+--+       +--+----------+
+//! | kernel code |       |  bsp code   |
+//! |             |       |  arch code  |
+//! +-------------+       +-------------+
+//! ```
+//!
+//! # Summary
+//!
+//! For a logical `kernel` subsystem, corresponding code can be distributed over several physical
+//! locations. Here is an example for the **memory** subsystem:
+//!
+//! - `src/memory.rs` and `src/memory/**/*`
+//!   - Common code that is agnostic of target processor architecture and `BSP` characteristics.
+//!     - Example: A function to zero a chunk of memory.
+//!   - Interfaces for the memory subsystem that are implemented by `arch` or `BSP` code.
+//!     - Example: An `MMU` interface that defines `MMU` function prototypes.
+//! - `src/bsp/__board_name__/memory.rs` and `src/bsp/__board_name__/memory/**/*`
+//!   - `BSP` specific code.
+//!   - Example: The board's memory map (physical addresses of DRAM and MMIO devices).
+//! - `src/_arch/__arch_name__/memory.rs` and `src/_arch/__arch_name__/memory/**/*`
+//!   - Processor architecture specific code.
+//!   - Example: Implementation of the `MMU` interface for the `__arch_name__` processor
+//!     architecture.
+//!
+//! From a namespace perspective, **memory** subsystem code lives in:
+//!
+//! - `crate::memory::*`
+//! - `crate::bsp::memory::*`
+//!
+//! # Host builds
+//!
+//! For unit testing drivers and other hardware-independent code, the library can also be compiled
+//! for the host (`make test_host`). In that case, `src/_arch/host` stands in for the processor
+//! architecture, MMIO registers are simulated (see `bsp::device_driver::common`), and code that
+//! only makes sense on the board, such as the boot and relocation code, is left out.
+
+#![feature(alloc_error_handler)]
+#![feature(custom_test_frameworks)]
+#![feature(format_args_nl)]
+#![feature(global_asm)]
+#![feature(llvm_asm)]
+#![feature(panic_info_message)]
+#![feature(trait_alias)]
+#![cfg_attr(target_os = "none", no_std)]
+// Testing
+#![cfg_attr(all(test, target_os = "none"), no_main)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(crate::test_runner)]
+
+extern crate alloc;
+
+// `mod cpu` provides the `_start()` function, the first function to run. `_start()` sets up the
+// stack and continues in `_start_rust()`, which calls `relocate_self()`, which continues in the
+// relocated `runtime_init()`, which jumps to `kernel_init()` (defined in `main.rs`).
+// `mod hypervisor` jumps to `loader_resume()` (defined in `main.rs` as well) when a guarded payload
+// hands control back.
+
+#[cfg(target_os = "none")]
+mod panic_wait;
+#[cfg(target_os = "none")]
+mod relocate;
+#[cfg(target_os = "none")]
+mod runtime_init;
+mod synchronization;
+
+pub mod boot_info;
+pub mod bsp;
+pub mod console;
+pub mod cpu;
+pub mod driver;
+pub mod exception;
+pub mod hypervisor;
+pub mod loader;
+pub mod memory;
+pub mod print;
+pub mod psci;
+#[cfg(target_os = "none")]
+pub mod semihosting;
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+/// A test that can be run by `test_runner()`.
+///
+/// Implemented for every plain function, so that `#[test_case]` can be put on a `fn()` and the
+/// function's name is printed alongside the result.
+pub trait Testable {
+    /// Run the test and report the result over the console.
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    fn run(&self) {
+        print!("{}...", core::any::type_name::<T>());
+        self();
+        println!("[ok]");
+    }
+}
+
+/// The default runner for unit tests.
+///
+/// A failing test panics, and the panic handler exits QEMU with a failure code, so reaching the end
+/// of this function means that all tests passed.
+pub fn test_runner(tests: &[&dyn Testable]) {
+    println!("Running {} tests", tests.len());
+    println!("-------------------------------------------------------------------\n");
+    for (i, test) in tests.iter().enumerate() {
+        print!("{:>3}. ", i + 1);
+        test.run();
+    }
+
+    println!("\n-------------------------------------------------------------------");
+    println!("Test result: ok. {} passed", tests.len());
+}
+
+/// The `kernel_init()` for unit tests. Called from `runtime_init()`.
+#[cfg(all(test, target_os = "none"))]
+#[no_mangle]
+unsafe fn kernel_init(_dtb_addr: usize) -> ! {
+    use memory::mmu::interface::MMU;
+
+    exception::handling_init();
+
+    if let Err(string) = memory::mmu::mmu().init() {
+        panic!("MMU: {}", string);
+    }
+
+    memory::heap::kernel_heap_allocator().init(bsp::memory::heap_region());
+
+    bsp::console::qemu_bring_up_console();
+
+    test_main();
+
+    semihosting::exit_success()
+}
+
+/// The `loader_resume()` for unit tests. There is never a payload to come back from.
+#[cfg(all(test, target_os = "none"))]
+#[no_mangle]
+unsafe extern "C" fn loader_resume() -> ! {
+    unreachable!()
+}
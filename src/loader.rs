@@ -0,0 +1,19 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! The loader.
+//!
+//! Everything that interprets bytes coming in over the wire lives here, separated from the code that
+//! actually touches memory. All submodules are pure functions of their input and therefore run on
+//! the host as well, which is what the fuzz targets in `fuzz/` rely on.
+//!
+//! - `protocol`: Turns the byte stream from `Minipush` into stores into a staging area.
+//! - `image`: Finds out what the staged payload is and where its parts have to go.
+//! - `fdt`: Points the device tree that is forwarded to the payload at the loader's spin-table.
+//! - `crc32`: Computes the digest of a received payload.
+
+pub mod crc32;
+pub mod fdt;
+pub mod image;
+pub mod protocol;
@@ -0,0 +1,132 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! CRC-32.
+//!
+//! The checksum of zlib, Ethernet and `crc32(1)`, so that a payload's digest can be compared with
+//! what the host computes for the file. On the board, the ARMv8 CRC32 instructions take 8 bytes at a
+//! time. Elsewhere, and as the reference in the tests, it works on a nibble at a time, which needs
+//! no more than a 16 entry table.
+
+// -------------------------------------------------------------------------------------------------
+// Private definitions
+// -------------------------------------------------------------------------------------------------
+
+/// The CRC of every nibble, for the reflected polynomial `0xedb8_8320`.
+#[cfg(any(test, not(all(target_arch = "aarch64", target_os = "none"))))]
+#[rustfmt::skip]
+const TABLE: [u32; 16] = [
+    0x0000_0000, 0x1db7_1064, 0x3b6e_20c8, 0x26d9_30ac, 0x76dc_4190, 0x6b6b_51f4, 0x4db2_6158,
+    0x5005_713c, 0xedb8_8320, 0xf00f_9344, 0xd6d6_a3e8, 0xcb61_b38c, 0x9b64_c2b0, 0x86d3_d2d4,
+    0xa00a_e278, 0xbdbd_f21c,
+];
+
+// -------------------------------------------------------------------------------------------------
+// Private code
+// -------------------------------------------------------------------------------------------------
+
+/// Continue `crc` over `data`, a nibble at a time.
+#[cfg(any(test, not(all(target_arch = "aarch64", target_os = "none"))))]
+fn update_table(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= u32::from(byte);
+        crc = (crc >> 4) ^ TABLE[(crc & 0xf) as usize];
+        crc = (crc >> 4) ^ TABLE[(crc & 0xf) as usize];
+    }
+
+    crc
+}
+
+/// Continue `crc` over `data` with the CRC32 instructions, a doubleword at a time where `data` is
+/// aligned, a byte at a time elsewhere.
+#[cfg(all(target_arch = "aarch64", target_os = "none"))]
+fn update(mut crc: u32, data: &[u8]) -> u32 {
+    #[inline(always)]
+    fn crc32b(crc: u32, byte: u8) -> u32 {
+        let result: u32;
+        unsafe {
+            llvm_asm!(".arch_extension crc
+                       crc32b ${0:w}, ${1:w}, ${2:w}"
+                : "=r"(result) : "r"(crc), "r"(u32::from(byte)))
+        };
+
+        result
+    }
+
+    #[inline(always)]
+    fn crc32x(crc: u32, doubleword: u64) -> u32 {
+        let result: u32;
+        unsafe {
+            llvm_asm!(".arch_extension crc
+                       crc32x ${0:w}, ${1:w}, $2"
+                : "=r"(result) : "r"(crc), "r"(doubleword))
+        };
+
+        result
+    }
+
+    // Any bit pattern is a valid u64.
+    let (head, doublewords, tail) = unsafe { data.align_to::<u64>() };
+
+    for &byte in head {
+        crc = crc32b(crc, byte);
+    }
+    // Little endian, so the first byte is the lowest, which the instruction takes first.
+    for &doubleword in doublewords {
+        crc = crc32x(crc, doubleword);
+    }
+    for &byte in tail {
+        crc = crc32b(crc, byte);
+    }
+
+    crc
+}
+
+#[cfg(not(all(target_arch = "aarch64", target_os = "none")))]
+use update_table as update;
+
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
+/// The CRC-32 of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    !update(!0, data)
+}
+
+// -------------------------------------------------------------------------------------------------
+// Testing
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The check values of the CRC catalogue, and of the empty input.
+    #[test_case]
+    fn check_values() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog"),
+            0x414f_a339
+        );
+    }
+
+    /// `crc32()` agrees with the table at every alignment and length around a doubleword.
+    #[test_case]
+    fn unaligned_data() {
+        let mut data = [0u8; 64];
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = (i * 37) as u8;
+        }
+
+        for start in 0..8 {
+            for end in start..start + 24 {
+                let slice = &data[start..end];
+                assert_eq!(crc32(slice), !update_table(!0, slice));
+            }
+        }
+    }
+}
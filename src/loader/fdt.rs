@@ -0,0 +1,756 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Flattened device trees.
+//!
+//! The loader hardly interprets the device tree that the firmware hands over, it mostly forwards it
+//! to the payload. The exceptions are:
+//!
+//! - The `cpu-release-addr` properties of the cpu nodes, which have to point to the loader's
+//!   spin-table. They are patched in place, so the blob never changes its size.
+//! - The `/memory` node, which tells where the loader can relocate itself to.
+//! - The `bootargs` of the `/chosen` node, which the payload finds in the boot info as well.
+
+use core::{convert::TryInto, fmt, ops::Range};
+
+// -------------------------------------------------------------------------------------------------
+// Public definitions
+// -------------------------------------------------------------------------------------------------
+
+/// The size of the part of the header that `total_size()` needs.
+pub const HEADER_PREFIX_SIZE: usize = 8;
+
+/// Why a device tree was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The blob is not a valid flattened device tree.
+    Malformed(&'static str),
+}
+
+// -------------------------------------------------------------------------------------------------
+// Private definitions
+// -------------------------------------------------------------------------------------------------
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const HEADER_SIZE: usize = 40;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+/// How deep the names of the nodes on the path to the current one are kept track of.
+const MAX_DEPTH: usize = 16;
+
+/// A token of the structure block. Names and values are ranges within the blob, so that the blob
+/// can be changed in between tokens.
+enum Token {
+    /// A node starts. It is the current node now.
+    BeginNode,
+
+    /// The current node ends. Its parent is the current node again.
+    EndNode,
+
+    /// A property of the current node.
+    Prop {
+        name: Range<usize>,
+        value: Range<usize>,
+    },
+}
+
+/// Walks the structure block one token at a time, keeping track of the path to the current node.
+struct Walker {
+    structure: Range<usize>,
+    strings: Range<usize>,
+    offset: usize,
+    depth: usize,
+
+    /// The names of the nodes from the root node down to the current one, up to `MAX_DEPTH`.
+    path: [Range<usize>; MAX_DEPTH],
+}
+
+/// What was found out about the node whose properties are being walked.
+#[derive(Default)]
+struct Node {
+    /// The first address of the `reg` property.
+    reg: Option<u64>,
+
+    /// Where the value of the `cpu-release-addr` property is.
+    release_addr: Option<Range<usize>>,
+}
+
+// -------------------------------------------------------------------------------------------------
+// Private code
+// -------------------------------------------------------------------------------------------------
+
+fn read_u32(blob: &[u8], offset: usize) -> Result<u32, Error> {
+    let bytes = offset
+        .checked_add(4)
+        .and_then(|end| blob.get(offset..end))
+        .ok_or(Error::Malformed("Truncated"))?;
+
+    Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+/// A cell-encoded value of one or two cells.
+fn read_cells(value: &[u8]) -> Option<u64> {
+    match value.len() {
+        4 => Some(u64::from(u32::from_be_bytes(value.try_into().unwrap()))),
+        8 => Some(u64::from_be_bytes(value.try_into().unwrap())),
+        _ => None,
+    }
+}
+
+/// The block of `size` bytes at `offset`, if it lies within `blob`.
+fn block(blob: &[u8], offset: usize, size: usize) -> Result<Range<usize>, Error> {
+    let end = offset
+        .checked_add(size)
+        .ok_or(Error::Malformed("Block exceeds the address space"))?;
+
+    if end > blob.len() {
+        return Err(Error::Malformed("Block outside of the blob"));
+    }
+
+    Ok(offset..end)
+}
+
+fn align4(offset: usize) -> Result<usize, Error> {
+    offset
+        .checked_add(3)
+        .map(|o| o & !3)
+        .ok_or(Error::Malformed("Offset exceeds the address space"))
+}
+
+/// The NUL terminated string at `offset` within `block`.
+fn string_at<'a>(blob: &'a [u8], block: &Range<usize>, offset: usize) -> Result<&'a [u8], Error> {
+    let start = block
+        .start
+        .checked_add(offset)
+        .filter(|start| *start < block.end)
+        .ok_or(Error::Malformed("String outside of its block"))?;
+
+    let len = blob[start..block.end]
+        .iter()
+        .position(|c| *c == 0)
+        .ok_or(Error::Malformed("Unterminated string"))?;
+
+    Ok(&blob[start..start + len])
+}
+
+/// The structure and strings blocks of `blob`.
+fn blocks(blob: &[u8]) -> Result<(Range<usize>, Range<usize>), Error> {
+    if blob.len() < HEADER_SIZE || total_size(blob)? > blob.len() {
+        return Err(Error::Malformed("Truncated"));
+    }
+
+    let structure = block(
+        blob,
+        read_u32(blob, 8)? as usize,
+        read_u32(blob, 36)? as usize,
+    )?;
+    let strings = block(
+        blob,
+        read_u32(blob, 12)? as usize,
+        read_u32(blob, 32)? as usize,
+    )?;
+
+    Ok((structure, strings))
+}
+
+/// The first address and size of a `reg` property with the given cell counts.
+fn read_reg(value: &[u8], address_cells: u32, size_cells: u32) -> Option<(u64, u64)> {
+    let address_len = address_cells as usize * 4;
+    let size_len = size_cells as usize * 4;
+
+    let address = read_cells(value.get(..address_len)?)?;
+    let size = read_cells(value.get(address_len..address_len + size_len)?)?;
+
+    Some((address, size))
+}
+
+impl Walker {
+    fn new(blob: &[u8]) -> Result<Self, Error> {
+        let (structure, strings) = blocks(blob)?;
+
+        Ok(Self {
+            offset: structure.start,
+            structure,
+            strings,
+            depth: 0,
+            path: Default::default(),
+        })
+    }
+
+    /// The next token of `blob`, which must be the blob the walker was created for, or `None` once
+    /// the structure block ended. Nodes that do not end, or end once too often, are malformed.
+    fn next(&mut self, blob: &[u8]) -> Result<Option<Token>, Error> {
+        let structure = &blob[..self.structure.end];
+
+        loop {
+            if self.offset >= self.structure.end {
+                return Err(Error::Malformed("Missing end token"));
+            }
+
+            let token = read_u32(structure, self.offset)?;
+            self.offset += 4;
+
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name =
+                        string_at(blob, &self.structure, self.offset - self.structure.start)?;
+                    let name = self.offset..self.offset + name.len();
+                    self.offset = align4(name.end + 1)?;
+
+                    if let Some(entry) = self.path.get_mut(self.depth) {
+                        *entry = name;
+                    }
+                    self.depth += 1;
+
+                    return Ok(Some(Token::BeginNode));
+                }
+                FDT_END_NODE => {
+                    self.depth = self
+                        .depth
+                        .checked_sub(1)
+                        .ok_or(Error::Malformed("Unbalanced end of node"))?;
+
+                    return Ok(Some(Token::EndNode));
+                }
+                FDT_PROP => {
+                    let len = read_u32(structure, self.offset)? as usize;
+                    let name_offset = read_u32(structure, self.offset + 4)? as usize;
+
+                    let value = block(structure, self.offset + 8, len)?;
+                    let name = string_at(blob, &self.strings, name_offset)?;
+                    let name = self.strings.start + name_offset
+                        ..self.strings.start + name_offset + name.len();
+                    self.offset = align4(value.end)?;
+
+                    return Ok(Some(Token::Prop { name, value }));
+                }
+                FDT_NOP => (),
+                FDT_END if self.depth == 0 => return Ok(None),
+                FDT_END => return Err(Error::Malformed("Unterminated node")),
+                _ => return Err(Error::Malformed("Unknown token")),
+            }
+        }
+    }
+
+    /// The depth of the current node: 1 for the root node, 2 for its children, 0 outside of it.
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The name of the current node, unless it is nested deeper than `MAX_DEPTH` or there is none.
+    fn node(&self) -> Option<Range<usize>> {
+        self.depth
+            .checked_sub(1)
+            .and_then(|i| self.path.get(i))
+            .cloned()
+    }
+
+    /// Check if the current node is a child of the root node, and `matches()` its name.
+    fn in_root_child(&self, blob: &[u8], matches: impl Fn(&[u8]) -> bool) -> bool {
+        match self.node() {
+            Some(name) if self.depth == 2 => matches(&blob[name]),
+            _ => false,
+        }
+    }
+}
+
+impl Node {
+    /// Patch the node's `cpu-release-addr`, if it has one. Returns the number of patched
+    /// properties.
+    fn finish<F>(self, blob: &mut [u8], release_addr: &F) -> usize
+    where
+        F: Fn(u64) -> Option<u64>,
+    {
+        let (reg, value) = match (self.reg, self.release_addr) {
+            (Some(reg), Some(value)) => (reg, value),
+            _ => return 0,
+        };
+
+        let addr = match release_addr(reg) {
+            Some(addr) => addr,
+            None => return 0,
+        };
+
+        match value.len() {
+            4 if addr <= u64::from(u32::MAX) => {
+                blob[value].copy_from_slice(&(addr as u32).to_be_bytes())
+            }
+            8 => blob[value].copy_from_slice(&addr.to_be_bytes()),
+            _ => return 0,
+        }
+
+        1
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Malformed(reason) => write!(f, "Malformed device tree: {}", reason),
+        }
+    }
+}
+
+/// The size of the device tree that starts with `header`, which needs to be at least
+/// `HEADER_PREFIX_SIZE` bytes.
+pub fn total_size(header: &[u8]) -> Result<usize, Error> {
+    if read_u32(header, 0)? != FDT_MAGIC {
+        return Err(Error::Malformed("Bad magic"));
+    }
+
+    Ok(read_u32(header, 4)? as usize)
+}
+
+/// Point the `cpu-release-addr` property of every node to `release_addr(reg)`, where `reg` is the
+/// first address in the node's `reg` property. Nodes for which `release_addr` returns `None` are
+/// left alone.
+///
+/// Returns the number of patched properties.
+pub fn patch_cpu_release_addrs<F>(blob: &mut [u8], release_addr: F) -> Result<usize, Error>
+where
+    F: Fn(u64) -> Option<u64>,
+{
+    let mut walker = Walker::new(blob)?;
+    let mut node = Node::default();
+    let mut patched = 0;
+
+    while let Some(token) = walker.next(blob)? {
+        match token {
+            // Properties precede the child nodes, so a node is complete once either starts.
+            Token::BeginNode | Token::EndNode => {
+                patched += core::mem::take(&mut node).finish(blob, &release_addr)
+            }
+            Token::Prop { name, value } => match &blob[name] {
+                b"reg" => node.reg = read_cells(&blob[value]),
+                b"cpu-release-addr" => node.release_addr = Some(value),
+                _ => (),
+            },
+        }
+    }
+
+    Ok(patched)
+}
+
+/// The first range of RAM that the `/memory` node lists, if there is one.
+pub fn memory_range(blob: &[u8]) -> Result<Option<Range<u64>>, Error> {
+    let mut walker = Walker::new(blob)?;
+
+    // The cell counts of the root node apply to its children. These are the defaults.
+    let mut address_cells = 2;
+    let mut size_cells = 1;
+
+    while let Some(token) = walker.next(blob)? {
+        let (name, value) = match token {
+            Token::Prop { name, value } => (&blob[name], &blob[value]),
+            _ => continue,
+        };
+        let in_memory_node = walker.in_root_child(blob, |node| {
+            node == b"memory" || node.starts_with(b"memory@")
+        });
+
+        match (walker.depth(), name) {
+            (1, b"#address-cells") => address_cells = read_u32(value, 0)?,
+            (1, b"#size-cells") => size_cells = read_u32(value, 0)?,
+            (2, b"reg") if in_memory_node => {
+                let (address, size) = read_reg(value, address_cells, size_cells)
+                    .ok_or(Error::Malformed("Bad memory reg"))?;
+                let end = address
+                    .checked_add(size)
+                    .ok_or(Error::Malformed("Memory exceeds the address space"))?;
+
+                return Ok(Some(address..end));
+            }
+            _ => (),
+        }
+    }
+
+    Ok(None)
+}
+
+/// The `bootargs` property of the `/chosen` node, up to its terminating NUL, if there is one.
+pub fn bootargs(blob: &[u8]) -> Result<Option<&[u8]>, Error> {
+    let mut walker = Walker::new(blob)?;
+
+    while let Some(token) = walker.next(blob)? {
+        if let Token::Prop { name, value } = token {
+            if &blob[name] == b"bootargs" && walker.in_root_child(blob, |node| node == b"chosen") {
+                let value = &blob[value];
+                let len = value.iter().position(|&c| c == 0).unwrap_or(value.len());
+
+                return Ok(Some(&value[..len]));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+// -------------------------------------------------------------------------------------------------
+// Testing
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// Builds device trees token by token.
+    struct Builder {
+        structure: Vec<u8>,
+        strings: Vec<u8>,
+    }
+
+    impl Builder {
+        fn new() -> Self {
+            Self {
+                structure: Vec::new(),
+                strings: Vec::new(),
+            }
+        }
+
+        fn token(mut self, token: u32) -> Self {
+            self.structure.extend_from_slice(&token.to_be_bytes());
+            self
+        }
+
+        fn pad(&mut self) {
+            let len = align4(self.structure.len()).unwrap();
+            self.structure.resize(len, 0);
+        }
+
+        fn begin(mut self, name: &str) -> Self {
+            self = self.token(FDT_BEGIN_NODE);
+            self.structure.extend_from_slice(name.as_bytes());
+            self.structure.push(0);
+            self.pad();
+            self
+        }
+
+        fn end(self) -> Self {
+            self.token(FDT_END_NODE)
+        }
+
+        fn prop(mut self, name: &str, value: &[u8]) -> Self {
+            let name_offset = self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+
+            self = self.token(FDT_PROP);
+            self.structure
+                .extend_from_slice(&(value.len() as u32).to_be_bytes());
+            self.structure.extend_from_slice(&name_offset.to_be_bytes());
+            self.structure.extend_from_slice(value);
+            self.pad();
+            self
+        }
+
+        /// The blob: The header, an empty memory reservation block, the structure block and the
+        /// strings block.
+        fn finish(self) -> Vec<u8> {
+            let structure = self.token(FDT_END);
+            let off_structure = HEADER_SIZE + 16;
+            let off_strings = off_structure + structure.structure.len();
+            let total_size = off_strings + structure.strings.len();
+
+            let mut blob = Vec::new();
+            for field in &[
+                FDT_MAGIC,
+                total_size as u32,
+                off_structure as u32,
+                off_strings as u32,
+                HEADER_SIZE as u32,
+                17,
+                16,
+                0,
+                structure.strings.len() as u32,
+                structure.structure.len() as u32,
+            ] {
+                blob.extend_from_slice(&field.to_be_bytes());
+            }
+            blob.resize(off_structure, 0);
+            blob.extend_from_slice(&structure.structure);
+            blob.extend_from_slice(&structure.strings);
+
+            blob
+        }
+    }
+
+    fn cells(values: &[u32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|v| v.to_be_bytes().to_vec())
+            .collect()
+    }
+
+    fn release_addr(reg: u64) -> Option<u64> {
+        if reg < 4 {
+            Some(0xd8 + 8 * reg)
+        } else {
+            None
+        }
+    }
+
+    /// Cpu nodes with the given `cpu-release-addr` cells for the cores 0 to 3.
+    fn cpus(release_addrs: [&[u32]; 4]) -> Vec<u8> {
+        let mut builder = Builder::new()
+            .begin("")
+            .begin("cpus")
+            .prop("#address-cells", &cells(&[1]))
+            .prop("#size-cells", &cells(&[0]));
+        for (core, release_addr) in release_addrs.iter().enumerate() {
+            builder = builder
+                .begin("cpu")
+                .prop("reg", &cells(&[core as u32]))
+                .prop("enable-method", b"spin-table\0")
+                .prop("cpu-release-addr", &cells(release_addr))
+                .end();
+        }
+
+        builder.end().end().finish()
+    }
+
+    /// The total size comes from the header, after the magic was checked.
+    #[test_case]
+    fn total_size() {
+        let mut blob = Builder::new().begin("").end().finish();
+
+        assert_eq!(
+            super::total_size(&blob[..HEADER_PREFIX_SIZE]),
+            Ok(blob.len())
+        );
+        assert_eq!(
+            super::total_size(&blob[..4]),
+            Err(Error::Malformed("Truncated"))
+        );
+
+        blob[0] = 0;
+        assert_eq!(super::total_size(&blob), Err(Error::Malformed("Bad magic")));
+    }
+
+    /// Release addresses of one and two cells are patched in place, other sizes and nodes without
+    /// one are left alone.
+    #[test_case]
+    fn release_addrs_are_patched() {
+        let mut blob = cpus([&[0, 0], &[0], &[0, 0, 0], &[]]);
+
+        assert_eq!(patch_cpu_release_addrs(&mut blob, release_addr), Ok(2));
+        assert_eq!(blob, cpus([&[0, 0xd8], &[0xe0], &[0, 0, 0], &[]]));
+    }
+
+    /// A release address of one cell cannot take an address beyond 4 GiB, and cores that
+    /// `release_addr()` does not know are left alone.
+    #[test_case]
+    fn release_addrs_that_do_not_fit_are_left_alone() {
+        let expected = cpus([&[0], &[0, 0], &[0], &[0, 0]]);
+        let mut blob = expected.clone();
+
+        let release_addr = |reg| if reg == 0 { Some(1 << 32) } else { None };
+
+        assert_eq!(patch_cpu_release_addrs(&mut blob, release_addr), Ok(0));
+        assert_eq!(blob, expected);
+    }
+
+    /// A blob that is cut short anywhere is rejected, even if what is left of it looks complete.
+    #[test_case]
+    fn truncated_blobs_are_rejected() {
+        let blob = cpus([&[0, 0], &[0, 0], &[0, 0], &[0, 0]]);
+
+        for len in &[0, HEADER_PREFIX_SIZE, HEADER_SIZE, blob.len() - 1] {
+            let mut truncated = blob[..*len].to_vec();
+
+            assert!(patch_cpu_release_addrs(&mut truncated, release_addr).is_err());
+        }
+
+        // The structure block says it ends before the end token.
+        let mut blob = blob;
+        let size_structure = read_u32(&blob, 36).unwrap() - 4;
+        blob[36..40].copy_from_slice(&size_structure.to_be_bytes());
+
+        assert_eq!(
+            patch_cpu_release_addrs(&mut blob, release_addr),
+            Err(Error::Malformed("Missing end token"))
+        );
+    }
+
+    /// Every node must end, and only once.
+    #[test_case]
+    fn unbalanced_nodes_are_rejected() {
+        let mut blob = Builder::new().begin("").begin("cpus").end().finish();
+        assert_eq!(
+            patch_cpu_release_addrs(&mut blob, release_addr),
+            Err(Error::Malformed("Unterminated node"))
+        );
+
+        let mut blob = Builder::new().begin("").end().end().finish();
+        assert_eq!(
+            patch_cpu_release_addrs(&mut blob, release_addr),
+            Err(Error::Malformed("Unbalanced end of node"))
+        );
+    }
+
+    /// A root node with the given cell counts, and a `/memory@0` node with the given `reg`.
+    fn memory(address_cells: Option<u32>, size_cells: Option<u32>, reg: &[u32]) -> Vec<u8> {
+        let mut builder = Builder::new().begin("");
+        if let Some(address_cells) = address_cells {
+            builder = builder.prop("#address-cells", &cells(&[address_cells]));
+        }
+        if let Some(size_cells) = size_cells {
+            builder = builder.prop("#size-cells", &cells(&[size_cells]));
+        }
+
+        builder
+            .begin("memory@0")
+            .prop("device_type", b"memory\0")
+            .prop("reg", &cells(reg))
+            .end()
+            .end()
+            .finish()
+    }
+
+    /// Without cell counts in the root node, an address takes two cells and a size one.
+    #[test_case]
+    fn memory_range_with_default_cells() {
+        assert_eq!(
+            memory_range(&memory(None, None, &[0, 0x1000, 0x3b40_0000])),
+            Ok(Some(0x1000..0x3b40_1000))
+        );
+    }
+
+    /// The root node's cell counts apply to the `reg` of `/memory`.
+    #[test_case]
+    fn memory_range_with_root_cells() {
+        assert_eq!(
+            memory_range(&memory(Some(1), Some(1), &[0, 0x3b40_0000])),
+            Ok(Some(0..0x3b40_0000))
+        );
+        assert_eq!(
+            memory_range(&memory(Some(2), Some(2), &[1, 0, 0, 0x4000_0000])),
+            Ok(Some(0x1_0000_0000..0x1_4000_0000))
+        );
+        assert_eq!(
+            memory_range(&memory(Some(2), Some(2), &[0, 0x3b40_0000])),
+            Err(Error::Malformed("Bad memory reg"))
+        );
+    }
+
+    /// Only a child of the root node counts as `/memory`.
+    #[test_case]
+    fn memory_range_without_memory_node() {
+        let blob = Builder::new()
+            .begin("")
+            .begin("soc")
+            .begin("memory@0")
+            .prop("reg", &cells(&[0, 0, 0x1000]))
+            .end()
+            .end()
+            .begin("memoryless")
+            .prop("reg", &cells(&[0, 0, 0x1000]))
+            .end()
+            .end()
+            .finish();
+
+        assert_eq!(memory_range(&blob), Ok(None));
+    }
+
+    /// Malformed blobs are rejected instead of being read as having no memory node.
+    #[test_case]
+    fn memory_range_of_malformed_blobs() {
+        let blob = memory(None, None, &[0, 0, 0x1000]);
+        assert_eq!(
+            memory_range(&blob[..blob.len() - 1]),
+            Err(Error::Malformed("Truncated"))
+        );
+
+        let blob = Builder::new().begin("").begin("cpus").end().finish();
+        assert_eq!(
+            memory_range(&blob),
+            Err(Error::Malformed("Unterminated node"))
+        );
+
+        let blob = Builder::new().begin("").end().end().finish();
+        assert_eq!(
+            memory_range(&blob),
+            Err(Error::Malformed("Unbalanced end of node"))
+        );
+    }
+
+    /// A `/chosen` node with the given `bootargs`, after a sibling that has some as well.
+    fn chosen(bootargs: &[u8]) -> Vec<u8> {
+        Builder::new()
+            .begin("")
+            .begin("soc")
+            .begin("chosen")
+            .prop("bootargs", b"nested\0")
+            .end()
+            .end()
+            .begin("chosen")
+            .prop("stdout-path", b"serial0\0")
+            .prop("bootargs", bootargs)
+            .end()
+            .end()
+            .finish()
+    }
+
+    /// The command line ends at the first NUL, or at the end of the property without one.
+    #[test_case]
+    fn bootargs_of_chosen_node() {
+        let console = &b"console=ttyAMA0"[..];
+
+        assert_eq!(bootargs(&chosen(b"console=ttyAMA0\0")), Ok(Some(console)));
+        assert_eq!(bootargs(&chosen(b"console=ttyAMA0")), Ok(Some(console)));
+        assert_eq!(
+            bootargs(&chosen(b"console=ttyAMA0\0quiet\0")),
+            Ok(Some(console))
+        );
+        assert_eq!(bootargs(&chosen(b"")), Ok(Some(&b""[..])));
+    }
+
+    /// Only a child of the root node counts as `/chosen`.
+    #[test_case]
+    fn bootargs_without_chosen_node() {
+        let blob = Builder::new()
+            .begin("")
+            .prop("bootargs", b"root\0")
+            .begin("soc")
+            .begin("chosen")
+            .prop("bootargs", b"nested\0")
+            .end()
+            .end()
+            .begin("chosen")
+            .prop("stdout-path", b"serial0\0")
+            .end()
+            .end()
+            .finish();
+
+        assert_eq!(bootargs(&blob), Ok(None));
+    }
+
+    /// Malformed blobs are rejected instead of being read as having no command line.
+    #[test_case]
+    fn bootargs_of_malformed_blobs() {
+        let blob = chosen(b"console=ttyAMA0\0");
+        assert_eq!(
+            bootargs(&blob[..blob.len() - 1]),
+            Err(Error::Malformed("Truncated"))
+        );
+
+        let blob = Builder::new().begin("").begin("chosen").end().finish();
+        assert_eq!(bootargs(&blob), Err(Error::Malformed("Unterminated node")));
+
+        let blob = Builder::new().begin("").end().end().finish();
+        assert_eq!(
+            bootargs(&blob),
+            Err(Error::Malformed("Unbalanced end of node"))
+        );
+    }
+}
@@ -0,0 +1,600 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Payload image formats.
+//!
+//! Two formats are understood:
+//!
+//! - Raw binaries, as produced by `objcopy -O binary`. They are moved to the load address and
+//!   entered at their first byte. Linux kernel `Image`s are raw binaries as well, but they are told
+//!   apart by the magic in their header. They go where the arm64 boot protocol wants them instead:
+//!   `text_offset` above a 2 MiB aligned base, with `image_size` bytes of memory for them.
+//! - AArch64 ELF64 executables. Their `PT_LOAD` segments are copied to their physical addresses and
+//!   they are entered at `e_entry`. The segments must not overlap each other.
+//!
+//! `parse()` only produces a `LoadPlan`. Every range in it has been checked against the payload
+//! window, so executing the plan with `LoadPlan::load()` cannot write anywhere else.
+
+use core::{convert::TryInto, fmt, ops::Range};
+
+// -------------------------------------------------------------------------------------------------
+// Public definitions
+// -------------------------------------------------------------------------------------------------
+
+/// The maximum number of `PT_LOAD` segments of an ELF payload.
+pub const MAX_SEGMENTS: usize = 8;
+
+/// The payload's format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// A flat binary.
+    Raw,
+
+    /// A flat binary with the header of an AArch64 Linux kernel `Image`.
+    Linux,
+
+    /// An AArch64 ELF64 executable.
+    Elf,
+}
+
+/// A part of the payload that has to be copied.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Segment {
+    /// The bytes to copy, as offsets into the image.
+    pub file: Range<usize>,
+
+    /// Where the segment goes. The bytes past `file.len()` are zeroed.
+    pub dest: Range<usize>,
+}
+
+/// Everything needed to put the payload in place and run it.
+#[derive(Debug)]
+pub struct LoadPlan {
+    /// The payload's format.
+    pub format: Format,
+
+    /// The address to jump to.
+    pub entry: usize,
+
+    segments: [Segment; MAX_SEGMENTS],
+    num_segments: usize,
+}
+
+/// Why a payload was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The image is not what it claims to be.
+    Malformed(&'static str),
+
+    /// The image is valid, but it would have to be put outside of the payload window.
+    OutOfBounds(&'static str),
+}
+
+// -------------------------------------------------------------------------------------------------
+// Private definitions
+// -------------------------------------------------------------------------------------------------
+
+const ELF_MAGIC: [u8; 4] = *b"\x7fELF";
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_AARCH64: u16 = 183;
+const PT_LOAD: u32 = 1;
+
+const LINUX_MAGIC: [u8; 4] = *b"ARM\x64";
+const LINUX_MAGIC_OFFSET: usize = 0x38;
+const LINUX_TEXT_OFFSET_OFFSET: usize = 0x08;
+const LINUX_IMAGE_SIZE_OFFSET: usize = 0x10;
+const LINUX_BASE_ALIGN: usize = 2 * 1024 * 1024;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+
+// -------------------------------------------------------------------------------------------------
+// Private code
+// -------------------------------------------------------------------------------------------------
+
+/// The `len` bytes at `offset`.
+fn bytes(image: &[u8], offset: usize, len: usize) -> Result<&[u8], Error> {
+    image
+        .get(range(offset, len)?)
+        .ok_or(Error::Malformed("Truncated header"))
+}
+
+fn read_u16(image: &[u8], offset: usize) -> Result<u16, Error> {
+    Ok(u16::from_le_bytes(
+        bytes(image, offset, 2)?.try_into().unwrap(),
+    ))
+}
+
+fn read_u32(image: &[u8], offset: usize) -> Result<u32, Error> {
+    Ok(u32::from_le_bytes(
+        bytes(image, offset, 4)?.try_into().unwrap(),
+    ))
+}
+
+fn read_usize(image: &[u8], offset: usize) -> Result<usize, Error> {
+    let value = u64::from_le_bytes(bytes(image, offset, 8)?.try_into().unwrap());
+    if value > usize::MAX as u64 {
+        return Err(Error::Malformed("Value exceeds the address space"));
+    }
+
+    Ok(value as usize)
+}
+
+fn contains(outer: &Range<usize>, inner: &Range<usize>) -> bool {
+    outer.start <= inner.start && inner.end <= outer.end
+}
+
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+/// `start..start + len`, if it does not wrap around.
+fn range(start: usize, len: usize) -> Result<Range<usize>, Error> {
+    let end = start
+        .checked_add(len)
+        .ok_or(Error::Malformed("Range exceeds the address space"))?;
+
+    Ok(start..end)
+}
+
+/// Where the Linux kernel `image` goes, at `min_addr` or above, and how much memory it takes.
+///
+/// That is the lowest address `text_offset` above a 2 MiB aligned base. Kernels before 3.17 leave
+/// `image_size` zero, they only need the image itself.
+fn linux_placement(image: &[u8], min_addr: usize) -> Result<(usize, usize), Error> {
+    let text_offset = read_usize(image, LINUX_TEXT_OFFSET_OFFSET)?;
+    let image_size = read_usize(image, LINUX_IMAGE_SIZE_OFFSET)?;
+
+    // The lowest aligned base that does not put the image below `min_addr`.
+    let base = range(min_addr.saturating_sub(text_offset), LINUX_BASE_ALIGN - 1)?.end
+        & !(LINUX_BASE_ALIGN - 1);
+    let addr = range(base, text_offset)?.end;
+
+    Ok((addr, image_size.max(image.len())))
+}
+
+fn parse_raw(image: &[u8], load_addr: usize, window: &Range<usize>) -> Result<LoadPlan, Error> {
+    let format = match image.get(LINUX_MAGIC_OFFSET..LINUX_MAGIC_OFFSET + LINUX_MAGIC.len()) {
+        Some(magic) if magic == LINUX_MAGIC => Format::Linux,
+        _ => Format::Raw,
+    };
+    let (load_addr, size) = match format {
+        Format::Linux => linux_placement(image, load_addr)?,
+        _ => (load_addr, image.len()),
+    };
+
+    let dest = range(load_addr, size)?;
+    if !contains(window, &dest) {
+        return Err(Error::OutOfBounds(
+            "Raw image does not fit the payload window",
+        ));
+    }
+
+    let mut plan = LoadPlan::new(format, load_addr);
+    plan.push(Segment {
+        file: 0..image.len(),
+        dest,
+    })?;
+
+    Ok(plan)
+}
+
+fn parse_elf(image: &[u8], image_addr: usize, window: &Range<usize>) -> Result<LoadPlan, Error> {
+    let staged = range(image_addr, image.len())?;
+
+    if image.len() < EHDR_SIZE {
+        return Err(Error::Malformed("Truncated header"));
+    }
+    if image[4] != ELFCLASS64 || image[5] != ELFDATA2LSB {
+        return Err(Error::Malformed("Not a little endian ELF64 file"));
+    }
+    if read_u16(image, 16)? != ET_EXEC {
+        return Err(Error::Malformed("Not an executable"));
+    }
+    if read_u16(image, 18)? != EM_AARCH64 {
+        return Err(Error::Malformed("Not an AArch64 executable"));
+    }
+
+    let entry = read_usize(image, 24)?;
+    let phoff = read_usize(image, 32)?;
+    let phentsize = usize::from(read_u16(image, 54)?);
+    let phnum = usize::from(read_u16(image, 56)?);
+
+    if phentsize < PHDR_SIZE {
+        return Err(Error::Malformed("Program header entries too small"));
+    }
+    let phdrs_len = phentsize
+        .checked_mul(phnum)
+        .ok_or(Error::Malformed("Program header table too large"))?;
+    if !contains(&(0..image.len()), &range(phoff, phdrs_len)?) {
+        return Err(Error::Malformed(
+            "Program header table outside of the image",
+        ));
+    }
+
+    let mut plan = LoadPlan::new(Format::Elf, entry);
+    for i in 0..phnum {
+        let phdr = phoff + i * phentsize;
+        if read_u32(image, phdr)? != PT_LOAD {
+            continue;
+        }
+
+        let offset = read_usize(image, phdr + 8)?;
+        let paddr = read_usize(image, phdr + 24)?;
+        let filesz = read_usize(image, phdr + 32)?;
+        let memsz = read_usize(image, phdr + 40)?;
+
+        if memsz == 0 {
+            continue;
+        }
+        if filesz > memsz {
+            return Err(Error::Malformed(
+                "Segment's file size exceeds its memory size",
+            ));
+        }
+
+        let file = range(offset, filesz)?;
+        if !contains(&(0..image.len()), &file) {
+            return Err(Error::Malformed("Segment outside of the image"));
+        }
+
+        let dest = range(paddr, memsz)?;
+        if !contains(window, &dest) {
+            return Err(Error::OutOfBounds("Segment outside of the payload window"));
+        }
+        // The image itself is the source of all segments; writing over it would corrupt segments
+        // that are yet to be copied.
+        if overlaps(&dest, &staged) {
+            return Err(Error::OutOfBounds("Segment overlaps the staged image"));
+        }
+        if plan.segments().iter().any(|s| overlaps(&s.dest, &dest)) {
+            return Err(Error::Malformed("Segments overlap"));
+        }
+
+        plan.push(Segment { file, dest })?;
+    }
+
+    if plan.segments().is_empty() {
+        return Err(Error::Malformed("No loadable segments"));
+    }
+    if !plan.segments().iter().any(|s| s.dest.contains(&entry)) {
+        return Err(Error::OutOfBounds(
+            "Entry point outside of the loaded segments",
+        ));
+    }
+
+    Ok(plan)
+}
+
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Malformed(reason) => write!(f, "Malformed image: {}", reason),
+            Error::OutOfBounds(reason) => write!(f, "Out of bounds: {}", reason),
+        }
+    }
+}
+
+impl LoadPlan {
+    fn new(format: Format, entry: usize) -> Self {
+        Self {
+            format,
+            entry,
+            segments: Default::default(),
+            num_segments: 0,
+        }
+    }
+
+    fn push(&mut self, segment: Segment) -> Result<(), Error> {
+        if self.num_segments == MAX_SEGMENTS {
+            return Err(Error::Malformed("Too many segments"));
+        }
+
+        self.segments[self.num_segments] = segment;
+        self.num_segments += 1;
+
+        Ok(())
+    }
+
+    /// The segments to copy, in order.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments[..self.num_segments]
+    }
+
+    /// The range spanned by all segments.
+    pub fn span(&self) -> Range<usize> {
+        let start = self.segments().iter().map(|s| s.dest.start).min();
+        let end = self.segments().iter().map(|s| s.dest.end).max();
+
+        start.unwrap_or(0)..end.unwrap_or(0)
+    }
+
+    /// Copy the segments from the staged image at `image_addr` to their destinations.
+    ///
+    /// # Safety
+    ///
+    /// - The plan must have been produced by `parse()` for the image at `image_addr`.
+    /// - The payload window passed to `parse()` must be unused RAM.
+    pub unsafe fn load(&self, image_addr: usize) {
+        for segment in self.segments() {
+            let src = (image_addr + segment.file.start) as *const u8;
+            let dest = segment.dest.start as *mut u8;
+
+            // A raw image is moved onto itself, so source and destination may overlap. Both calls
+            // end up in the kernel's `memmove()` and `memset()`.
+            core::ptr::copy(src, dest, segment.file.len());
+            core::ptr::write_bytes(
+                dest.add(segment.file.len()),
+                0,
+                segment.dest.len() - segment.file.len(),
+            );
+        }
+    }
+}
+
+/// Find out the format of the `image` staged at `image_addr` and plan where it goes.
+///
+/// Raw images are placed at `load_addr`, Linux kernel `Image`s as close above it as their header
+/// allows. Every destination of the resulting plan lies within
+/// `window`.
+pub fn parse(
+    image: &[u8],
+    image_addr: usize,
+    load_addr: usize,
+    window: &Range<usize>,
+) -> Result<LoadPlan, Error> {
+    if image.starts_with(&ELF_MAGIC) {
+        parse_elf(image, image_addr, window)
+    } else {
+        parse_raw(image, load_addr, window)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Testing
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{vec, vec::Vec};
+
+    const WINDOW: Range<usize> = 0x8_0000..0x200_0000;
+    const LOAD_ADDR: usize = 0x8_0000;
+    const IMAGE_ADDR: usize = 0x1f0_0000;
+
+    /// A program header: `p_type`, `p_offset`, `p_paddr`, `p_filesz` and `p_memsz`.
+    type Phdr = (u32, u64, u64, u64, u64);
+
+    /// An AArch64 executable with the given entry and program headers, and room for the file
+    /// contents of the segments.
+    fn elf(entry: u64, phdrs: &[Phdr]) -> Vec<u8> {
+        let phdrs_end = EHDR_SIZE + phdrs.len() * PHDR_SIZE;
+        let len = phdrs
+            .iter()
+            .map(|p| (p.1 + p.3) as usize)
+            .fold(phdrs_end, usize::max);
+        let mut image = vec![0; len];
+
+        image[..4].copy_from_slice(&ELF_MAGIC);
+        image[4] = ELFCLASS64;
+        image[5] = ELFDATA2LSB;
+        image[16..18].copy_from_slice(&ET_EXEC.to_le_bytes());
+        image[18..20].copy_from_slice(&EM_AARCH64.to_le_bytes());
+        image[24..32].copy_from_slice(&entry.to_le_bytes());
+        image[32..40].copy_from_slice(&(EHDR_SIZE as u64).to_le_bytes());
+        image[54..56].copy_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+        image[56..58].copy_from_slice(&(phdrs.len() as u16).to_le_bytes());
+
+        for (i, &(p_type, offset, paddr, filesz, memsz)) in phdrs.iter().enumerate() {
+            let phdr = EHDR_SIZE + i * PHDR_SIZE;
+            image[phdr..phdr + 4].copy_from_slice(&p_type.to_le_bytes());
+            image[phdr + 8..phdr + 16].copy_from_slice(&offset.to_le_bytes());
+            image[phdr + 24..phdr + 32].copy_from_slice(&paddr.to_le_bytes());
+            image[phdr + 32..phdr + 40].copy_from_slice(&filesz.to_le_bytes());
+            image[phdr + 40..phdr + 48].copy_from_slice(&memsz.to_le_bytes());
+        }
+
+        image
+    }
+
+    /// A Linux kernel `Image` header with the given `text_offset` and `image_size`.
+    fn linux(text_offset: u64, image_size: u64) -> Vec<u8> {
+        let mut image = vec![0; 0x1000];
+        image[LINUX_TEXT_OFFSET_OFFSET..LINUX_TEXT_OFFSET_OFFSET + 8]
+            .copy_from_slice(&text_offset.to_le_bytes());
+        image[LINUX_IMAGE_SIZE_OFFSET..LINUX_IMAGE_SIZE_OFFSET + 8]
+            .copy_from_slice(&image_size.to_le_bytes());
+        image[LINUX_MAGIC_OFFSET..LINUX_MAGIC_OFFSET + 4].copy_from_slice(&LINUX_MAGIC);
+
+        image
+    }
+
+    fn parse_err(image: &[u8]) -> Option<Error> {
+        parse(image, IMAGE_ADDR, LOAD_ADDR, &WINDOW).err()
+    }
+
+    /// A raw image is moved to the load address and entered at its first byte.
+    #[test_case]
+    fn raw_image() {
+        let image = [0x14u8; 0x100];
+        let plan = parse(&image, IMAGE_ADDR, LOAD_ADDR, &WINDOW).unwrap();
+
+        assert_eq!(plan.format, Format::Raw);
+        assert_eq!(plan.entry, LOAD_ADDR);
+        assert_eq!(
+            plan.segments(),
+            &[Segment {
+                file: 0..0x100,
+                dest: LOAD_ADDR..LOAD_ADDR + 0x100,
+            }]
+        );
+    }
+
+    /// A raw image must fit between the load address and the end of the window.
+    #[test_case]
+    fn raw_image_too_large() {
+        let image = vec![0; WINDOW.end - LOAD_ADDR + 1];
+
+        assert_eq!(
+            parse_err(&image),
+            Some(Error::OutOfBounds(
+                "Raw image does not fit the payload window"
+            ))
+        );
+    }
+
+    /// Only the magic at 0x38 makes a Linux kernel `Image`.
+    #[test_case]
+    fn linux_magic() {
+        let plan = parse(&linux(0x8_0000, 0), IMAGE_ADDR, LOAD_ADDR, &WINDOW).unwrap();
+        assert_eq!(plan.format, Format::Linux);
+
+        let mut image = linux(0x8_0000, 0);
+        image.copy_within(LINUX_MAGIC_OFFSET..LINUX_MAGIC_OFFSET + 4, 0x30);
+        image[LINUX_MAGIC_OFFSET..LINUX_MAGIC_OFFSET + 4].copy_from_slice(&[0; 4]);
+
+        let plan = parse(&image, IMAGE_ADDR, LOAD_ADDR, &WINDOW).unwrap();
+        assert_eq!(plan.format, Format::Raw);
+    }
+
+    /// A Linux kernel `Image` goes `text_offset` above the lowest 2 MiB aligned base that keeps it
+    /// at or above the load address, with `image_size` bytes for it.
+    #[test_case]
+    fn linux_image_placement() {
+        let plan = parse(&linux(0x8_0000, 0), IMAGE_ADDR, LOAD_ADDR, &WINDOW).unwrap();
+        assert_eq!(plan.entry, 0x8_0000);
+        assert_eq!(plan.span(), 0x8_0000..0x8_1000);
+
+        let plan = parse(&linux(0, 0x10_0000), IMAGE_ADDR, LOAD_ADDR, &WINDOW).unwrap();
+        assert_eq!(plan.entry, 0x20_0000);
+        assert_eq!(plan.segments()[0].file, 0..0x1000);
+        assert_eq!(plan.span(), 0x20_0000..0x30_0000);
+
+        assert_eq!(
+            parse_err(&linux(0, WINDOW.end as u64)),
+            Some(Error::OutOfBounds(
+                "Raw image does not fit the payload window"
+            ))
+        );
+    }
+
+    /// The `PT_LOAD` segments are planned in order, with their bss zeroed, and others are skipped.
+    #[test_case]
+    fn elf_segments() {
+        let image = elf(
+            0x10_0000,
+            &[
+                (PT_LOAD, 0x1000, 0x10_0000, 0x100, 0x100),
+                (0x6474_e551, 0, 0, 0, 0),
+                (PT_LOAD, 0x1100, 0x20_0000, 0x10, 0x1000),
+            ],
+        );
+        let plan = parse(&image, IMAGE_ADDR, LOAD_ADDR, &WINDOW).unwrap();
+
+        assert_eq!(plan.format, Format::Elf);
+        assert_eq!(plan.entry, 0x10_0000);
+        assert_eq!(
+            plan.segments(),
+            &[
+                Segment {
+                    file: 0x1000..0x1100,
+                    dest: 0x10_0000..0x10_0100,
+                },
+                Segment {
+                    file: 0x1100..0x1110,
+                    dest: 0x20_0000..0x20_1000,
+                },
+            ]
+        );
+    }
+
+    /// An executable without anything to load has nothing to run either.
+    #[test_case]
+    fn elf_without_pt_load() {
+        let image = elf(0x10_0000, &[(0x6474_e551, 0, 0, 0, 0)]);
+
+        assert_eq!(
+            parse_err(&image),
+            Some(Error::Malformed("No loadable segments"))
+        );
+    }
+
+    /// No more than `MAX_SEGMENTS` segments are loaded.
+    #[test_case]
+    fn elf_with_too_many_segments() {
+        let phdrs: Vec<Phdr> = (0..MAX_SEGMENTS as u64 + 1)
+            .map(|i| (PT_LOAD, 0x1000, 0x10_0000 + i * 0x1000, 0x10, 0x10))
+            .collect();
+
+        assert_eq!(
+            parse_err(&elf(0x10_0000, &phdrs)),
+            Some(Error::Malformed("Too many segments"))
+        );
+    }
+
+    /// The entry point must be in a loaded segment.
+    #[test_case]
+    fn elf_entry_outside_of_segments() {
+        let image = elf(0x10_0100, &[(PT_LOAD, 0x1000, 0x10_0000, 0x100, 0x100)]);
+
+        assert_eq!(
+            parse_err(&image),
+            Some(Error::OutOfBounds(
+                "Entry point outside of the loaded segments"
+            ))
+        );
+    }
+
+    /// A segment must neither write over the image it is copied from, nor over another segment.
+    #[test_case]
+    fn elf_overlapping_segments() {
+        let image = elf(
+            IMAGE_ADDR as u64,
+            &[(PT_LOAD, 0x1000, IMAGE_ADDR as u64 - 0x100, 0x100, 0x200)],
+        );
+        assert_eq!(
+            parse_err(&image),
+            Some(Error::OutOfBounds("Segment overlaps the staged image"))
+        );
+
+        let image = elf(
+            0x10_0000,
+            &[
+                (PT_LOAD, 0x1000, 0x10_0000, 0x100, 0x1000),
+                (PT_LOAD, 0x1100, 0x10_0800, 0x100, 0x100),
+            ],
+        );
+        assert_eq!(
+            parse_err(&image),
+            Some(Error::Malformed("Segments overlap"))
+        );
+    }
+
+    /// Segments must be in the image and go to the window.
+    #[test_case]
+    fn elf_segments_out_of_bounds() {
+        let mut image = elf(0x10_0000, &[(PT_LOAD, 0x1000, 0x10_0000, 0x100, 0x100)]);
+        image.truncate(0x10ff);
+        assert_eq!(
+            parse_err(&image),
+            Some(Error::Malformed("Segment outside of the image"))
+        );
+
+        let image = elf(
+            WINDOW.end as u64 - 0x80,
+            &[(PT_LOAD, 0x1000, WINDOW.end as u64 - 0x80, 0x100, 0x100)],
+        );
+        assert_eq!(
+            parse_err(&image),
+            Some(Error::OutOfBounds("Segment outside of the payload window"))
+        );
+    }
+}
@@ -0,0 +1,330 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! The loader side of the chainloading protocol.
+//!
+//! 1. The loader sends `REQUEST_TOKEN`.
+//! 2. The host sends the binary's size, followed by the `FLAG_*` bits that apply to it. Both are
+//!    little endian `u32`s.
+//! 3. The loader answers with `SIZE_ACK` if the binary fits into the payload window, or with
+//!    `SIZE_NACK` if it does not. In the latter case, the exchange starts over.
+//! 4. The host sends the binary, which is stored at the top of the payload window (the *staging
+//!    area*), from where `image` takes over.
+//!
+//! With `FLAG_MEMTEST`, the host sends a range of RAM to test instead of a binary, see
+//! `memtest_range()`. The loader requests a binary again when the test is done.
+
+use core::ops::Range;
+
+// -------------------------------------------------------------------------------------------------
+// Public definitions
+// -------------------------------------------------------------------------------------------------
+
+/// The three characters with which the loader requests a binary.
+pub const REQUEST_TOKEN: [u8; 3] = [3, 3, 3];
+
+/// Answer to a size that fits.
+pub const SIZE_ACK: [u8; 2] = *b"OK";
+
+/// Answer to a size that does not fit.
+pub const SIZE_NACK: [u8; 2] = *b"SE";
+
+/// Alignment of the staging area's start address.
+pub const STAGING_ALIGN: usize = 4096;
+
+/// Flag: Enter the payload at EL1h instead of EL2.
+pub const FLAG_ENTER_EL1: u32 = 1 << 0;
+
+/// Flag: Keep the loader resident and guard it from the payload, which is entered at EL1h.
+pub const FLAG_GUARD_LOADER: u32 = 1 << 1;
+
+/// Flag: Let the payload use FP/SIMD instructions instead of trapping them.
+pub const FLAG_ENABLE_FP: u32 = 1 << 2;
+
+/// Flag: Instead of a binary, the host sends a range of RAM to test.
+pub const FLAG_MEMTEST: u32 = 1 << 3;
+
+/// What the loader has to do after feeding a byte to the `Receiver`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Nothing, more bytes are needed.
+    Pending,
+
+    /// The size was accepted. Answer with `SIZE_ACK`.
+    SizeAccepted,
+
+    /// The size was rejected. Answer with `SIZE_NACK` and start over.
+    SizeRejected {
+        /// The rejected size.
+        size: usize,
+    },
+
+    /// Store `value` at `addr`.
+    Store {
+        /// Always within the staging area.
+        addr: usize,
+        /// The received byte.
+        value: u8,
+    },
+
+    /// The exchange is already over. The byte was dropped.
+    Ignored,
+}
+
+/// The protocol state machine. Fed one byte at a time.
+pub struct Receiver {
+    window: Range<usize>,
+    flags: u32,
+    state: State,
+}
+
+// -------------------------------------------------------------------------------------------------
+// Private definitions
+// -------------------------------------------------------------------------------------------------
+
+enum State {
+    Header {
+        bytes: [u8; 8],
+        received: usize,
+    },
+    Payload {
+        staging: Range<usize>,
+        received: usize,
+    },
+    Complete {
+        staging: Range<usize>,
+    },
+    Rejected,
+}
+
+// -------------------------------------------------------------------------------------------------
+// Private code
+// -------------------------------------------------------------------------------------------------
+
+/// Where a binary of `size` bytes is staged, if it fits into `window` at all.
+fn staging_area(window: &Range<usize>, size: usize) -> Option<Range<usize>> {
+    if size == 0 || window.start > window.end {
+        return None;
+    }
+
+    let start = window.end.checked_sub(size)? & !(STAGING_ALIGN - 1);
+    if start < window.start {
+        return None;
+    }
+
+    Some(start..start + size)
+}
+
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
+impl Receiver {
+    /// Create a receiver that only ever stores into `window`.
+    pub const fn new(window: Range<usize>) -> Self {
+        Self {
+            window,
+            flags: 0,
+            state: State::Header {
+                bytes: [0; 8],
+                received: 0,
+            },
+        }
+    }
+
+    /// Advance the protocol by one received byte.
+    pub fn feed(&mut self, byte: u8) -> Event {
+        match self.state {
+            State::Header {
+                ref mut bytes,
+                ref mut received,
+            } => {
+                bytes[*received] = byte;
+                *received += 1;
+                if *received < bytes.len() {
+                    return Event::Pending;
+                }
+
+                let size = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+                let flags = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+                match staging_area(&self.window, size) {
+                    Some(staging) => {
+                        self.flags = flags;
+                        self.state = State::Payload {
+                            staging,
+                            received: 0,
+                        };
+                        Event::SizeAccepted
+                    }
+                    None => {
+                        self.state = State::Rejected;
+                        Event::SizeRejected { size }
+                    }
+                }
+            }
+            State::Payload {
+                ref staging,
+                ref mut received,
+            } => {
+                let addr = staging.start + *received;
+                *received += 1;
+
+                if *received == staging.len() {
+                    self.state = State::Complete {
+                        staging: staging.clone(),
+                    };
+                }
+
+                Event::Store { addr, value: byte }
+            }
+            State::Complete { .. } | State::Rejected => Event::Ignored,
+        }
+    }
+
+    /// The staging area holding the complete binary, once the last byte was stored.
+    pub fn staged(&self) -> Option<Range<usize>> {
+        match self.state {
+            State::Complete { ref staging } => Some(staging.clone()),
+            _ => None,
+        }
+    }
+
+    /// The `FLAG_*` bits the host sent along with an accepted size.
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Check if the exchange is over, either because the binary is complete or it was rejected.
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, State::Complete { .. } | State::Rejected)
+    }
+}
+
+/// The range of RAM that a `FLAG_MEMTEST` binary asks to test. The binary holds the range's start
+/// and end as little endian `u64`s.
+pub fn memtest_range(binary: &[u8]) -> Option<Range<usize>> {
+    if binary.len() != 16 {
+        return None;
+    }
+
+    let mut start = [0; 8];
+    let mut end = [0; 8];
+    start.copy_from_slice(&binary[..8]);
+    end.copy_from_slice(&binary[8..]);
+
+    Some(u64::from_le_bytes(start) as usize..u64::from_le_bytes(end) as usize)
+}
+
+// -------------------------------------------------------------------------------------------------
+// Testing
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Range<usize> = 0x8_0000..0x20_0000;
+
+    /// Feed the size and flags header to `receiver`, and return the event for its last byte.
+    fn feed_header(receiver: &mut Receiver, size: u32, flags: u32) -> Event {
+        let mut header = [0; 8];
+        header[..4].copy_from_slice(&size.to_le_bytes());
+        header[4..].copy_from_slice(&flags.to_le_bytes());
+
+        for byte in &header[..7] {
+            assert_eq!(receiver.feed(*byte), Event::Pending);
+        }
+        receiver.feed(header[7])
+    }
+
+    /// The host tool answers to these.
+    #[test_case]
+    fn answers() {
+        assert_eq!(&SIZE_ACK, b"OK");
+        assert_eq!(&SIZE_NACK, b"SE");
+    }
+
+    /// Size and flags are little endian, and the binary is stored at the top of the window, on a
+    /// `STAGING_ALIGN` boundary.
+    #[test_case]
+    fn binary_is_staged() {
+        let mut receiver = Receiver::new(WINDOW);
+        let flags = FLAG_ENTER_EL1 | FLAG_ENABLE_FP;
+
+        assert_eq!(feed_header(&mut receiver, 3, flags), Event::SizeAccepted);
+        assert_eq!(receiver.flags(), flags);
+
+        let staging = WINDOW.end - STAGING_ALIGN;
+        for (i, byte) in [0xaa, 0xbb, 0xcc].iter().enumerate() {
+            assert_eq!(receiver.staged(), None);
+            assert!(!receiver.is_done());
+            assert_eq!(
+                receiver.feed(*byte),
+                Event::Store {
+                    addr: staging + i,
+                    value: *byte
+                }
+            );
+        }
+
+        assert!(receiver.is_done());
+        assert_eq!(receiver.staged(), Some(staging..staging + 3));
+        assert_eq!(receiver.feed(0), Event::Ignored);
+    }
+
+    /// A binary that fills the window exactly still fits.
+    #[test_case]
+    fn binary_fills_window() {
+        let window = WINDOW.start..WINDOW.start + STAGING_ALIGN;
+        let mut receiver = Receiver::new(window.clone());
+
+        assert_eq!(
+            feed_header(&mut receiver, STAGING_ALIGN as u32, 0),
+            Event::SizeAccepted
+        );
+        for i in 0..STAGING_ALIGN {
+            assert_eq!(
+                receiver.feed(0),
+                Event::Store {
+                    addr: window.start + i,
+                    value: 0
+                }
+            );
+        }
+
+        assert_eq!(receiver.staged(), Some(window));
+    }
+
+    /// Empty and oversized binaries are rejected, and nothing is stored after that.
+    #[test_case]
+    fn bad_sizes_are_rejected() {
+        let oversized = (WINDOW.end - WINDOW.start + 1) as u32;
+
+        for size in &[0, oversized, u32::MAX] {
+            let mut receiver = Receiver::new(WINDOW);
+
+            assert_eq!(
+                feed_header(&mut receiver, *size, 0),
+                Event::SizeRejected {
+                    size: *size as usize
+                }
+            );
+            assert!(receiver.is_done());
+            assert_eq!(receiver.staged(), None);
+            assert_eq!(receiver.feed(0), Event::Ignored);
+        }
+    }
+
+    /// A memtest request is exactly two little endian `u64`s.
+    #[test_case]
+    fn memtest_requests() {
+        let mut binary = [0; 16];
+        binary[..8].copy_from_slice(&0x10_0000u64.to_le_bytes());
+        binary[8..].copy_from_slice(&0x20_0000u64.to_le_bytes());
+
+        assert_eq!(memtest_range(&binary), Some(0x10_0000..0x20_0000));
+        assert_eq!(memtest_range(&binary[..15]), None);
+        assert_eq!(memtest_range(&[0; 17]), None);
+    }
+}
@@ -7,120 +7,92 @@
 
 //! The `kernel` binary.
 //!
-//! # Code organization and architecture
-//!
-//! The code is divided into different *modules*, each representing a typical **subsystem** of the
-//! `kernel`. Top-level module files of subsystems reside directly in the `src` folder. For example,
-//! `src/memory.rs` contains code that is concerned with all things memory management.
-//!
-//! ## Visibility of processor architecture code
-//!
-//! Some of the `kernel`'s subsystems depend on low-level code that is specific to the target
-//! processor architecture. For each supported processor architecture, there exists a subfolder in
-//! `src/_arch`, for example, `src/_arch/aarch64`.
-//!
-//! The architecture folders mirror the subsystem modules laid out in `src`. For example,
-//! architectural code that belongs to the `kernel`'s memory subsystem (`src/memory.rs`) would go
-//! into `src/_arch/aarch64/memory.rs`. The latter file is directly included and re-exported in
-//! `src/memory.rs`, so that the architectural code parts are transparent with respect to the code's
-//! module organization. That means a public function `foo()` defined in
-//! `src/_arch/aarch64/memory.rs` would be reachable as `crate::memory::foo()` only.
-//!
-//! The `_` in `_arch` denotes that this folder is not part of the standard module hierarchy.
-//! Rather, it's contents are conditionally pulled into respective files using the `#[path =
-//! "_arch/xxx/yyy.rs"]` attribute.
-//!
-//! ## BSP code
-//!
-//! `BSP` stands for Board Support Package. `BSP` code is organized under `src/bsp.rs` and contains
-//! target board specific definitions and functions. These are things such as the board's memory map
-//! or instances of drivers for devices that are featured on the respective board.
-//!
-//! Just like processor architecture code, the `BSP` code's module structure tries to mirror the
-//! `kernel`'s subsystem modules, but there is no transparent re-exporting this time. That means
-//! whatever is provided must be called starting from the `bsp` namespace, e.g.
-//! `bsp::driver::driver_manager()`.
-//!
-//! ## Kernel interfaces
-//!
-//! Both `arch` and `bsp` contain code that is conditionally compiled depending on the actual target
-//! and board for which the kernel is compiled. For example, the `interrupt controller` hardware of
-//! the `Raspberry Pi 3` and the `Raspberry Pi 4` is different, but we want the rest of the `kernel`
-//! code to play nicely with any of the two without much hassle.
-//!
-//! In order to provide a clean abstraction between `arch`, `bsp` and `generic kernel code`,
-//! `interface` traits are provided *whenever possible* and *where it makes sense*. They are defined
-//! in the respective subsystem module and help to enforce the idiom of *program to an interface,
-//! not an implementation*. For example, there will be a common IRQ handling interface which the two
-//! different interrupt controller `drivers` of both Raspberrys will implement, and only export the
-//! interface to the rest of the `kernel`.
-//!
-//! ```
-//!         +-------------------+
-//!         | Interface (Trait) |
-//!         |                   |
-//!         +--+-------------+--+
-//!            ^             ^
-//!            |             |
-//!            |             |
-//! +----------+--+       +--+----------+
-//! | kernel code |       |  bsp code   |
-//! |             |       |  arch code  |
-//! +-------------+       +-------------+
-//! ```
-//!
-//! # Summary
-//!
-//! For a logical `kernel` subsystem, corresponding code can be distributed over several physical
-//! locations. Here is an example for the **memory** subsystem:
-//!
-//! - `src/memory.rs` and `src/memory/**/*`
-//!   - Common code that is agnostic of target processor architecture and `BSP` characteristics.
-//!     - Example: A function to zero a chunk of memory.
-//!   - Interfaces for the memory subsystem that are implemented by `arch` or `BSP` code.
-//!     - Example: An `MMU` interface that defines `MMU` function prototypes.
-//! - `src/bsp/__board_name__/memory.rs` and `src/bsp/__board_name__/memory/**/*`
-//!   - `BSP` specific code.
-//!   - Example: The board's memory map (physical addresses of DRAM and MMIO devices).
-//! - `src/_arch/__arch_name__/memory.rs` and `src/_arch/__arch_name__/memory/**/*`
-//!   - Processor architecture specific code.
-//!   - Example: Implementation of the `MMU` interface for the `__arch_name__` processor
-//!     architecture.
-//!
-//! From a namespace perspective, **memory** subsystem code lives in:
-//!
-//! - `crate::memory::*`
-//! - `crate::bsp::memory::*`
+//! All subsystems live in the `libkernel` library (`src/lib.rs`); see there for an overview of the
+//! code organization. The binary only provides `kernel_init()` and the loader itself.
 
 #![feature(format_args_nl)]
-#![feature(naked_functions)]
-#![feature(panic_info_message)]
-#![feature(trait_alias)]
 #![no_main]
 #![no_std]
 
-// `mod cpu` provides the `_start()` function, the first function to run. `_start()` then calls
-// `runtime_init()`, which jumps to `kernel_init()`.
-
-mod bsp;
-mod console;
-mod cpu;
-mod driver;
-mod memory;
-mod panic_wait;
-mod print;
-mod relocate;
-mod runtime_init;
-mod synchronization;
-
-/// Early init code.
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use libkernel::{
+    boot_info::BootInfo,
+    bsp, console, cpu, driver,
+    exception::{self, PrivilegeLevel},
+    hypervisor,
+    loader::{
+        crc32::crc32,
+        fdt,
+        image::{self, LoadPlan},
+        protocol,
+    },
+    memory::{self, map::RegionKind},
+    println,
+};
+
+/// Early init code. Called from `runtime_init()` with the device tree address that the firmware
+/// passed.
 ///
 /// # Safety
 ///
 /// - Only a single core must be active and running this function.
 /// - The init calls in this function must appear in the correct order
-unsafe fn kernel_init() -> ! {
+#[no_mangle]
+unsafe fn kernel_init(dtb_addr: usize) -> ! {
+    exception::handling_init();
+
+    init_mmu_and_drivers();
+
+    memory::heap::kernel_heap_allocator().init(bsp::memory::heap_region());
+
+    // println! is usable from here on
+
+    // Transition from unsafe to safe
+    kernel_main(dtb_addr);
+}
+
+/// Where the loader takes over again when a guarded payload hands control back. Jumped to from the
+/// hypervisor's exception handlers on the boot core, with the MMU and caches off and the boot core's
+/// stack reset.
+///
+/// # Safety
+///
+/// - No other core may run loader code.
+#[no_mangle]
+unsafe extern "C" fn loader_resume() -> ! {
+    // Recorded before the hypervisor jumps here.
+    let reason = hypervisor::recall_reason().unwrap();
+
+    // The payload's FP/SIMD state is of no interest anymore.
+    cpu::init_fp();
+
+    // The payload may have reprogrammed the devices.
+    init_mmu_and_drivers();
+
+    // Whatever was allocated went away with the stack frames that were abandoned for the payload.
+    memory::heap::kernel_heap_allocator().init(bsp::memory::heap_region());
+
+    println!();
+    println!("[ML] Back in the loader: {}", reason);
+
+    serve_payload(DTB_ADDR.load(Ordering::Relaxed))
+}
+
+/// Turn on the MMU and bring up the drivers.
+///
+/// # Safety
+///
+/// - Only a single core must be active and running this function.
+unsafe fn init_mmu_and_drivers() {
     use driver::interface::DriverManager;
+    use memory::mmu::interface::MMU;
+
+    if let Err(string) = memory::mmu::mmu().init() {
+        panic!("MMU: {}", string);
+    }
 
     for i in bsp::driver::driver_manager().all_device_drivers().iter() {
         if i.init().is_err() {
@@ -129,17 +101,346 @@ unsafe fn kernel_init() -> ! {
     }
 
     bsp::driver::driver_manager().post_device_driver_init();
+}
 
-    // println! is usable from here on
+/// The device tree address that the firmware passed, for when the loader takes over again.
+static DTB_ADDR: AtomicUsize = AtomicUsize::new(0);
 
-    // Transition from unsafe to safe
-    kernel_main();
+/// A received payload, ready to be loaded.
+struct Payload {
+    /// Where the payload was received into.
+    staged: Range<usize>,
+
+    /// Where the payload's parts go.
+    plan: LoadPlan,
+
+    /// The `protocol::FLAG_*` bits sent along with the payload.
+    flags: u32,
+
+    /// The CRC-32 of the payload as it was received.
+    crc32: u32,
+}
+
+/// Check that the staged payload is free RAM, and reserve everything it is loaded to.
+///
+/// Nothing stays reserved if any part cannot be.
+fn reserve_payload(
+    staged: &Range<usize>,
+    plan: &LoadPlan,
+) -> Result<(), (Range<usize>, memory::map::Error)> {
+    memory::map::kernel_map().lock(|memory_map| {
+        memory_map
+            .check_free(staged)
+            .map_err(|e| (staged.clone(), e))?;
+
+        for segment in plan.segments() {
+            if let Err(e) = memory_map.reserve("Payload", RegionKind::Payload, segment.dest.clone())
+            {
+                memory_map.release(RegionKind::Payload);
+                return Err((segment.dest.clone(), e));
+            }
+        }
+
+        Ok(())
+    })
 }
 
-fn kernel_main() -> ! {
+/// Run all memory tests over `range`, which must be free RAM, and report the results.
+fn run_memtest(range: Range<usize>) {
+    use memory::memtest;
+
+    // Per test. The outcome still tells about all of them.
+    const MAX_REPORTED_FAILURES: usize = 8;
+
+    if let Err(e) = memory::map::kernel_map().lock(|memory_map| memory_map.check_free(&range)) {
+        println!(
+            "[ML] Cannot test {:#x}..{:#x}: {}",
+            range.start, range.end, e
+        );
+        return;
+    }
+
+    println!("[ML] Testing {:#x}..{:#x}", range.start, range.end);
+    for test in memtest::ALL_TESTS.iter() {
+        let mut reported = 0;
+
+        // The range is free RAM, which nobody uses.
+        let outcome = unsafe {
+            memtest::run(*test, range.clone(), |failure| {
+                if reported < MAX_REPORTED_FAILURES {
+                    println!("[ML]   {}: {}", test, failure);
+                    reported += 1;
+                }
+            })
+        };
+        println!("[ML]   {}", outcome);
+    }
+}
+
+/// Test all free RAM.
+///
+/// The secondary cores must be parked, they start out running from the payload window.
+#[cfg(feature = "memtest_at_boot")]
+fn memtest_free_ram() {
+    let mut i = 0;
+
+    // The memory map cannot change in between, the other cores wait on the spin-table.
+    while let Some(range) =
+        memory::map::kernel_map().lock(|memory_map| memory_map.free_ranges().nth(i))
+    {
+        run_memtest(range);
+        i += 1;
+    }
+}
+/// Receive a payload over the console, find out where it goes and reserve that in the memory map.
+/// Receive a payload over the console and find out where it goes.
+///
+/// Starts over as often as needed until a valid payload was received.
+fn receive_payload(window: &Range<usize>) -> Payload {
     use bsp::console::console;
     use console::interface::All;
 
+    loop {
+        println!("[ML] Requesting binary");
+        console().flush();
+
+        // Clear the RX FIFOs, if any, of spurious received characters before starting with the
+        // loader protocol.
+        console().clear();
+
+        // Notify `Minipush` to send the binary.
+        for c in protocol::REQUEST_TOKEN.iter() {
+            console().write_char(*c as char);
+        }
+
+        let mut receiver = protocol::Receiver::new(window.clone());
+        while !receiver.is_done() {
+            match receiver.feed(console().read_char() as u8) {
+                protocol::Event::SizeAccepted => {
+                    for c in protocol::SIZE_ACK.iter() {
+                        console().write_char(*c as char);
+                    }
+                }
+                protocol::Event::SizeRejected { .. } => {
+                    for c in protocol::SIZE_NACK.iter() {
+                        console().write_char(*c as char);
+                    }
+                }
+                protocol::Event::Store { addr, value } => unsafe {
+                    core::ptr::write_volatile(addr as *mut u8, value)
+                },
+                protocol::Event::Pending | protocol::Event::Ignored => (),
+            }
+        }
+
+        let staged = match receiver.staged() {
+            Some(staged) => staged,
+            None => {
+                println!(
+                    "[ML] Binary too big for {:#x}..{:#x}",
+                    window.start, window.end
+                );
+                continue;
+            }
+        };
+
+        let payload =
+            unsafe { core::slice::from_raw_parts(staged.start as *const u8, staged.len()) };
+
+        if receiver.flags() & protocol::FLAG_MEMTEST != 0 {
+            match protocol::memtest_range(payload) {
+                Some(range) => run_memtest(range),
+                None => println!("[ML] Invalid memtest request"),
+            }
+            continue;
+        }
+        let plan = match image::parse(
+            payload,
+            staged.start,
+            bsp::cpu::BOARD_DEFAULT_LOAD_ADDRESS,
+            window,
+        ) {
+            Ok(plan) => plan,
+            Err(e) => {
+                println!("[ML] {}", e);
+                continue;
+            }
+        };
+
+        if let Err((range, e)) = reserve_payload(&staged, &plan) {
+            println!(
+                "[ML] Cannot load to {:#x}..{:#x}: {}",
+                range.start, range.end, e
+            );
+            continue;
+        }
+
+        return Payload {
+            staged,
+            plan,
+            flags: receiver.flags(),
+            crc32: crc32(payload),
+        };
+    }
+}
+
+/// Point the `cpu-release-addr`s in the device tree at `dtb_addr` to the spin-table.
+///
+/// Returns the address of the device tree to hand to the payload, or zero if there is none.
+fn forward_device_tree(dtb_addr: usize, window: &Range<usize>) -> usize {
+    if dtb_addr == 0 {
+        println!("[ML] No device tree to forward");
+        return 0;
+    }
+
+    // The firmware put a device tree there, or nothing sensible at all, which the magic catches.
+    let header =
+        unsafe { core::slice::from_raw_parts(dtb_addr as *const u8, fdt::HEADER_PREFIX_SIZE) };
+    let dtb = match fdt::total_size(header) {
+        Ok(size) => dtb_addr..dtb_addr.saturating_add(size),
+        Err(e) => {
+            println!(
+                "[ML] Not forwarding the device tree at {:#x}: {}",
+                dtb_addr, e
+            );
+            return 0;
+        }
+    };
+
+    // A device tree that reaches into the payload window might have been overwritten by now, and so
+    // might one that the memory map does not cover, e.g. because it overlaps the loader.
+    let in_window = dtb.start < window.end && window.start < dtb.end;
+    let reserved = memory::map::kernel_map().lock(|memory_map| {
+        memory_map.regions().iter().any(|region| {
+            region.kind == RegionKind::DeviceTree
+                && region.start <= dtb.start
+                && dtb.end <= region.end
+        })
+    });
+    if in_window || !reserved {
+        println!(
+            "[ML] Not forwarding the device tree at {:#x}..{:#x}: {}",
+            dtb.start,
+            dtb.end,
+            if in_window {
+                "In the payload window"
+            } else {
+                "Not reserved in the memory map"
+            }
+        );
+        return 0;
+    }
+
+    let blob = unsafe { core::slice::from_raw_parts_mut(dtb.start as *mut u8, dtb.len()) };
+    match fdt::patch_cpu_release_addrs(blob, bsp::cpu::spin_table_release_addr) {
+        Ok(patched) => {
+            println!(
+                "[ML] Forwarding the device tree at {:#x}, {} cpu-release-addr patched",
+                dtb_addr, patched
+            );
+            dtb_addr
+        }
+        Err(e) => {
+            println!(
+                "[ML] Not forwarding the device tree at {:#x}: {}",
+                dtb_addr, e
+            );
+            0
+        }
+    }
+}
+
+/// The `bootargs` of the device tree at `dtb_addr`, or nothing if there are none.
+fn device_tree_bootargs(dtb_addr: usize) -> &'static [u8] {
+    if dtb_addr == 0 {
+        return &[];
+    }
+
+    // Only called for device trees that `forward_device_tree()` accepted.
+    let header =
+        unsafe { core::slice::from_raw_parts(dtb_addr as *const u8, fdt::HEADER_PREFIX_SIZE) };
+    let blob = match fdt::total_size(header) {
+        Ok(size) => unsafe { core::slice::from_raw_parts(dtb_addr as *const u8, size) },
+        Err(_) => return &[],
+    };
+
+    fdt::bootargs(blob).ok().flatten().unwrap_or(&[])
+}
+
+/// Write the boot info for `payload`, which gets the device tree at `dtb_addr`.
+///
+/// Returns the address to pass to the payload in x1, which is zero for Linux, or if the region for
+/// the boot info is not reserved in the memory map.
+fn write_boot_info(payload: &Payload, dtb_addr: usize) -> usize {
+    let region = bsp::memory::boot_info_region();
+
+    // Unless exactly this region is reserved for it, the region holds something else, like the
+    // device tree.
+    let reserved = memory::map::kernel_map().lock(|memory_map| {
+        memory_map
+            .regions()
+            .iter()
+            .any(|other| other.kind == RegionKind::BootInfo && other.range() == region)
+    });
+    if !reserved {
+        println!(
+            "[ML] No boot info: {:#x}..{:#x} is in use",
+            region.start, region.end
+        );
+        return 0;
+    }
+
+    let info = unsafe { &mut *(region.start as *mut BootInfo) };
+    *info = BootInfo::new();
+
+    info.set_board_name(bsp::board_name());
+    info.set_loader_version(env!("CARGO_PKG_VERSION"));
+    info.dtb_addr = dtb_addr as u64;
+    info.uart_base = bsp::console::UART_BASE as u64;
+    info.uart_baud = bsp::console::BAUD_RATE;
+    memory::map::kernel_map().lock(|memory_map| info.set_memory_map(memory_map));
+    info.payload_start = payload.plan.span().start as u64;
+    info.payload_end = payload.plan.span().end as u64;
+    info.payload_crc32 = payload.crc32;
+    info.set_cmdline(device_tree_bootargs(dtb_addr));
+
+    println!(
+        "[ML] Boot info at {:#x}. Payload at {:#x}..{:#x}, CRC-32 {:#010x}",
+        region.start, info.payload_start, info.payload_end, info.payload_crc32
+    );
+
+    if payload.plan.format == image::Format::Linux {
+        0
+    } else {
+        region.start
+    }
+}
+
+/// Keep the loader out of the payload's reach, and let a break on the console hand control back.
+fn guard_loader() {
+    let loader = bsp::memory::loader_range();
+
+    if let Err(e) = unsafe { hypervisor::guard(&loader) } {
+        println!("[ML] Cannot guard the loader: {}", e);
+        return;
+    }
+    println!(
+        "[ML] Guarding the loader at {:#x}..{:#x}. hvc #{:#x} returns to it",
+        loader.start,
+        loader.end,
+        hypervisor::RECALL_HVC_IMMEDIATE
+    );
+
+    match unsafe { hypervisor::recall_on_uart_break(cpu::smp::core_id()) } {
+        Ok(()) => println!("[ML] A break on the console returns to the loader"),
+        Err(e) => println!(
+            "[ML] A break on the console does not return to the loader: {}",
+            e
+        ),
+    }
+}
+
+fn kernel_main(dtb_addr: usize) -> ! {
     println!(" __  __ _      _ _                 _ ");
     println!("|  \\/  (_)_ _ (_) |   ___  __ _ __| |");
     println!("| |\\/| | | ' \\| | |__/ _ \\/ _` / _` |");
@@ -147,43 +448,108 @@ fn kernel_main() -> ! {
     println!();
     println!("{:^37}", bsp::board_name());
     println!();
-    println!("[ML] Requesting binary");
-    console().flush();
 
-    // Clear the RX FIFOs, if any, of spurious received characters before starting with the loader
-    // protocol.
-    console().clear();
+    let (_, el_string) = exception::current_privilege_level();
+    println!("[ML] Current privilege level: {}", el_string);
+
+    let mut complete = true;
+    bsp::memory::init_memory_map(dtb_addr, |name, e| {
+        println!("[ML] Cannot reserve {}: {}", name, e);
+        complete = false;
+    });
+    println!("[ML] Memory map:");
+    memory::map::kernel_map().lock(|memory_map| memory_map.print());
 
-    // Notify `Minipush` to send the binary.
-    for _ in 0..3 {
-        console().write_char(3 as char);
+    // Payloads would be checked against a map that misses what the loader must not overwrite.
+    if !complete {
+        panic!("Memory map incomplete");
     }
 
-       // Read the binary's size.
-    let mut size: u32 = u32::from(console().read_char() as u8);
-    size |= u32::from(console().read_char() as u8) << 8;
-    size |= u32::from(console().read_char() as u8) << 16;
-    size |= u32::from(console().read_char() as u8) << 24;
+    println!(
+        "[ML] Heap: {}",
+        memory::heap::kernel_heap_allocator().stats()
+    );
+    println!("[ML] Boot: {}", memory::boot_times());
+
+    println!("[ML] MMU online. Special regions:");
+    bsp::memory::mmu::virt_mem_layout().print_layout();
+
+    println!(
+        "[ML] Secondary cores parked: {}",
+        cpu::smp::park_secondary_cores()
+    );
 
-    // Trust it's not too big.
-    console().write_char('O');
-    console().write_char('K');
+    #[cfg(feature = "memtest_at_boot")]
+    memtest_free_ram();
 
-    let kernel_addr: *mut u8 = bsp::cpu::BOARD_DEFAULT_LOAD_ADDRESS as *mut u8;
+    DTB_ADDR.store(dtb_addr, Ordering::Relaxed);
+    serve_payload(dtb_addr)
+}
+
+/// Receive a payload, load it and jump to it.
+fn serve_payload(dtb_addr: usize) -> ! {
+    use bsp::console::console;
+    use console::interface::All;
+    use memory::mmu::interface::MMU;
+
+    // A payload that handed control back is gone.
+    memory::map::kernel_map().lock(|memory_map| memory_map.release(RegionKind::Payload));
+
+    let window = bsp::memory::payload_window();
+    let payload = receive_payload(&window);
+
+    // The plan's destinations were free RAM, and are reserved for the payload now.
+    let start = cpu::uptime();
+    unsafe { payload.plan.load(payload.staged.start) };
+    let load_time = cpu::uptime() - start;
+
+    // Make the freshly written code visible to instruction fetches.
+    cpu::cache::clean_dcache_range(payload.plan.span());
+    cpu::cache::invalidate_icache_all();
+    cpu::cache::isb();
+
+    let dtb_addr = forward_device_tree(dtb_addr, &window);
+    let boot_info_addr = write_boot_info(&payload, dtb_addr);
+
+    let guard = payload.flags & protocol::FLAG_GUARD_LOADER != 0;
+    let (level, level_string) = if guard || payload.flags & protocol::FLAG_ENTER_EL1 != 0 {
+        (PrivilegeLevel::Kernel, "EL1h")
+    } else {
+        (
+            PrivilegeLevel::Hypervisor,
+            exception::current_privilege_level().1,
+        )
+    };
+
+    unsafe { hypervisor::end_recall() };
+    if guard {
+        guard_loader();
+    }
+
+    let fp = payload.flags & protocol::FLAG_ENABLE_FP != 0;
     unsafe {
-        // Read the kernel byte by byte.
-        for i in 0..size {
-            *kernel_addr.offset(i as isize) = console().read_char() as u8;
-        }
+        cpu::set_payload_level(level);
+        cpu::set_payload_fp(fp);
     }
 
-    println!("[ML] Loaded! Executing the payload now\n");
+    println!("[ML] Placed the payload in {} us", load_time.as_micros());
+    println!(
+        "[ML] Boot core stack: {}",
+        memory::stack::boot_stack_usage()
+    );
+    println!("[ML] FP/SIMD: {}", if fp { "Enabled" } else { "Trapped" });
+    println!(
+        "[ML] Loaded! Executing the payload now at {}\n",
+        level_string
+    );
     console().flush();
 
-    // Use black magic to get a function pointer.
-    let kernel: extern "C" fn() -> ! = unsafe { core::mem::transmute(kernel_addr as *const ()) };
-
-    // Jump to loaded kernel!
-    kernel()
+    // The payload starts out like it would from the firmware: MMU and caches off, and everything
+    // the loader wrote in DRAM.
+    unsafe {
+        memory::mmu::mmu().disable();
 
+        // Jump to loaded kernel!
+        cpu::jump_to_payload(payload.plan.entry, level, dtb_addr, boot_info_addr)
+    }
 }
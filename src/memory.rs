@@ -1,24 +1,115 @@
 //! Memory Management.
 
-use core::ops::Range;
+#[cfg(all(target_arch = "aarch64", target_os = "none"))]
+#[path = "_arch/aarch64/memory.rs"]
+mod arch_memory;
+
+#[cfg(not(target_os = "none"))]
+#[path = "_arch/host/memory.rs"]
+mod arch_memory;
+
+pub use arch_memory::*;
+
+pub mod heap;
+pub mod map;
+pub mod memtest;
+pub mod mmu;
+pub mod stack;
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// How long the loader took to put itself in place at boot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BootTimes {
+    /// Copying the binary and applying its relocations.
+    pub relocation: Duration,
+
+    /// Zeroing `.bss`.
+    pub zero_bss: Duration,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static RELOCATION_NANOS: AtomicU64 = AtomicU64::new(0);
+static ZERO_BSS_NANOS: AtomicU64 = AtomicU64::new(0);
 
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
 
-/// Zero out a memory region.
-///
-/// # Safety
-///
-/// - `range.start` and `range.end` must be valid.
-/// - `range.start` and `range.end` must be `T` aligned.
-pub unsafe fn zero_volatile<T>(range: Range<*mut T>)
-where T: From<u8>
-{
-    let mut ptr = range.start;
-
-    while ptr < range.end {
-        core::ptr::write_volatile(ptr, T::from(0));
-        ptr = ptr.offset(1);
+impl fmt::Display for BootTimes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Relocation {} us, zeroing .bss {} us",
+            self.relocation.as_micros(),
+            self.zero_bss.as_micros()
+        )
+    }
+}
+
+/// Remember how long the loader took to put itself in place. Called from `runtime_init()`.
+pub fn set_boot_times(times: BootTimes) {
+    RELOCATION_NANOS.store(times.relocation.as_nanos() as u64, Ordering::Relaxed);
+    ZERO_BSS_NANOS.store(times.zero_bss.as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// Return how long the loader took to put itself in place.
+pub fn boot_times() -> BootTimes {
+    BootTimes {
+        relocation: Duration::from_nanos(RELOCATION_NANOS.load(Ordering::Relaxed)),
+        zero_bss: Duration::from_nanos(ZERO_BSS_NANOS.load(Ordering::Relaxed)),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `copy()` handles overlapping ranges in both directions, at any alignment.
+    #[test_case]
+    fn copy_overlapping() {
+        let mut x = [0u8; 256];
+        for (i, byte) in x.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        // Upwards, by an odd distance, which also takes the backward copy.
+        unsafe { copy(x.as_mut_ptr().add(3), x.as_ptr().add(1), 200) };
+        assert!((0..200).all(|i| x[i + 3] == (i + 1) as u8));
+
+        // Downwards, by a whole number of words.
+        unsafe { copy(x.as_mut_ptr(), x.as_ptr().add(16), 128) };
+        assert!((0..128).all(|i| x[i] == (i + 16 - 2) as u8));
+    }
+
+    /// `fill()` sets exactly the given bytes, and zeroing leaves everything around alone.
+    #[test_case]
+    fn fill_exact() {
+        let mut x = [0xffu8; 1024];
+
+        unsafe { fill(x.as_mut_ptr().add(5), 0, 1000) };
+        assert!(x[..5].iter().all(|b| *b == 0xff));
+        assert!(x[5..1005].iter().all(|b| *b == 0));
+        assert!(x[1005..].iter().all(|b| *b == 0xff));
+
+        unsafe { fill(x.as_mut_ptr().add(1), 0x5a, 63) };
+        assert_eq!(x[0], 0xff);
+        assert!(x[1..64].iter().all(|b| *b == 0x5a));
+        assert_eq!(x[64], 0);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,381 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Heap.
+//!
+//! A first-fit allocator over the heap region that the linker script sets aside. Free blocks form a
+//! list that is sorted by address and lives in the free memory itself, so that neighbouring blocks
+//! can be merged when memory is given back.
+//!
+//! Every block is a multiple of `BLOCK_ALIGN` in size and starts on such a boundary. Whatever is
+//! left over when a block is split is therefore always large enough to hold a `FreeBlock`.
+
+use crate::synchronization::{interface::Mutex, IRQSafeLock};
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    fmt, mem,
+    ops::Range,
+    ptr,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The header of a free block, at the block's start.
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+/// The size and alignment of every block.
+const BLOCK_ALIGN: usize = 16;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Usage statistics, in bytes unless noted otherwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// The size of the heap.
+    pub size: usize,
+
+    /// What is allocated right now, including the rounding to `BLOCK_ALIGN`.
+    pub used: usize,
+
+    /// The most that was ever allocated at once.
+    pub peak_used: usize,
+
+    /// The number of live allocations.
+    pub allocations: usize,
+
+    /// The number of allocations that could not be served.
+    pub failures: usize,
+}
+
+/// The allocator itself, without any locking.
+pub struct LinkedListHeap {
+    head: *mut FreeBlock,
+    stats: HeapStats,
+}
+
+/// The loader's `#[global_allocator]`.
+pub struct HeapAllocator {
+    inner: IRQSafeLock<LinkedListHeap>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(target_os = "none")]
+#[global_allocator]
+static KERNEL_HEAP_ALLOCATOR: HeapAllocator = HeapAllocator::new();
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+const fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+/// The block size that serves `layout`.
+fn block_size(layout: &Layout) -> usize {
+    align_up(layout.size().max(mem::size_of::<FreeBlock>()), BLOCK_ALIGN)
+}
+
+impl LinkedListHeap {
+    /// Put the block at `addr` into the free list, merging it with its neighbours.
+    ///
+    /// # Safety
+    ///
+    /// - The block must be part of the heap, and must not be in the free list already.
+    unsafe fn free(&mut self, addr: usize, size: usize) {
+        // Find the free blocks right before and after the new one.
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut next = self.head;
+        while !next.is_null() && (next as usize) < addr {
+            prev = next;
+            next = (*next).next;
+        }
+
+        let block = addr as *mut FreeBlock;
+        block.write(FreeBlock { size, next });
+
+        if !next.is_null() && addr + size == next as usize {
+            (*block).size += (*next).size;
+            (*block).next = (*next).next;
+        }
+
+        if prev.is_null() {
+            self.head = block;
+        } else if prev as usize + (*prev).size == addr {
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        } else {
+            (*prev).next = block;
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} KiB used ({} KiB at most), {} allocations, {} failed",
+            self.used / 1024,
+            self.size / 1024,
+            self.peak_used / 1024,
+            self.allocations,
+            self.failures
+        )
+    }
+}
+
+impl LinkedListHeap {
+    /// Create an instance without any memory.
+    pub const fn empty() -> Self {
+        Self {
+            head: ptr::null_mut(),
+            stats: HeapStats {
+                size: 0,
+                used: 0,
+                peak_used: 0,
+                allocations: 0,
+                failures: 0,
+            },
+        }
+    }
+
+    /// Hand the memory in `region` to the heap, forgetting all previous allocations.
+    ///
+    /// # Safety
+    ///
+    /// - `region` must be unused memory, for as long as the heap lives.
+    /// - No previous allocation may be used anymore.
+    pub unsafe fn init(&mut self, region: Range<usize>) {
+        let start = align_up(region.start, BLOCK_ALIGN);
+        let end = region.end & !(BLOCK_ALIGN - 1);
+
+        *self = Self::empty();
+        if end > start {
+            self.stats.size = end - start;
+            self.free(start, end - start);
+        }
+    }
+
+    /// Allocate a block for `layout`. Returns a null pointer if the heap has no room for it.
+    pub fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        let size = block_size(&layout);
+        let align = layout.align().max(BLOCK_ALIGN);
+
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut current = self.head;
+        while !current.is_null() {
+            let block_start = current as usize;
+            let (block_size, next) = unsafe { ((*current).size, (*current).next) };
+            let block_end = block_start + block_size;
+            let addr = align_up(block_start, align);
+
+            if addr.checked_add(size).map_or(false, |end| end <= block_end) {
+                // Take the block out of the list, and give back what is left on either side.
+                unsafe {
+                    if prev.is_null() {
+                        self.head = next;
+                    } else {
+                        (*prev).next = next;
+                    }
+
+                    if addr > block_start {
+                        self.free(block_start, addr - block_start);
+                    }
+                    if addr + size < block_end {
+                        self.free(addr + size, block_end - addr - size);
+                    }
+                }
+
+                self.stats.used += size;
+                self.stats.peak_used = self.stats.peak_used.max(self.stats.used);
+                self.stats.allocations += 1;
+
+                return addr as *mut u8;
+            }
+
+            prev = current;
+            current = next;
+        }
+
+        self.stats.failures += 1;
+        ptr::null_mut()
+    }
+
+    /// Give the block at `addr` back.
+    ///
+    /// # Safety
+    ///
+    /// - `addr` must have been returned by `alloc()` for the same `layout`.
+    pub unsafe fn dealloc(&mut self, addr: *mut u8, layout: Layout) {
+        let size = block_size(&layout);
+
+        self.free(addr as usize, size);
+
+        self.stats.used -= size;
+        self.stats.allocations -= 1;
+    }
+
+    /// Return the usage statistics.
+    pub fn stats(&self) -> HeapStats {
+        self.stats
+    }
+}
+
+// The free list is only ever touched by whoever holds the heap.
+unsafe impl Send for LinkedListHeap {}
+
+impl HeapAllocator {
+    /// Create an instance. It has no memory until `init()` is called.
+    pub const fn new() -> Self {
+        Self {
+            inner: IRQSafeLock::new(LinkedListHeap::empty()),
+        }
+    }
+
+    /// Hand the memory in `region` to the heap, forgetting all previous allocations.
+    ///
+    /// # Safety
+    ///
+    /// - `region` must be unused memory, for as long as the heap lives.
+    /// - No previous allocation may be used anymore.
+    pub unsafe fn init(&self, region: Range<usize>) {
+        let mut r = &self.inner;
+        r.lock(|heap| heap.init(region))
+    }
+
+    /// Return the usage statistics.
+    pub fn stats(&self) -> HeapStats {
+        let mut r = &self.inner;
+        r.lock(|heap| heap.stats())
+    }
+}
+
+unsafe impl GlobalAlloc for HeapAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut r = &self.inner;
+        r.lock(|heap| heap.alloc(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let mut r = &self.inner;
+        r.lock(|heap| heap.dealloc(ptr, layout))
+    }
+}
+
+/// Return a reference to the loader's heap.
+#[cfg(target_os = "none")]
+pub fn kernel_heap_allocator() -> &'static HeapAllocator {
+    &KERNEL_HEAP_ALLOCATOR
+}
+
+/// Report a failed allocation through the panic console, and stop.
+#[cfg(target_os = "none")]
+#[alloc_error_handler]
+fn alloc_error(layout: Layout) -> ! {
+    panic!(
+        "Cannot allocate {} bytes aligned to {}. Heap: {}",
+        layout.size(),
+        layout.align(),
+        kernel_heap_allocator().stats()
+    )
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEAP_SIZE: usize = 4096;
+
+    #[repr(align(16))]
+    struct Memory([u8; HEAP_SIZE]);
+
+    fn heap_in(memory: &mut Memory) -> LinkedListHeap {
+        let start = memory.0.as_mut_ptr() as usize;
+        let mut heap = LinkedListHeap::empty();
+
+        unsafe { heap.init(start..start + HEAP_SIZE) };
+
+        heap
+    }
+
+    /// Freed blocks merge again, no matter in which order they are given back.
+    #[test_case]
+    fn alloc_dealloc_merges() {
+        let mut memory = Memory([0; HEAP_SIZE]);
+        let mut heap = heap_in(&mut memory);
+        let layout = Layout::from_size_align(100, 8).unwrap();
+
+        let a = heap.alloc(layout);
+        let b = heap.alloc(layout);
+        let c = heap.alloc(layout);
+        assert!(!a.is_null() && !b.is_null() && !c.is_null());
+        assert_eq!(heap.stats().used, 3 * 112);
+        assert_eq!(heap.stats().allocations, 3);
+
+        unsafe {
+            heap.dealloc(a, layout);
+            heap.dealloc(c, layout);
+            heap.dealloc(b, layout);
+        }
+        assert_eq!(heap.stats().used, 0);
+        assert_eq!(heap.stats().peak_used, 3 * 112);
+
+        // Only a single block left, so the whole heap can be allocated again.
+        let all = Layout::from_size_align(HEAP_SIZE, 16).unwrap();
+        assert!(!heap.alloc(all).is_null());
+    }
+
+    /// Large alignments are honoured, and the skipped memory stays usable.
+    #[test_case]
+    fn alloc_aligned() {
+        let mut memory = Memory([0; HEAP_SIZE]);
+        let mut heap = heap_in(&mut memory);
+        let small = Layout::from_size_align(16, 16).unwrap();
+        let aligned = Layout::from_size_align(64, 1024).unwrap();
+
+        let a = heap.alloc(small);
+        let b = heap.alloc(aligned);
+        assert_eq!(b as usize % 1024, 0);
+
+        unsafe {
+            heap.dealloc(a, small);
+            heap.dealloc(b, aligned);
+        }
+        let all = Layout::from_size_align(HEAP_SIZE, 16).unwrap();
+        assert!(!heap.alloc(all).is_null());
+    }
+
+    /// An allocation that does not fit fails without disturbing the heap.
+    #[test_case]
+    fn alloc_too_big() {
+        let mut memory = Memory([0; HEAP_SIZE]);
+        let mut heap = heap_in(&mut memory);
+
+        let too_big = Layout::from_size_align(HEAP_SIZE + 1, 8).unwrap();
+        assert!(heap.alloc(too_big).is_null());
+        assert_eq!(heap.stats().failures, 1);
+        assert_eq!(heap.stats().used, 0);
+
+        let all = Layout::from_size_align(HEAP_SIZE, 8).unwrap();
+        assert!(!heap.alloc(all).is_null());
+    }
+}
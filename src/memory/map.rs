@@ -0,0 +1,374 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Physical memory map.
+//!
+//! Knows the RAM and every region in the address space that is spoken for: firmware data, the
+//! VideoCore's share of the RAM, the loader with its stacks and heap, the device tree, devices, the
+//! payload and its boot info. Regions never overlap. Whatever is left in the RAM is free for
+//! payloads.
+//!
+//! The BSP fills the map at boot, see `bsp::memory::init_memory_map()`.
+
+use crate::synchronization::{interface::Mutex, IRQSafeLock};
+use core::{fmt, iter, ops::Range};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The maximum number of regions.
+pub const MAX_REGIONS: usize = 24;
+
+/// What a region is used for. The values are what the payload finds in the boot info.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum RegionKind {
+    /// Data the firmware left behind, e.g. the spin-table.
+    Firmware = 1,
+
+    /// The VideoCore's share of the RAM.
+    VideoCore = 2,
+
+    /// The loader's code, data, stacks or heap.
+    Loader = 3,
+
+    /// The device tree that is forwarded to the payload.
+    DeviceTree = 4,
+
+    /// Memory-mapped devices.
+    Mmio = 5,
+
+    /// A loaded payload.
+    Payload = 6,
+
+    /// The boot info that is handed to the payload.
+    BootInfo = 7,
+}
+
+/// A named part of the address space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    /// What the region holds, for humans.
+    pub name: &'static str,
+
+    /// What the region is used for.
+    pub kind: RegionKind,
+
+    /// The first address.
+    pub start: usize,
+
+    /// The first address after the region.
+    pub end: usize,
+}
+
+/// Why a region could not be reserved, or a range cannot be loaded to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The range overlaps the given region.
+    Overlap(Region),
+
+    /// The range is not completely in the RAM.
+    OutsideRam,
+
+    /// There is no room for another region.
+    Full,
+}
+
+/// The memory map itself.
+pub struct MemoryMap {
+    ram: Range<usize>,
+    regions: [Region; MAX_REGIONS],
+    num_regions: usize,
+}
+
+/// The loader's memory map.
+pub struct KernelMemoryMap {
+    inner: IRQSafeLock<MemoryMap>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static KERNEL_MEMORY_MAP: KernelMemoryMap = KernelMemoryMap {
+    inner: IRQSafeLock::new(MemoryMap::new()),
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+const EMPTY_REGION: Region = Region {
+    name: "",
+    kind: RegionKind::Firmware,
+    start: 0,
+    end: 0,
+};
+
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Display for RegionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RegionKind::Firmware => "Firmware",
+            RegionKind::VideoCore => "VideoCore",
+            RegionKind::Loader => "Loader",
+            RegionKind::DeviceTree => "DTB",
+            RegionKind::Mmio => "MMIO",
+            RegionKind::Payload => "Payload",
+            RegionKind::BootInfo => "Boot info",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "      {:#010x} - {:#010x} | {:>9} | {}",
+            self.start,
+            self.end - 1,
+            self.kind,
+            self.name
+        )
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Overlap(region) => write!(
+                f,
+                "Overlaps {} at {:#x}..{:#x}",
+                region.name, region.start, region.end
+            ),
+            Error::OutsideRam => write!(f, "Outside of the RAM"),
+            Error::Full => write!(f, "Too many regions"),
+        }
+    }
+}
+
+impl Region {
+    /// The region as a range.
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+}
+
+impl MemoryMap {
+    /// Create an instance without any RAM or regions.
+    pub const fn new() -> Self {
+        Self {
+            ram: 0..0,
+            regions: [EMPTY_REGION; MAX_REGIONS],
+            num_regions: 0,
+        }
+    }
+
+    /// Start over with `ram` and no regions.
+    pub fn reset(&mut self, ram: Range<usize>) {
+        *self = Self::new();
+        self.ram = ram;
+    }
+
+    /// The RAM the ARM cores can use.
+    pub fn ram(&self) -> Range<usize> {
+        self.ram.clone()
+    }
+
+    /// The regions, sorted by address.
+    pub fn regions(&self) -> &[Region] {
+        &self.regions[..self.num_regions]
+    }
+
+    /// The first region that overlaps `range`, if any.
+    pub fn overlapping(&self, range: &Range<usize>) -> Option<&Region> {
+        self.regions()
+            .iter()
+            .find(|region| overlaps(&region.range(), range))
+    }
+
+    /// Reserve `range` as the region `name`. Empty ranges are not recorded.
+    pub fn reserve(
+        &mut self,
+        name: &'static str,
+        kind: RegionKind,
+        range: Range<usize>,
+    ) -> Result<(), Error> {
+        if range.start >= range.end {
+            return Ok(());
+        }
+        if let Some(region) = self.overlapping(&range) {
+            return Err(Error::Overlap(*region));
+        }
+        if self.num_regions == MAX_REGIONS {
+            return Err(Error::Full);
+        }
+
+        let index = self
+            .regions()
+            .iter()
+            .position(|region| region.start > range.start)
+            .unwrap_or(self.num_regions);
+        self.regions.copy_within(index..self.num_regions, index + 1);
+        self.regions[index] = Region {
+            name,
+            kind,
+            start: range.start,
+            end: range.end,
+        };
+        self.num_regions += 1;
+
+        Ok(())
+    }
+
+    /// Give back all regions of `kind`.
+    pub fn release(&mut self, kind: RegionKind) {
+        let mut kept = 0;
+        for i in 0..self.num_regions {
+            if self.regions[i].kind != kind {
+                self.regions[kept] = self.regions[i];
+                kept += 1;
+            }
+        }
+
+        self.num_regions = kept;
+    }
+
+    /// The parts of the RAM that no region covers, sorted by address.
+    pub fn free_ranges(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        let ram_end = self.ram.end;
+        let mut next = self.ram.start;
+
+        self.regions()
+            .iter()
+            .map(Region::range)
+            .chain(iter::once(ram_end..ram_end))
+            .filter_map(move |taken| {
+                let free = next..taken.start.min(ram_end);
+                next = next.max(taken.end);
+
+                if free.start < free.end {
+                    Some(free)
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Check that `range` is free RAM.
+    pub fn check_free(&self, range: &Range<usize>) -> Result<(), Error> {
+        if range.start < self.ram.start || range.end > self.ram.end {
+            return Err(Error::OutsideRam);
+        }
+
+        match self.overlapping(range) {
+            Some(region) => Err(Error::Overlap(*region)),
+            None => Ok(()),
+        }
+    }
+
+    /// Print the memory map.
+    pub fn print(&self) {
+        use crate::println;
+
+        if self.ram.is_empty() {
+            println!("      No RAM");
+        } else {
+            println!(
+                "      {:#010x} - {:#010x} |       RAM |",
+                self.ram.start,
+                self.ram.end - 1
+            );
+        }
+        for region in self.regions() {
+            println!("{}", region);
+        }
+    }
+}
+
+impl KernelMemoryMap {
+    /// Grant access to the memory map for the duration of `f`.
+    pub fn lock<R>(&self, f: impl FnOnce(&mut MemoryMap) -> R) -> R {
+        let mut r = &self.inner;
+        r.lock(f)
+    }
+}
+
+/// Return a reference to the loader's memory map.
+pub fn kernel_map() -> &'static KernelMemoryMap {
+    &KERNEL_MEMORY_MAP
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map() -> MemoryMap {
+        let mut map = MemoryMap::new();
+        map.reset(0..0x1000);
+        map.reserve("B", RegionKind::Loader, 0x800..0x900).unwrap();
+        map.reserve("A", RegionKind::Firmware, 0x0..0x100).unwrap();
+        map
+    }
+
+    /// Regions are kept sorted, and may not overlap.
+    #[test_case]
+    fn reserve_sorts_and_rejects_overlaps() {
+        let mut map = map();
+
+        assert_eq!(map.regions()[0].name, "A");
+        assert_eq!(map.regions()[1].name, "B");
+        assert_eq!(
+            map.reserve("C", RegionKind::Payload, 0x8ff..0xa00),
+            Err(Error::Overlap(map.regions()[1]))
+        );
+        assert_eq!(map.reserve("C", RegionKind::Payload, 0x900..0xa00), Ok(()));
+        assert_eq!(map.regions()[2].name, "C");
+    }
+
+    /// Only free RAM passes the check.
+    #[test_case]
+    fn check_free() {
+        let mut map = map();
+
+        assert_eq!(map.check_free(&(0x100..0x800)), Ok(()));
+        assert_eq!(
+            map.check_free(&(0x100..0x801)),
+            Err(Error::Overlap(map.regions()[1]))
+        );
+        assert_eq!(map.check_free(&(0xf00..0x1001)), Err(Error::OutsideRam));
+
+        map.release(RegionKind::Loader);
+        assert_eq!(map.check_free(&(0x100..0x1000)), Ok(()));
+    }
+
+    /// The free ranges are the gaps between the regions, within the RAM.
+    #[test_case]
+    fn free_ranges() {
+        let mut map = map();
+        assert_eq!(
+            map.reserve("MMIO", RegionKind::Mmio, 0x2000..0x3000),
+            Ok(())
+        );
+
+        let mut free = map.free_ranges();
+        assert_eq!(free.next(), Some(0x100..0x800));
+        assert_eq!(free.next(), Some(0x900..0x1000));
+        assert_eq!(free.next(), None);
+    }
+}
@@ -0,0 +1,298 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! RAM test.
+//!
+//! Every test writes patterns to each 64 bit word of a range and reads them back:
+//!
+//! - *Walking ones*: Word `i` holds `1 << (i % 64)`, so that every data line carries a lone one
+//!   within any 64 neighbouring words.
+//! - *Walking zeros*: The same, inverted.
+//! - *Address in address*: Every word holds its own address, then its complement. Address lines
+//!   that are stuck or shorted make words alias each other.
+//! - *March C-*: ⇕(w0) ⇑(r0,w1) ⇑(r1,w0) ⇓(r0,w1) ⇓(r1,w0) ⇕(r0), with words of all zeros and all
+//!   ones. Finds stuck-at, transition and most coupling faults.
+//!
+//! The data cache is cleaned and invalidated after every pass that writes, so that the reads come
+//! from DRAM.
+
+use crate::cpu;
+use core::{fmt, mem, ops::Range, ptr};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A test.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Test {
+    /// A lone one in every word.
+    WalkingOnes,
+
+    /// A lone zero in every word.
+    WalkingZeros,
+
+    /// Every word's own address.
+    AddressInAddress,
+
+    /// The March C- algorithm.
+    MarchCMinus,
+}
+
+/// All tests, in the order in which they are best run.
+pub const ALL_TESTS: [Test; 4] = [
+    Test::WalkingOnes,
+    Test::WalkingZeros,
+    Test::AddressInAddress,
+    Test::MarchCMinus,
+];
+
+/// A word that did not read back what was written to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Failure {
+    /// The word's address.
+    pub addr: usize,
+
+    /// What was written.
+    pub expected: u64,
+
+    /// What was read.
+    pub actual: u64,
+}
+
+/// The result of a test.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    /// The test that was run.
+    pub test: Test,
+
+    /// The number of words tested.
+    pub words: usize,
+
+    /// The number of failed reads.
+    pub failures: usize,
+
+    /// The bits that were wrong in any of the failed reads.
+    pub bits: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A test in progress.
+struct Run<F: FnMut(&Failure)> {
+    start: usize,
+    outcome: Outcome,
+    on_failure: F,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+const WORD_SIZE: usize = mem::size_of::<u64>();
+
+impl<F: FnMut(&Failure)> Run<F> {
+    fn addr(&self, i: usize) -> usize {
+        self.start + i * WORD_SIZE
+    }
+
+    /// Push what was written out of the data cache.
+    fn flush(&self) {
+        cpu::cache::clean_invalidate_dcache_range(self.start..self.addr(self.outcome.words));
+    }
+
+    unsafe fn write(&self, i: usize, value: u64) {
+        ptr::write_volatile(self.addr(i) as *mut u64, value);
+    }
+
+    unsafe fn check(&mut self, i: usize, expected: u64) {
+        let addr = self.addr(i);
+        let actual = ptr::read_volatile(addr as *const u64);
+        if actual == expected {
+            return;
+        }
+
+        self.outcome.failures += 1;
+        self.outcome.bits |= actual ^ expected;
+        (self.on_failure)(&Failure {
+            addr,
+            expected,
+            actual,
+        });
+    }
+
+    /// Write `pattern(i)` to every word `i`, then read it all back.
+    unsafe fn write_and_check(&mut self, pattern: impl Fn(usize) -> u64) {
+        for i in 0..self.outcome.words {
+            self.write(i, pattern(i));
+        }
+        self.flush();
+
+        for i in 0..self.outcome.words {
+            self.check(i, pattern(i));
+        }
+    }
+
+    /// A March element: Go through the words upwards or downwards, first reading `read` from each,
+    /// then writing `write` to it.
+    unsafe fn march(&mut self, upwards: bool, read: Option<u64>, write: Option<u64>) {
+        for n in 0..self.outcome.words {
+            let i = if upwards {
+                n
+            } else {
+                self.outcome.words - 1 - n
+            };
+
+            if let Some(value) = read {
+                self.check(i, value);
+            }
+            if let Some(value) = write {
+                self.write(i, value);
+            }
+        }
+
+        if write.is_some() {
+            self.flush();
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Display for Test {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Test::WalkingOnes => "Walking ones",
+            Test::WalkingZeros => "Walking zeros",
+            Test::AddressInAddress => "Address in address",
+            Test::MarchCMinus => "March C-",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl Failure {
+    /// The bits that were wrong.
+    pub fn bits(&self) -> u64 {
+        self.expected ^ self.actual
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:#010x}: wrote {:#018x}, read {:#018x}, bits {:#018x}",
+            self.addr,
+            self.expected,
+            self.actual,
+            self.bits()
+        )
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} KiB ", self.test, self.words * WORD_SIZE / 1024)?;
+
+        if self.failures == 0 {
+            write!(f, "passed")
+        } else {
+            write!(
+                f,
+                "FAILED, {} bad reads, bits {:#018x}",
+                self.failures, self.bits
+            )
+        }
+    }
+}
+
+/// Run `test` over the whole words in `range`, and call `on_failure` for every bad read.
+///
+/// # Safety
+///
+/// - Nobody may be using `range`. Its contents are lost.
+pub unsafe fn run(test: Test, range: Range<usize>, on_failure: impl FnMut(&Failure)) -> Outcome {
+    let start = (range.start + WORD_SIZE - 1) & !(WORD_SIZE - 1);
+    let end = range.end & !(WORD_SIZE - 1);
+
+    let mut run = Run {
+        start,
+        outcome: Outcome {
+            test,
+            words: end.saturating_sub(start) / WORD_SIZE,
+            failures: 0,
+            bits: 0,
+        },
+        on_failure,
+    };
+
+    match test {
+        Test::WalkingOnes => run.write_and_check(|i| 1 << (i % 64)),
+        Test::WalkingZeros => run.write_and_check(|i| !(1 << (i % 64))),
+        Test::AddressInAddress => {
+            let start = run.start;
+            run.write_and_check(|i| (start + i * WORD_SIZE) as u64);
+            run.write_and_check(|i| !(start + i * WORD_SIZE) as u64);
+        }
+        Test::MarchCMinus => {
+            run.march(true, None, Some(0));
+            run.march(true, Some(0), Some(!0));
+            run.march(true, Some(!0), Some(0));
+            run.march(false, Some(0), Some(!0));
+            run.march(false, Some(!0), Some(0));
+            run.march(true, Some(0), None);
+        }
+    }
+
+    run.outcome
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORDS: usize = 256;
+
+    /// Good memory passes every test, and March C- leaves it zeroed.
+    #[test_case]
+    fn good_memory_passes() {
+        let mut memory = [0x55u64; WORDS];
+        let start = memory.as_mut_ptr() as usize;
+
+        for test in ALL_TESTS.iter() {
+            let outcome = unsafe {
+                run(*test, start..start + WORDS * 8, |f| {
+                    panic!("{}: {}", test, f)
+                })
+            };
+
+            assert_eq!(outcome.words, WORDS);
+            assert_eq!(outcome.failures, 0);
+            assert_eq!(outcome.bits, 0);
+        }
+        assert!(memory.iter().all(|word| *word == 0));
+    }
+
+    /// Only the whole words in the range are touched.
+    #[test_case]
+    fn partial_words_are_left_alone() {
+        let mut memory = [0x55u64; 4];
+        let start = memory.as_mut_ptr() as usize;
+
+        let outcome = unsafe { run(Test::MarchCMinus, start + 1..start + 31, |_| ()) };
+
+        assert_eq!(outcome.words, 2);
+        assert_eq!(memory, [0x55, 0, 0, 0x55]);
+    }
+}
@@ -0,0 +1,249 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Memory Management Unit.
+//!
+//! The loader maps the address space one-to-one, only to get the caches going. It is the `BSP` that
+//! knows which parts of it are DRAM and which are device MMIO (`bsp::memory::mmu`), and the `arch`
+//! code that turns this into translation tables.
+//!
+//! The same layout is used for the stage 2 translation that keeps a payload at EL1 away from the
+//! loader, see `hypervisor`.
+
+#[cfg(all(target_arch = "aarch64", target_os = "none"))]
+#[path = "../_arch/aarch64/memory/mmu.rs"]
+mod arch_mmu;
+
+#[cfg(all(target_arch = "aarch64", target_os = "none"))]
+pub use arch_mmu::*;
+
+use core::{fmt, ops::RangeInclusive};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Memory Management interfaces.
+pub mod interface {
+
+    use core::ops::Range;
+
+    /// MMU functions.
+    pub trait MMU {
+        /// Set up the translation tables and turn on the MMU and the caches.
+        ///
+        /// # Safety
+        ///
+        /// - Changes the hardware's global state.
+        unsafe fn init(&self) -> Result<(), &'static str>;
+
+        /// Turn off the MMU and the caches again.
+        ///
+        /// Everything that was written while the caches were on is in DRAM afterwards, and no stale
+        /// instructions, data or translations are left behind for whoever runs next.
+        ///
+        /// # Safety
+        ///
+        /// - Changes the hardware's global state.
+        unsafe fn disable(&self);
+    }
+
+    /// Stage 2 translation, which confines a payload at EL1.
+    pub trait Stage2 {
+        /// Set up the stage 2 translation tables. They map the address space one-to-one, like the
+        /// loader's own tables, except for the blocks that overlap `hidden`.
+        ///
+        /// # Safety
+        ///
+        /// - Must not be called while any core translates through the tables.
+        unsafe fn init(&self, hidden: &Range<usize>) -> Result<(), &'static str>;
+
+        /// Translate the executing core's EL1 and EL0 accesses through the tables.
+        ///
+        /// # Safety
+        ///
+        /// - Changes the hardware's global state.
+        unsafe fn enable(&self);
+
+        /// Unmap everything, for all cores. Any core that translates through the tables faults on
+        /// its next memory access.
+        ///
+        /// # Safety
+        ///
+        /// - Changes the hardware's global state.
+        unsafe fn revoke(&self);
+
+        /// Turn off stage 2 translation for the executing core.
+        ///
+        /// # Safety
+        ///
+        /// - Changes the hardware's global state.
+        unsafe fn disable(&self);
+    }
+}
+
+/// Architecture agnostic memory attributes.
+#[allow(missing_docs)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MemAttributes {
+    CacheableDRAM,
+    Device,
+}
+
+/// Architecture agnostic access permissions.
+#[allow(missing_docs)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AccessPermissions {
+    ReadOnly,
+    ReadWrite,
+}
+
+/// Collection of memory attributes.
+#[allow(missing_docs)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct AttributeFields {
+    pub mem_attributes: MemAttributes,
+    pub acc_perms: AccessPermissions,
+    pub execute_never: bool,
+}
+
+/// An address range with the attributes it is mapped with.
+#[allow(missing_docs)]
+pub struct RangeDescriptor {
+    pub name: &'static str,
+    pub virtual_range: fn() -> RangeInclusive<usize>,
+    pub attribute_fields: AttributeFields,
+}
+
+/// Type for expressing the kernel's virtual memory layout.
+pub struct KernelVirtualLayout {
+    /// The last (inclusive) address of the address space.
+    max_virt_addr_inclusive: usize,
+
+    /// Ranges that are mapped with attributes other than the default.
+    inner: &'static [RangeDescriptor],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Default for AttributeFields {
+    fn default() -> AttributeFields {
+        AttributeFields {
+            mem_attributes: MemAttributes::CacheableDRAM,
+            acc_perms: AccessPermissions::ReadWrite,
+            execute_never: false,
+        }
+    }
+}
+
+/// Human-readable output of a RangeDescriptor.
+impl fmt::Display for RangeDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Call the function to which self.range points, and dereference the result, which causes
+        // Rust to copy the value.
+        let start = *(self.virtual_range)().start();
+        let end = *(self.virtual_range)().end();
+
+        let attr = match self.attribute_fields.mem_attributes {
+            MemAttributes::CacheableDRAM => "C",
+            MemAttributes::Device => "Dev",
+        };
+
+        let acc_p = match self.attribute_fields.acc_perms {
+            AccessPermissions::ReadOnly => "RO",
+            AccessPermissions::ReadWrite => "RW",
+        };
+
+        let xn = if self.attribute_fields.execute_never {
+            "PXN"
+        } else {
+            "PX"
+        };
+
+        write!(
+            f,
+            "[ML]     {:#010x} - {:#010x} | {: <3} {} {: <3} | {}",
+            start, end, attr, acc_p, xn, self.name
+        )
+    }
+}
+
+impl KernelVirtualLayout {
+    /// Create a new instance.
+    pub const fn new(max: usize, layout: &'static [RangeDescriptor]) -> Self {
+        Self {
+            max_virt_addr_inclusive: max,
+            inner: layout,
+        }
+    }
+
+    /// The last (inclusive) address that is mapped.
+    pub fn max_virt_addr_inclusive(&self) -> usize {
+        self.max_virt_addr_inclusive
+    }
+
+    /// For a virtual address, find and return the attributes it is mapped with.
+    ///
+    /// Addresses that are not covered by any of the layout's ranges are cacheable DRAM.
+    pub fn virt_addr_properties(&self, virt_addr: usize) -> Result<AttributeFields, &'static str> {
+        if virt_addr > self.max_virt_addr_inclusive {
+            return Err("Address out of range");
+        }
+
+        for i in self.inner.iter() {
+            if (i.virtual_range)().contains(&virt_addr) {
+                return Ok(i.attribute_fields);
+            }
+        }
+
+        Ok(AttributeFields::default())
+    }
+
+    /// Print the memory layout.
+    pub fn print_layout(&self) {
+        use crate::println;
+
+        for i in self.inner.iter() {
+            println!("{}", i);
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEVICE: AttributeFields = AttributeFields {
+        mem_attributes: MemAttributes::Device,
+        acc_perms: AccessPermissions::ReadWrite,
+        execute_never: true,
+    };
+
+    static LAYOUT: KernelVirtualLayout = KernelVirtualLayout::new(
+        0xFFFF,
+        &[RangeDescriptor {
+            name: "Device MMIO",
+            virtual_range: || RangeInclusive::new(0xF000, 0xFFFF),
+            attribute_fields: DEVICE,
+        }],
+    );
+
+    /// Addresses inside a descriptor's range get its attributes, all others the default.
+    #[test_case]
+    fn virt_addr_properties_picks_range() {
+        assert_eq!(LAYOUT.virt_addr_properties(0xF000), Ok(DEVICE));
+        assert_eq!(LAYOUT.virt_addr_properties(0xFFFF), Ok(DEVICE));
+        assert_eq!(
+            LAYOUT.virt_addr_properties(0xEFFF),
+            Ok(AttributeFields::default())
+        );
+        assert!(LAYOUT.virt_addr_properties(0x1_0000).is_err());
+    }
+}
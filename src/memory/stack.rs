@@ -0,0 +1,155 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Stack painting.
+//!
+//! Before the boot core switches to its stack, the stack is painted with a pattern, and its lowest
+//! word is set to a canary. How much of the paint is left tells how deep the stack ever got, and a
+//! missing canary that it overflowed, or came close to it.
+//!
+//! Below the stack is a guard page that the MMU leaves unmapped, so that an overflow that jumps
+//! over the canary faults as well. The fault is handled on an exception stack of its own.
+
+use core::{fmt, mem, ops::Range, ptr};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// What an unused stack word holds.
+pub const PAINT: u64 = 0xa5a5_a5a5_a5a5_a5a5;
+
+/// What the lowest word of the stack holds, as long as the stack did not overflow.
+pub const CANARY: u64 = 0x5ca1_ab1e_c0de_cafe;
+
+/// How much of a painted stack was used, in bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackUsage {
+    /// The size of the stack.
+    pub size: usize,
+
+    /// The most that was ever used at once, as far as the paint tells.
+    pub peak_used: usize,
+
+    /// Whether the canary is still in place.
+    pub canary_intact: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Display for StackUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} KiB used at most",
+            (self.peak_used + 1023) / 1024,
+            self.size / 1024
+        )?;
+
+        if !self.canary_intact {
+            write!(f, ", canary overwritten")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Paint `stack`, and put the canary in its lowest word.
+///
+/// Does not touch any statics, so that it can run before the loader is relocated.
+///
+/// # Safety
+///
+/// - `stack` must be `u64` aligned, and nobody may be using it.
+pub unsafe fn paint(stack: Range<usize>) {
+    let mut ptr = stack.start as *mut u64;
+
+    while (ptr as usize) < stack.end {
+        ptr::write_volatile(ptr, PAINT);
+        ptr = ptr.offset(1);
+    }
+
+    ptr::write_volatile(stack.start as *mut u64, CANARY);
+}
+
+/// Tell how much of the painted `stack` was used.
+///
+/// # Safety
+///
+/// - `stack` must have been painted with `paint()`.
+pub unsafe fn usage(stack: Range<usize>) -> StackUsage {
+    let canary_intact = ptr::read_volatile(stack.start as *const u64) == CANARY;
+
+    // The stack grows downwards, so the lowest word that is no longer paint marks the peak.
+    let mut addr = stack.start + mem::size_of::<u64>();
+    while addr < stack.end && ptr::read_volatile(addr as *const u64) == PAINT {
+        addr += mem::size_of::<u64>();
+    }
+
+    StackUsage {
+        size: stack.end - stack.start,
+        peak_used: stack.end - addr,
+        canary_intact,
+    }
+}
+
+/// Tell how much of the boot core's stack was used.
+#[cfg(target_os = "none")]
+pub fn boot_stack_usage() -> StackUsage {
+    unsafe { usage(crate::bsp::memory::boot_stack()) }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORDS: usize = 64;
+
+    fn range_of(stack: &mut [u64; WORDS]) -> Range<usize> {
+        let start = stack.as_mut_ptr() as usize;
+
+        start..start + WORDS * mem::size_of::<u64>()
+    }
+
+    /// The peak is where the highest overwritten word is, counted from the top.
+    #[test_case]
+    fn usage_finds_peak() {
+        let mut stack = [0; WORDS];
+        let range = range_of(&mut stack);
+
+        unsafe { paint(range.clone()) };
+        assert_eq!(unsafe { usage(range.clone()) }.peak_used, 0);
+
+        // A used word may happen to hold the paint, the one below it does not.
+        stack[WORDS - 10] = 0;
+        stack[WORDS - 11] = PAINT;
+        stack[WORDS - 12] = 42;
+
+        let usage = unsafe { usage(range) };
+        assert_eq!(usage.size, WORDS * 8);
+        assert_eq!(usage.peak_used, 12 * 8);
+        assert!(usage.canary_intact);
+    }
+
+    /// An overwritten canary is reported, and everything above it counts as used.
+    #[test_case]
+    fn usage_detects_overflow() {
+        let mut stack = [0; WORDS];
+        let range = range_of(&mut stack);
+
+        unsafe { paint(range.clone()) };
+        stack[0] = 0;
+        stack[1] = 0;
+
+        let usage = unsafe { usage(range) };
+        assert_eq!(usage.peak_used, WORDS * 8 - 8);
+        assert!(!usage.canary_intact);
+    }
+}
@@ -4,12 +4,25 @@
 
 //! A panic handler that infinitely waits.
 
-use crate::{bsp, cpu};
+use crate::{bsp, memory};
 use core::{fmt, panic::PanicInfo};
 
 
 // ---------------------------------- Private code -------------------------------------------------
 
+/// Stop execution. When testing, this exits QEMU with a failure code instead.
+fn _panic_exit() -> ! {
+    #[cfg(not(feature = "test_build"))]
+    {
+        crate::cpu::wait_forever()
+    }
+
+    #[cfg(feature = "test_build")]
+    {
+        crate::semihosting::exit_failure()
+    }
+}
+
 fn _panic_print(args: fmt::Arguments) {
     use fmt::Write;
     unsafe { bsp::console::panic_console_out().write_fmt(args).unwrap() };
@@ -33,5 +46,11 @@ fn panic(info: &PanicInfo) -> ! {
         panic_println!("\nKernel panic!");
     }
 
-    cpu::wait_forever()
+    // An overflowing stack may well be the reason for the panic.
+    let stack = memory::stack::boot_stack_usage();
+    if !stack.canary_intact {
+        panic_println!("Boot core stack overflowed: {}", stack);
+    }
+
+    _panic_exit()
 }
\ No newline at end of file
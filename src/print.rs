@@ -1,18 +1,26 @@
 //! Printing facilities.
 
-use crate::{bsp, console};
 use core::fmt;
 
 //--------------------------------------------------------------------------------------------------
 // Private Code
 //--------------------------------------------------------------------------------------------------
 
+#[doc(hidden)]
+#[cfg(target_os = "none")]
 pub fn _print(args: fmt::Arguments) {
-    use console::interface::Write;
+    use crate::{bsp, console::interface::Write};
 
     bsp::console::console().write_fmt(args).unwrap();
 }
 
+/// On the host, there is no UART to talk to, so print to `stdout` instead.
+#[doc(hidden)]
+#[cfg(not(target_os = "none"))]
+pub fn _print(args: fmt::Arguments) {
+    std::print!("{}", args);
+}
+
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
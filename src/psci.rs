@@ -0,0 +1,252 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Power State Coordination Interface.
+//!
+//! A minimal PSCI 1.0 service for payloads running at EL1, called through `hvc #0` with the SMC
+//! calling convention: the function ID in x0, its arguments in x1 to x3, and the result in x0.
+//!
+//! Cores are brought up and taken down through the parking in `cpu::smp`, and the system is reset or
+//! powered off by the `BSP`. The loader does not add a `psci` node to the device tree, so a payload
+//! has to know that it may use this interface.
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// PSCI interfaces.
+pub mod interface {
+    use super::{AffinityState, Error};
+
+    /// Powering individual cores on and off.
+    pub trait CpuPower {
+        /// Power on the core with the MPIDR `target` at `entry`, at EL1 with `context_id` in x0.
+        fn cpu_on(&self, target: u64, entry: u64, context_id: u64) -> Result<(), Error>;
+
+        /// Power off the calling core. Only returns if the core could not be powered off.
+        fn cpu_off(&self) -> Error;
+
+        /// The power state of the core with the MPIDR `target`.
+        fn affinity_info(&self, target: u64) -> Result<AffinityState, Error>;
+    }
+
+    /// Powering the whole system off.
+    pub trait SystemPower {
+        /// Power off the system.
+        fn system_off(&self) -> !;
+
+        /// Reset the system.
+        fn system_reset(&self) -> !;
+    }
+}
+
+/// Function IDs.
+#[allow(missing_docs)]
+pub mod function_id {
+    pub const PSCI_VERSION: u32 = 0x8400_0000;
+    pub const CPU_OFF: u32 = 0x8400_0002;
+    pub const CPU_ON_32: u32 = 0x8400_0003;
+    pub const CPU_ON_64: u32 = 0xC400_0003;
+    pub const AFFINITY_INFO_32: u32 = 0x8400_0004;
+    pub const AFFINITY_INFO_64: u32 = 0xC400_0004;
+    pub const SYSTEM_OFF: u32 = 0x8400_0008;
+    pub const SYSTEM_RESET: u32 = 0x8400_0009;
+    pub const PSCI_FEATURES: u32 = 0x8400_000A;
+}
+
+/// The implemented version, 1.0.
+pub const VERSION: u32 = 1 << 16;
+
+/// Error return codes.
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    NotSupported = -1,
+    InvalidParameters = -2,
+    Denied = -3,
+    AlreadyOn = -4,
+    OnPending = -5,
+    InternalFailure = -6,
+}
+
+/// The power state of a core, as returned by `AFFINITY_INFO`.
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AffinityState {
+    On = 0,
+    Off = 1,
+    OnPending = 2,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn to_return_value(result: Result<u64, Error>) -> u64 {
+    match result {
+        Ok(value) => value,
+        Err(e) => e as i64 as u64,
+    }
+}
+
+fn is_implemented(function_id: u32) -> bool {
+    use function_id::*;
+
+    matches!(
+        function_id,
+        PSCI_VERSION
+            | CPU_OFF
+            | CPU_ON_32
+            | CPU_ON_64
+            | AFFINITY_INFO_32
+            | AFFINITY_INFO_64
+            | SYSTEM_OFF
+            | SYSTEM_RESET
+            | PSCI_FEATURES
+    )
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Carry out the PSCI call `function_id` with the arguments `args`. Returns the value for x0.
+pub fn dispatch(
+    function_id: u64,
+    args: [u64; 3],
+    cpus: &impl interface::CpuPower,
+    system: &impl interface::SystemPower,
+) -> u64 {
+    use function_id::*;
+
+    // Arguments of the 32 bit calls only use the lower halves of the registers.
+    let function_id = function_id as u32;
+    let args = if function_id & 0x4000_0000 == 0 {
+        [
+            args[0] as u32 as u64,
+            args[1] as u32 as u64,
+            args[2] as u32 as u64,
+        ]
+    } else {
+        args
+    };
+
+    let result = match function_id {
+        PSCI_VERSION => Ok(u64::from(VERSION)),
+        CPU_OFF => Err(cpus.cpu_off()),
+        CPU_ON_32 | CPU_ON_64 => cpus.cpu_on(args[0], args[1], args[2]).map(|_| 0),
+        AFFINITY_INFO_32 | AFFINITY_INFO_64 => {
+            // Only the affinity level of single cores is supported.
+            if args[1] != 0 {
+                Err(Error::InvalidParameters)
+            } else {
+                cpus.affinity_info(args[0]).map(|state| state as u64)
+            }
+        }
+        SYSTEM_OFF => system.system_off(),
+        SYSTEM_RESET => system.system_reset(),
+        PSCI_FEATURES => {
+            if is_implemented(args[0] as u32) {
+                Ok(0)
+            } else {
+                Err(Error::NotSupported)
+            }
+        }
+        _ => Err(Error::NotSupported),
+    };
+
+    to_return_value(result)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two cores: 0 is on, 1 is off.
+    struct TestCpus;
+
+    struct TestSystem;
+
+    impl interface::CpuPower for TestCpus {
+        fn cpu_on(&self, target: u64, entry: u64, context_id: u64) -> Result<(), Error> {
+            assert_eq!((entry, context_id), (0x8_0000, 0x42));
+
+            match target {
+                0 => Err(Error::AlreadyOn),
+                1 => Ok(()),
+                _ => Err(Error::InvalidParameters),
+            }
+        }
+
+        fn cpu_off(&self) -> Error {
+            Error::Denied
+        }
+
+        fn affinity_info(&self, target: u64) -> Result<AffinityState, Error> {
+            match target {
+                0 => Ok(AffinityState::On),
+                1 => Ok(AffinityState::Off),
+                _ => Err(Error::InvalidParameters),
+            }
+        }
+    }
+
+    impl interface::SystemPower for TestSystem {
+        fn system_off(&self) -> ! {
+            panic!("system_off")
+        }
+
+        fn system_reset(&self) -> ! {
+            panic!("system_reset")
+        }
+    }
+
+    fn call(function_id: u32, args: [u64; 3]) -> u64 {
+        dispatch(u64::from(function_id), args, &TestCpus, &TestSystem)
+    }
+
+    /// Version and feature discovery.
+    #[test_case]
+    fn psci_version_and_features() {
+        assert_eq!(call(function_id::PSCI_VERSION, [0; 3]), 0x1_0000);
+        assert_eq!(call(function_id::PSCI_FEATURES, [0xC400_0003, 0, 0]), 0);
+        assert_eq!(
+            call(function_id::PSCI_FEATURES, [0x8400_0001, 0, 0]),
+            Error::NotSupported as i64 as u64
+        );
+        assert_eq!(call(0x8400_0001, [0; 3]), Error::NotSupported as i64 as u64);
+    }
+
+    /// Results and errors of the core calls are passed through, and 32 bit calls only see the lower
+    /// halves of their arguments.
+    #[test_case]
+    fn psci_cpu_calls() {
+        assert_eq!(call(function_id::CPU_ON_64, [1, 0x8_0000, 0x42]), 0);
+        assert_eq!(
+            call(function_id::CPU_ON_64, [0, 0x8_0000, 0x42]),
+            Error::AlreadyOn as i64 as u64
+        );
+        assert_eq!(
+            call(
+                function_id::CPU_ON_32,
+                [1 << 32 | 1, 1 << 32 | 0x8_0000, 0x42]
+            ),
+            0
+        );
+        assert_eq!(
+            call(function_id::CPU_OFF, [0; 3]),
+            Error::Denied as i64 as u64
+        );
+
+        assert_eq!(call(function_id::AFFINITY_INFO_64, [1, 0, 0]), 1);
+        assert_eq!(
+            call(function_id::AFFINITY_INFO_64, [0, 1, 0]),
+            Error::InvalidParameters as i64 as u64
+        );
+    }
+}
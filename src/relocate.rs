@@ -1,48 +1,152 @@
 //! Relocation code.
+//!
+//! The loader is linked as a position-independent executable. Relocating it takes copying the
+//! binary, and then adjusting every absolute address in it, as listed by its dynamic relocations.
+//! Those are the GOT entries, vtables and statics that hold function pointers or references. After
+//! that, the relocated binary runs like any other.
+//!
+//! Until then, the binary still runs where the firmware loaded it, and only PC-relative addressing
+//! yields addresses of the running binary. That is why this code looks up linker symbols with
+//! `adrp` instead of going through the GOT.
 
-use crate::{bsp, runtime_init};
+use crate::{bsp, cpu, memory};
+use core::{mem, ptr, slice};
 
 // -------------------------------------------------------------------------------------------------
-// Public code
+// Private Definitions
+// -------------------------------------------------------------------------------------------------
+
+/// An ELF64 relocation with addend.
+#[repr(C)]
+struct Rela {
+    offset: u64,
+    info: u64,
+    addend: u64,
+}
+
+/// Adjust the addend to the base address. The only type of dynamic relocation a statically linked
+/// position-independent executable has.
+const R_AARCH64_RELATIVE: u64 = 1027;
+
+/// The address of the linker symbol `$sym` in the running binary.
+macro_rules! run_addr {
+    ($sym:tt) => {{
+        let addr: usize;
+        llvm_asm!(concat!("adrp $0, ", $sym, "\n add $0, $0, #:lo12:", $sym)
+            : "=r"(addr) ::: "volatile");
+        addr
+    }};
+}
+
+// -------------------------------------------------------------------------------------------------
+// Private Code
 // -------------------------------------------------------------------------------------------------
 
+/// The address the binary was linked to.
+#[inline(always)]
+unsafe fn link_addr() -> usize {
+    let addr: usize;
+    llvm_asm!("movz $0, #:abs_g3:__binary_link_start
+               movk $0, #:abs_g2_nc:__binary_link_start
+               movk $0, #:abs_g1_nc:__binary_link_start
+               movk $0, #:abs_g0_nc:__binary_link_start"
+        : "=r"(addr) ::: "volatile");
+    addr
+}
 
-/// Relocates the own binary from `bsp::cpu::BOARD_DEFAULT_LOAD_ADDRESS` to the `__binary_start`
-/// address from the linker script.
+/// Apply `relocations` to the binary at `base`, which was linked to `link_base`.
 ///
 /// # Safety
 ///
-/// - Only a single core must be active and running this function.
-/// - Function must not use the `bss` section.
-pub unsafe fn relocate_self<T>() -> ! {
-    extern "C" {
-        static __binary_start: usize;
-        static __binary_end: usize;
+/// - `relocations` must be the binary's own.
+#[inline(always)]
+unsafe fn apply_relocations(relocations: &[Rela], base: usize, link_base: usize) {
+    for rela in relocations {
+        // There is no way to report anything yet. A different type means a broken link.
+        if rela.info & 0xffff_ffff != R_AARCH64_RELATIVE {
+            cpu::wait_forever();
+        }
+
+        let target = (rela.offset as usize - link_base + base) as *mut u64;
+        let value = rela.addend.wrapping_sub(link_base as u64) + base as u64;
+
+        ptr::write_volatile(target, value);
     }
+}
+
+/// The end of the boot core's stack in the running binary, which is where the boot core's stack
+/// pointer starts.
+#[inline(always)]
+unsafe fn boot_stack_end() -> usize {
+    run_addr!("__boot_stack_end")
+}
 
-    let binary_start_addr: usize = &__binary_start as *const _ as _;
-    let binary_end_addr: usize = &__binary_end as *const _ as _;
+// -------------------------------------------------------------------------------------------------
+// Public code
+// -------------------------------------------------------------------------------------------------
+
+/// Relocates the own binary from where it runs to where the BSP wants it, and continues in the
+/// relocated `runtime_init()`, on the relocated boot core's stack. `dtb_addr` is handed on to it,
+/// along with how long the relocation took.
+///
+/// If the BSP has no better idea, the destination is the link address.
+///
+/// # Safety
+///
+/// - Only a single core must be active and running this function.
+/// - Function must not use the `bss` section.
+pub unsafe fn relocate_self(dtb_addr: usize) -> ! {
+    let start = cpu::uptime();
+    let binary_start_addr: usize = run_addr!("__binary_start");
+    let binary_end_addr: usize = run_addr!("__binary_end");
     let binary_size_in_bytes: usize = binary_end_addr - binary_start_addr;
 
-    // Get the relocation destination address from the linker symbol
-    let mut reloc_dest_addr: *mut T = binary_start_addr as *mut T;
+    // The stacks and the heap follow the binary, and move along with it.
+    let loader_end_addr: usize = run_addr!("__loader_end");
+    let loader_size_in_bytes: usize = loader_end_addr - binary_start_addr;
+    let link_start_addr: usize = link_addr();
+    let reloc_start_addr: usize =
+        match bsp::memory::relocation_target(dtb_addr, loader_size_in_bytes) {
+            // The copy must not overwrite what is being copied, nor the stack this code runs on.
+            Some(addr) if addr >= loader_end_addr => addr,
+            _ => link_start_addr,
+        };
 
-    // The address of where the previous firmware loaded us
-    let mut src_addr: *const T = bsp::cpu::BOARD_DEFAULT_LOAD_ADDRESS as *const _;
+    // Copy the whole binary from where the previous firmware loaded us.
+    memory::copy(
+        reloc_start_addr as *mut u8,
+        binary_start_addr as *const u8,
+        binary_size_in_bytes,
+    );
 
-    // Copy the whole binary
-    //
-    // This is essentially `memcpy()` optimized for throughput by transferring chunks of T
-    let n = binary_size_in_bytes / core::mem::size_of::<T>();
-    for _ in 0..n {
-        use core::ptr;
+    // The relocations are read from the running binary, which is left untouched.
+    let rela_start_addr: usize = run_addr!("__rela_dyn_start");
+    let rela_end_addr: usize = run_addr!("__rela_dyn_end");
+    let relocations = slice::from_raw_parts(
+        rela_start_addr as *const Rela,
+        (rela_end_addr - rela_start_addr) / mem::size_of::<Rela>(),
+    );
+    apply_relocations(relocations, reloc_start_addr, link_start_addr);
 
-        ptr::write_volatile::<T>(reloc_dest_addr, ptr::read_volatile::<T>(src_addr));
-        reloc_dest_addr = reloc_dest_addr.offset(1);
-        src_addr = src_addr.offset(1);
-    }
+    // Nothing of the relocated code may be fetched from before it was written.
+    cpu::cache::dsb();
+    cpu::cache::invalidate_icache_all();
+    cpu::cache::isb();
+
+    // The relocated boot core's stack is not in use yet.
+    let boot_stack_start_addr: usize =
+        run_addr!("__boot_stack_start") - binary_start_addr + reloc_start_addr;
+    let boot_stack_end_addr: usize = boot_stack_end() - binary_start_addr + reloc_start_addr;
+    memory::stack::paint(boot_stack_start_addr..boot_stack_end_addr);
+
+    // Continue in the relocated binary on the relocated stack, through an absolute jump.
+    let runtime_init_addr: usize = run_addr!("runtime_init") - binary_start_addr + reloc_start_addr;
+    let relocation_nanos = (cpu::uptime() - start).as_nanos() as u64;
+    llvm_asm!("mov sp, $0
+               br  $1"
+        :: "r"(boot_stack_end_addr), "r"(runtime_init_addr), "{x0}"(dtb_addr),
+           "{x1}"(relocation_nanos)
+        :: "volatile");
 
-    // Call `runtime_init()` through a trait object, causing the jump to use an absolute address to
-    // reach the relocated binary. See `runtime_init::runtime_init(`)`.
-    runtime_init::get().runtime_init();
-}
\ No newline at end of file
+    cpu::wait_forever()
+}
@@ -1,54 +1,18 @@
 //! Rust runtime initialization code.
 
-use crate::memory;
-use core::ops::Range;
-
-
-// -------------------------------------------------------------------------------------------------
-// Private definitions
-// -------------------------------------------------------------------------------------------------
-
-struct Traitor;
-
-// -------------------------------------------------------------------------------------------------
-// Public definitions
-// -------------------------------------------------------------------------------------------------
-
-/// We are outsmarting the compiler here by using a trait as a layer of indirection. Because we are
-/// generating PIC code, a static dispatch to `init()` would generate a relative jump from the
-/// callee to `init()`. However, when calling `init()`, code just finished copying the binary to the
-/// actual link-time address, and hence is still running at whatever location the previous loader
-/// has put it. So we do not want a relative jump, because it would not jump to the relocated code.
-///
-/// By indirecting through a trait object, we can make use of the property that vtables store
-/// absolute addresses. So calling `init()` this way will kick execution to the relocated binary.
-pub trait RunTimeInit {
-    /// Equivalent to `crt0` or `c0` code in C/C++ world. Clears the `bss` section, then jumps to
-    /// the kernel init code.
-    /// 
-    /// # Safety
-    /// 
-    /// - Only a single core must be active and running this function
-    unsafe fn runtime_init(&self) -> ! {
-        zero_bss();
-        crate::kernel_init()
-    }
-} 
-
+use crate::{cpu, memory};
+use core::{ops::Range, time::Duration};
 
 //--------------------------------------------------------------------------------------------------
 // Private Code
 //--------------------------------------------------------------------------------------------------
 
-impl RunTimeInit for Traitor {}
-
 /// Return the range spanning the .bss section.
 ///
 /// # Safety
 ///
 /// - The symbol-provided addresses must be valid.
 /// - The symbol-provided addresses must be usize aligned.
-
 unsafe fn bss_range() -> Range<*mut usize> {
     extern "C" {
         static mut __bss_start: usize;
@@ -66,19 +30,42 @@ unsafe fn bss_range() -> Range<*mut usize> {
 /// # Safety
 ///
 /// - Must only be called pre `kernel_init()`.
-
 #[inline(always)]
 unsafe fn zero_bss() {
-    memory::zero_volatile(bss_range());
-}
+    let bss = bss_range();
 
+    memory::fill(
+        bss.start as *mut u8,
+        0,
+        bss.end as usize - bss.start as usize,
+    );
+}
 
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
 
-/// Give the callee a `RunTimeInit` trait object
+/// Equivalent to `crt0` or `c0` code in C/C++ world. Clears the `bss` section, then jumps to the
+/// kernel init code, passing on the firmware's device tree address.
+///
+/// Entered from `relocate_self()`, in the relocated binary, with the nanoseconds the relocation
+/// took.
+///
+/// # Safety
+///
+/// - Only a single core must be active and running this function.
+#[no_mangle]
+pub unsafe extern "C" fn runtime_init(dtb_addr: usize, relocation_nanos: u64) -> ! {
+    extern "Rust" {
+        fn kernel_init(dtb_addr: usize) -> !;
+    }
+
+    let start = cpu::uptime();
+    zero_bss();
+    memory::set_boot_times(memory::BootTimes {
+        relocation: Duration::from_nanos(relocation_nanos),
+        zero_bss: cpu::uptime() - start,
+    });
 
-pub unsafe fn get() -> &'static dyn RunTimeInit {
-    &Traitor {}
-}
\ No newline at end of file
+    kernel_init(dtb_addr)
+}
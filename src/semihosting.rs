@@ -0,0 +1,13 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Semihosting.
+//!
+//! Lets code running in QEMU (started with `-semihosting`) call into the host. Used by the test
+//! framework to end a test run with an exit code that `cargo test` understands.
+
+#[cfg(all(target_arch = "aarch64", target_os = "none"))]
+#[path = "_arch/aarch64/semihosting.rs"]
+mod arch_semihosting;
+pub use arch_semihosting::*;
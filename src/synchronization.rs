@@ -1,5 +1,8 @@
 //! Synchonization primitives
 
+use crate::exception::asynchronous::{local_irq_mask_save, local_irq_restore};
+#[cfg(feature = "debug_lock")]
+use core::cell::Cell;
 use core::cell::UnsafeCell;
 
 // -----------------------------------------------------------------------------
@@ -51,9 +54,69 @@ pub mod interface {
 ///
 /// [interior mutability]: https://doc.rust-lang.org/std/cell/index.html
 pub struct NullLock<T: ?Sized> {
+    held: HeldFlag,
     data: UnsafeCell<T>,
 }
 
+/// A lock that masks IRQs and FIQs on the executing core for the duration of the critical section.
+///
+/// Protects data that is shared between normal code and interrupt handlers of the same core. Like
+/// `NullLock`, it does not protect against access from other cores.
+///
+/// The previous mask state is restored afterwards, so critical sections can be nested and the lock
+/// can be taken from within interrupt handlers.
+pub struct IRQSafeLock<T: ?Sized> {
+    held: HeldFlag,
+    data: UnsafeCell<T>,
+}
+
+// -------------------------------------------------------------------------------------------------
+// Private definitions
+// -------------------------------------------------------------------------------------------------
+
+/// Tracks if a lock is held, so that reentrant locking is caught instead of handing out aliasing
+/// mutable references.
+///
+/// Only does something with the `debug_lock` feature. The panic it raises is printed through
+/// `bsp::console::panic_console_out()`, which does not take any lock, so it still gets out if the
+/// reentered lock is the console's.
+struct HeldFlag {
+    #[cfg(feature = "debug_lock")]
+    held: Cell<bool>,
+}
+
+// -------------------------------------------------------------------------------------------------
+// Private code
+// -------------------------------------------------------------------------------------------------
+
+impl HeldFlag {
+    const fn new() -> Self {
+        Self {
+            #[cfg(feature = "debug_lock")]
+            held: Cell::new(false),
+        }
+    }
+
+    /// Mark the lock of type `L` as held. Panics if it already is.
+    #[inline(always)]
+    fn acquire<L: ?Sized>(&self) {
+        #[cfg(feature = "debug_lock")]
+        {
+            if self.held.replace(true) {
+                panic!("Reentrant locking of {}", core::any::type_name::<L>());
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn release(&self) {
+        #[cfg(feature = "debug_lock")]
+        {
+            self.held.set(false);
+        }
+    }
+}
+
 // =================================== Public Code =================================================
 
 unsafe impl <T: ?Sized> Sync for NullLock<T> {}
@@ -61,21 +124,105 @@ unsafe impl <T: ?Sized> Sync for NullLock<T> {}
 impl<T> NullLock<T> {
     /// Wraps `data` into a new `NullLock`
     pub const fn new(data: T) -> Self {
-        Self { data: UnsafeCell::new(data) }
+        Self {
+            held: HeldFlag::new(),
+            data: UnsafeCell::new(data),
+        }
     }
 }
 
+unsafe impl<T: ?Sized> Sync for IRQSafeLock<T> {}
+
+impl<T> IRQSafeLock<T> {
+    /// Wraps `data` into a new `IRQSafeLock`
+    pub const fn new(data: T) -> Self {
+        Self {
+            held: HeldFlag::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
 
 // ================================== OS Interface Code ============================================
 
+impl<T> interface::Mutex for &IRQSafeLock<T> {
+    type Data = T;
+
+    fn lock<R>(&mut self, f: impl FnOnce(&mut Self::Data) -> R) -> R {
+        // No handler can run on this core until the state is restored, so the mutable reference is
+        // the only one.
+        let saved = local_irq_mask_save();
+        self.held.acquire::<IRQSafeLock<T>>();
+
+        let data = unsafe { &mut *self.data.get() };
+        let result = f(data);
+
+        self.held.release();
+        local_irq_restore(saved);
+
+        result
+    }
+}
+
 impl<T> interface::Mutex for &NullLock<T> {
     type Data = T;
 
     fn lock<R>(&mut self, f: impl FnOnce(&mut Self::Data) -> R) -> R {
         // In a real lock there would be code encapsulating this line that this mutable reference
-        // will ever only be gived out once at a time.
+        // will ever only be gived out once at a time. With `debug_lock`, at least reentrancy on
+        // the same core is caught.
+        self.held.acquire::<NullLock<T>>();
         let data = unsafe { &mut *self.data.get() };
-        
-        f(data)
+        let result = f(data);
+        self.held.release();
+
+        result
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interface::Mutex;
+
+    /// Changes made inside the critical section must be visible afterwards.
+    #[test_case]
+    fn null_lock_grants_mutable_access() {
+        let lock = NullLock::new(0_u32);
+
+        let mut r = &lock;
+        r.lock(|data| *data += 1);
+
+        assert_eq!(r.lock(|data| *data), 1);
+    }
+
+    /// Nested critical sections must work, and the data must be accessible from both.
+    #[test_case]
+    fn irq_safe_lock_nests() {
+        let outer = IRQSafeLock::new(1_u32);
+        let inner = IRQSafeLock::new(2_u32);
+
+        let (mut o, mut i) = (&outer, &inner);
+        let sum = o.lock(|a| i.lock(|b| *a + *b));
+
+        assert_eq!(sum, 3);
+    }
+
+    /// Locking a `NullLock` from within its own critical section must panic.
+    #[cfg(all(feature = "debug_lock", not(target_os = "none")))]
+    #[test_case]
+    fn null_lock_detects_reentrancy() {
+        let lock = NullLock::new(0_u32);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let (mut outer, mut inner) = (&lock, &lock);
+            outer.lock(|_| inner.lock(|_| ()));
+        }));
+
+        assert!(result.is_err());
     }
 }
@@ -0,0 +1,48 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Console sanity tests.
+
+#![feature(custom_test_frameworks)]
+#![feature(format_args_nl)]
+#![no_main]
+#![no_std]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(libkernel::test_runner)]
+
+use libkernel::{bsp, console, print, semihosting};
+
+#[no_mangle]
+unsafe fn kernel_init(_dtb_addr: usize) -> ! {
+    bsp::console::qemu_bring_up_console();
+
+    test_main();
+
+    semihosting::exit_success()
+}
+
+#[no_mangle]
+unsafe extern "C" fn loader_resume() -> ! {
+    unreachable!()
+}
+
+/// Everything that is printed must show up in the statistics.
+#[test_case]
+fn chars_written_counts_output() {
+    use console::interface::Statistics;
+
+    let before = bsp::console::console().chars_written();
+    print!("12345");
+
+    assert_eq!(bsp::console::console().chars_written(), before + 5);
+}
+
+/// Clearing the RX FIFO must return instead of spinning. In QEMU, the host side is idle, so there is
+/// nothing or little to drain.
+#[test_case]
+fn clear_terminates() {
+    use console::interface::Read;
+
+    bsp::console::console().clear();
+}
@@ -0,0 +1,60 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Host side of the `MiniLoad` chainloading protocol.
+//!
+//! The library part is split out of the `minipush` binary so that integration tests can drive the
+//! protocol against QEMU without going through the interactive terminal.
+
+pub mod protocol;
+pub mod target;
+pub mod terminal;
+
+use std::{fmt, io};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Everything that can go wrong while talking to the target.
+#[derive(Debug)]
+pub enum Error {
+    /// The connection to the target was lost, e.g. the USB serial was unplugged.
+    Connection(io::Error),
+
+    /// The target answered something unexpected.
+    Protocol(String),
+
+    /// The target did not request a binary in time.
+    Timeout,
+
+    /// The binary, of the given size in bytes, does not fit into the target's RAM.
+    TooBig(usize),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Connection(e) => write!(f, "Connection Error: {}", e),
+            Error::Protocol(msg) => write!(f, "Protocol Error: {}", msg),
+            Error::Timeout => write!(f, "Timeout waiting for the binary request"),
+            Error::TooBig(size) => write!(f, "Binary too big for the target: {} bytes", size),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Error::Timeout,
+            _ => Error::Connection(e),
+        }
+    }
+}
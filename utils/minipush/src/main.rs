@@ -0,0 +1,266 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! `minipush`: push a binary to `MiniLoad` and drop into a terminal afterwards.
+//!
+//! Usage: `minipush [--el1] [--guard] [--fp] <TARGET> <BINARY>`, where `TARGET` is a serial device,
+//! a PTY or `tcp:HOST:PORT`.
+//!
+//! `minipush --memtest START..END <TARGET>` has `MiniLoad` test that range of RAM instead, over and
+//! over again until the terminal is quit.
+//!
+//! When the target requests a binary again from the terminal, e.g. after a guarded payload handed
+//! control back to `MiniLoad`, the binary is pushed again.
+
+use crossterm::style::Stylize;
+use minipush::{
+    protocol,
+    target::{Target, TargetSpec},
+    terminal, Error,
+};
+use std::{
+    env, fs,
+    io::{self, Write},
+    ops::Range,
+    path::PathBuf,
+    process, thread,
+    time::{Duration, Instant},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// How long the target may take from connecting until it requests the binary.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(7);
+
+/// Width of the progress bar in characters.
+const BAR_WIDTH: usize = 40;
+
+/// What to do after an error.
+enum Recovery {
+    Retry,
+    Quit,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn print_progress(sent: usize, total: usize, start: Instant) {
+    let fraction = if total == 0 {
+        1.0
+    } else {
+        sent as f64 / total as f64
+    };
+    let filled = (fraction * BAR_WIDTH as f64) as usize;
+    let elapsed = start.elapsed().as_secs_f64().max(0.001);
+
+    print!(
+        "\r[MP] ⏩ Pushing {} KiB {}🦀{} {:3.0}% {:.0} KiB/s {:.1}s",
+        sent / 1024,
+        "=".repeat(filled),
+        " ".repeat(BAR_WIDTH - filled),
+        fraction * 100.0,
+        sent as f64 / 1024.0 / elapsed,
+        elapsed
+    );
+    let _ = io::stdout().flush();
+}
+
+fn print_error(msg: &str) {
+    println!();
+    println!("[MP] ⚡ {}", msg.red());
+}
+
+/// One full session: connect, then push and terminal until the user quits.
+fn session(spec: &TargetSpec, image: &[u8], flags: u32) -> Result<(), Error> {
+    let mut target = spec
+        .wait_and_open(|| {
+            print!("\r[MP] ⏳ Waiting for {}", spec);
+            let _ = io::stdout().flush();
+        })
+        .map_err(Error::Connection)?;
+    println!();
+    println!("[MP] ✅ Connected");
+
+    protocol::wait_for_request(&mut target, REQUEST_TIMEOUT, &mut io::stdout())?;
+
+    loop {
+        push(&mut target, image, flags)?;
+
+        match terminal::run(&mut target)? {
+            terminal::Exit::Quit => return Ok(()),
+            terminal::Exit::Requested => {
+                println!();
+                println!("[MP] 🔁 Target requested the binary again");
+            }
+        }
+    }
+}
+
+fn push(target: &mut Target, image: &[u8], flags: u32) -> Result<(), Error> {
+    let start = Instant::now();
+    protocol::push(target, image, flags, |sent| {
+        print_progress(sent, image.len(), start)
+    })?;
+    println!();
+
+    Ok(())
+}
+
+fn handle_error(spec: &TargetSpec, error: Error) -> Recovery {
+    match error {
+        Error::Connection(ref e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            print_error(&format!("{} - Maybe try with 'sudo'", e));
+            Recovery::Quit
+        }
+        // Retrying will not make the binary any smaller.
+        Error::TooBig(_) => {
+            print_error(&error.to_string());
+            Recovery::Quit
+        }
+        // When the serial lost power or was removed during R/W operation.
+        Error::Connection(_) => {
+            print_error(&format!("{}: Reinsert the USB serial again", error));
+            Recovery::Retry
+        }
+        // When the serial is still powered.
+        Error::Protocol(_) | Error::Timeout => {
+            print_error(&format!(
+                "{}: Remove and insert the USB serial again",
+                error
+            ));
+            while let TargetSpec::Device(_) = spec {
+                if !spec.is_present() {
+                    break;
+                }
+                thread::sleep(Duration::from_secs(1));
+            }
+            Recovery::Retry
+        }
+    }
+}
+
+/// Parse an address, in hex with a `0x` prefix or in decimal.
+fn parse_addr(arg: &str) -> Option<u64> {
+    match arg.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => arg.parse().ok(),
+    }
+}
+
+/// Parse a range given as `START..END`.
+fn parse_range(arg: &str) -> Option<Range<u64>> {
+    let (start, end) = arg.split_once("..")?;
+
+    Some(parse_addr(start)?..parse_addr(end)?)
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: minipush [--el1] [--guard] [--fp] <TARGET> <BINARY>");
+    eprintln!("       minipush --memtest <START>..<END> <TARGET>");
+    eprintln!();
+    eprintln!("  --el1   Enter the binary at EL1h instead of EL2");
+    eprintln!("  --guard Enter the binary at EL1h, with MiniLoad kept out of its reach");
+    eprintln!("  --fp    Let the binary use FP/SIMD instructions");
+    eprintln!("  --memtest");
+    eprintln!("          Test the target's RAM from START to END, e.g. 0x1000000..0x3b400000");
+    eprintln!("  TARGET  Serial device or PTY (e.g. /dev/ttyUSB0), or tcp:HOST:PORT");
+    eprintln!("  BINARY  The kernel image to push");
+    process::exit(1);
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut flags = 0;
+    let mut memtest = None;
+    while args.first().is_some_and(|arg| arg.starts_with("--")) {
+        flags |= match args.remove(0).as_str() {
+            "--el1" => protocol::FLAG_ENTER_EL1,
+            "--guard" => protocol::FLAG_GUARD_LOADER,
+            "--fp" => protocol::FLAG_ENABLE_FP,
+            "--memtest" if !args.is_empty() => {
+                memtest = Some(parse_range(&args.remove(0)).unwrap_or_else(|| usage()));
+                protocol::FLAG_MEMTEST
+            }
+            _ => usage(),
+        };
+    }
+    if args.len() != if memtest.is_some() { 1 } else { 2 } {
+        usage();
+    }
+
+    let spec = TargetSpec::parse(&args[0]);
+
+    println!("{}", "Minipush 1.0".cyan());
+    println!();
+
+    let image = match memtest {
+        Some(range) => protocol::memtest_request(range),
+        None => {
+            let image_path = PathBuf::from(&args[1]);
+            match fs::read(&image_path) {
+                Ok(image) => image,
+                Err(e) => {
+                    print_error(&format!("Cannot read {}: {}", image_path.display(), e));
+                    process::exit(1);
+                }
+            }
+        }
+    };
+
+    loop {
+        match session(&spec, &image, flags) {
+            Ok(()) => break,
+            Err(e) => match handle_error(&spec, e) {
+                Recovery::Retry => continue,
+                Recovery::Quit => break,
+            },
+        }
+    }
+
+    println!();
+    println!("[MP] Bye 👋");
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_in_hex_and_decimal() {
+        assert_eq!(
+            parse_range("0x1000000..0x3b400000"),
+            Some(0x100_0000..0x3b40_0000)
+        );
+        assert_eq!(parse_range("4096..8192"), Some(4096..8192));
+        assert_eq!(parse_range("0x1000..8192"), Some(0x1000..8192));
+        assert_eq!(parse_range("0..0xffffffffffffffff"), Some(0..u64::MAX));
+    }
+
+    #[test]
+    fn malformed_ranges() {
+        for arg in &[
+            "",
+            "..",
+            "0x1000",
+            "0x1000..",
+            "..0x2000",
+            "0x..0x2000",
+            "0X1000..0x2000",
+            "0x1000...0x2000",
+            "0xg000..0x2000",
+            "-1..2",
+            "0x1000 .. 0x2000",
+            "0..0x10000000000000000",
+        ] {
+            assert_eq!(parse_range(arg), None, "{:?}", arg);
+        }
+    }
+}
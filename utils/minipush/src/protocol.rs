@@ -0,0 +1,268 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! The chainloading protocol, as spoken by `kernel_main()` on the target.
+//!
+//! 1. The target prints its banner and then sends three `0x03` characters to request a binary.
+//! 2. The host sends the binary's size, followed by the `FLAG_*` bits that apply to it. Both are
+//!    little endian `u32`s.
+//! 3. The target answers with `OK`, or with `SE` if the binary does not fit into its RAM.
+//! 4. The host sends the binary.
+//!
+//! With `FLAG_MEMTEST`, the binary is a `memtest_request()` instead. The target tests that range of
+//! its RAM, prints the results and requests a binary again.
+
+use crate::{target::Target, Error};
+use std::{
+    io::{Read, Write},
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The three characters with which the target requests a binary.
+pub const REQUEST_TOKEN: [u8; 3] = [3, 3, 3];
+
+/// The target's acknowledgement of the binary's size.
+pub const SIZE_ACK: &[u8; 2] = b"OK";
+
+/// The target's answer to a binary that is too big.
+pub const SIZE_NACK: &[u8; 2] = b"SE";
+
+/// Flag: The target enters the binary at EL1h instead of EL2.
+pub const FLAG_ENTER_EL1: u32 = 1 << 0;
+
+/// Flag: The target keeps the loader resident and guards it from the binary, which is entered at
+/// EL1h.
+pub const FLAG_GUARD_LOADER: u32 = 1 << 1;
+
+/// Flag: The target lets the binary use FP/SIMD instructions instead of trapping them.
+pub const FLAG_ENABLE_FP: u32 = 1 << 2;
+
+/// Flag: Instead of a binary, the host sends a range of the target's RAM to test.
+pub const FLAG_MEMTEST: u32 = 1 << 3;
+
+/// The binary is sent in chunks of this size, so that progress can be reported in between.
+pub const CHUNK_SIZE: usize = 512;
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// What is sent instead of a binary with `FLAG_MEMTEST`: the start and end of `range`, as little
+/// endian `u64`s.
+pub fn memtest_request(range: Range<u64>) -> Vec<u8> {
+    let mut request = range.start.to_le_bytes().to_vec();
+    request.extend_from_slice(&range.end.to_le_bytes());
+
+    request
+}
+
+/// Wait until the target requests a binary.
+///
+/// Everything the target sends before the request token, e.g. its banner, is passed through to
+/// `echo`.
+pub fn wait_for_request(
+    target: &mut Target,
+    timeout: Duration,
+    echo: &mut impl Write,
+) -> Result<(), Error> {
+    let deadline = Instant::now() + timeout;
+    let mut tokens_seen = 0;
+    let mut buf = [0u8; 4096];
+
+    loop {
+        let remaining = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|d| *d > Duration::from_millis(0))
+            .ok_or(Error::Timeout)?;
+        target.set_timeout(Some(remaining))?;
+
+        let n = target.read(&mut buf)?;
+        if n == 0 {
+            return Err(Error::Connection(std::io::ErrorKind::UnexpectedEof.into()));
+        }
+
+        for &c in &buf[..n] {
+            if c == REQUEST_TOKEN[tokens_seen] {
+                tokens_seen += 1;
+
+                if tokens_seen == REQUEST_TOKEN.len() {
+                    echo.flush()?;
+                    target.set_timeout(None)?;
+                    return Ok(());
+                }
+
+                continue;
+            }
+
+            // A partial token turned out to be ordinary output.
+            echo.write_all(&REQUEST_TOKEN[..tokens_seen])?;
+            tokens_seen = 0;
+            echo.write_all(&[c])?;
+        }
+
+        echo.flush()?;
+    }
+}
+
+/// Send the binary's size and flags and wait for the target's acknowledgement.
+pub fn send_header(target: &mut Target, size: u32, flags: u32) -> Result<(), Error> {
+    target.write_all(&size.to_le_bytes())?;
+    target.write_all(&flags.to_le_bytes())?;
+    target.flush()?;
+
+    let mut answer = [0u8; 2];
+    target.read_exact(&mut answer)?;
+
+    if &answer == SIZE_NACK {
+        return Err(Error::TooBig(size as usize));
+    }
+
+    if &answer != SIZE_ACK {
+        return Err(Error::Protocol(format!(
+            "Expected {:?}, got {:?}",
+            String::from_utf8_lossy(SIZE_ACK),
+            String::from_utf8_lossy(&answer)
+        )));
+    }
+
+    Ok(())
+}
+
+/// Send the binary in `CHUNK_SIZE` chunks, calling `progress` with the number of bytes sent so far.
+pub fn send_binary(
+    target: &mut Target,
+    image: &[u8],
+    mut progress: impl FnMut(usize),
+) -> Result<(), Error> {
+    let mut sent = 0;
+
+    for chunk in image.chunks(CHUNK_SIZE) {
+        target.write_all(chunk)?;
+        sent += chunk.len();
+        progress(sent);
+    }
+
+    target.flush()?;
+
+    Ok(())
+}
+
+/// Run the host side of the protocol, starting after the request token was received.
+pub fn push(
+    target: &mut Target,
+    image: &[u8],
+    flags: u32,
+    progress: impl FnMut(usize),
+) -> Result<(), Error> {
+    if image.len() > u32::MAX as usize {
+        return Err(Error::TooBig(image.len()));
+    }
+
+    send_header(target, image.len() as u32, flags)?;
+    send_binary(target, image, progress)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+
+    /// A `Target` connected to a socket that plays the target's part.
+    fn connect() -> (Target, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (board, _) = listener.accept().unwrap();
+        board
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        (Target::Tcp(host), board)
+    }
+
+    fn receive(board: &mut TcpStream, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        board.read_exact(&mut buf).unwrap();
+
+        buf
+    }
+
+    #[test]
+    fn header_is_size_then_flags() {
+        let (mut target, mut board) = connect();
+        board.write_all(SIZE_ACK).unwrap();
+
+        send_header(&mut target, 0x0001_0203, FLAG_ENTER_EL1 | FLAG_MEMTEST).unwrap();
+
+        assert_eq!(receive(&mut board, 8), [3, 2, 1, 0, 0b1001, 0, 0, 0]);
+    }
+
+    #[test]
+    fn size_nack_is_too_big() {
+        let (mut target, mut board) = connect();
+        board.write_all(SIZE_NACK).unwrap();
+
+        match send_header(&mut target, 1234, 0) {
+            Err(Error::TooBig(1234)) => (),
+            other => panic!("Expected TooBig(1234), got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unexpected_answer_is_a_protocol_error() {
+        let (mut target, mut board) = connect();
+        board.write_all(b"ok").unwrap();
+
+        match send_header(&mut target, 1, 0) {
+            Err(Error::Protocol(_)) => (),
+            other => panic!("Expected a protocol error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn push_sends_header_and_binary() {
+        let (mut target, mut board) = connect();
+        board.write_all(SIZE_ACK).unwrap();
+
+        let image: Vec<u8> = (0..CHUNK_SIZE + 3).map(|i| i as u8).collect();
+        let mut progress = Vec::new();
+        push(&mut target, &image, FLAG_ENABLE_FP, |sent| {
+            progress.push(sent)
+        })
+        .unwrap();
+
+        let header = receive(&mut board, 8);
+        assert_eq!(header[..4], (image.len() as u32).to_le_bytes());
+        assert_eq!(header[4..], FLAG_ENABLE_FP.to_le_bytes());
+        assert_eq!(receive(&mut board, image.len()), image);
+        assert_eq!(progress, [CHUNK_SIZE, CHUNK_SIZE + 3]);
+    }
+
+    #[test]
+    fn output_before_the_request_is_echoed() {
+        let (mut target, mut board) = connect();
+        board.write_all(b"Banner\x03\x03!\x03\x03\x03").unwrap();
+
+        let mut echo = Vec::new();
+        wait_for_request(&mut target, Duration::from_secs(5), &mut echo).unwrap();
+
+        assert_eq!(echo, b"Banner\x03\x03!");
+    }
+
+    #[test]
+    fn memtest_request_is_start_then_end() {
+        let request = memtest_request(0x1000..0x0102_0304_0506_0708);
+
+        assert_eq!(request[..8], 0x1000u64.to_le_bytes());
+        assert_eq!(request[8..], 0x0102_0304_0506_0708u64.to_le_bytes());
+    }
+}
@@ -0,0 +1,183 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! Connections to the target: a serial device, a PTY or a TCP socket.
+
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The baud rate `MiniLoad` configures the PL011 UART for.
+pub const BAUD_RATE: u32 = 230_400;
+
+/// Where to find the target.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TargetSpec {
+    /// A serial device or PTY, e.g. `/dev/ttyUSB0` or the `/dev/pts/N` that QEMU prints for
+    /// `-serial pty`.
+    Device(PathBuf),
+
+    /// A TCP socket, e.g. QEMU's `-serial tcp::4444,server`. Written as `tcp:HOST:PORT`.
+    Tcp(String),
+}
+
+/// An open connection to the target.
+pub enum Target {
+    /// A serial device or PTY, configured for `BAUD_RATE` 8N1.
+    Serial(Box<dyn serialport::SerialPort>),
+
+    /// A connected TCP socket.
+    Tcp(TcpStream),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Timeout used when a blocking read is requested. `serialport` has no notion of "no timeout".
+const BLOCKING: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// How long a break holds the line low. Anything above one character time at the baud rate works.
+const BREAK_DURATION: Duration = Duration::from_millis(100);
+
+fn open_device(path: &Path) -> io::Result<Target> {
+    let port = serialport::new(path.to_string_lossy(), BAUD_RATE)
+        .data_bits(serialport::DataBits::Eight)
+        .stop_bits(serialport::StopBits::One)
+        .parity(serialport::Parity::None)
+        .timeout(BLOCKING)
+        .open()?;
+
+    Ok(Target::Serial(port))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl TargetSpec {
+    /// Parse a command line argument.
+    pub fn parse(arg: &str) -> Self {
+        match arg.strip_prefix("tcp:") {
+            Some(addr) => TargetSpec::Tcp(addr.to_string()),
+            None => TargetSpec::Device(PathBuf::from(arg)),
+        }
+    }
+
+    /// Check if the target can be opened right now.
+    pub fn is_present(&self) -> bool {
+        match self {
+            TargetSpec::Device(path) => path.exists(),
+            TargetSpec::Tcp(_) => true,
+        }
+    }
+
+    /// Open the target once.
+    pub fn open(&self) -> io::Result<Target> {
+        match self {
+            TargetSpec::Device(path) => open_device(path),
+            TargetSpec::Tcp(addr) => {
+                let stream = TcpStream::connect(addr)?;
+                stream.set_nodelay(true)?;
+
+                Ok(Target::Tcp(stream))
+            }
+        }
+    }
+
+    /// Open the target, retrying every second until it shows up.
+    ///
+    /// `waiting` is called before each retry so that the caller can report progress. Permission
+    /// errors are returned immediately, because waiting will not fix them.
+    pub fn wait_and_open(&self, mut waiting: impl FnMut()) -> io::Result<Target> {
+        loop {
+            if self.is_present() {
+                match self.open() {
+                    Ok(target) => return Ok(target),
+                    Err(e) if e.kind() == io::ErrorKind::PermissionDenied => return Err(e),
+                    Err(_) => (),
+                }
+            }
+
+            waiting();
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+}
+
+impl std::fmt::Display for TargetSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TargetSpec::Device(path) => write!(f, "{}", path.display()),
+            TargetSpec::Tcp(addr) => write!(f, "tcp:{}", addr),
+        }
+    }
+}
+
+impl Target {
+    /// Set the read timeout. `None` blocks until data arrives.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Target::Serial(port) => Ok(port.set_timeout(timeout.unwrap_or(BLOCKING))?),
+            Target::Tcp(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+
+    /// Hold the line low for a while, which `MiniLoad` sees as a break. Only serial devices can do
+    /// that.
+    pub fn send_break(&mut self) -> io::Result<()> {
+        match self {
+            Target::Serial(port) => {
+                port.set_break()?;
+                thread::sleep(BREAK_DURATION);
+                Ok(port.clear_break()?)
+            }
+            Target::Tcp(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "A TCP socket cannot send a break",
+            )),
+        }
+    }
+
+    /// Get a second handle to the same connection, used to read and write from different threads.
+    pub fn try_clone(&self) -> io::Result<Target> {
+        match self {
+            Target::Serial(port) => Ok(Target::Serial(port.try_clone()?)),
+            Target::Tcp(stream) => Ok(Target::Tcp(stream.try_clone()?)),
+        }
+    }
+}
+
+impl Read for Target {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Target::Serial(port) => port.read(buf),
+            Target::Tcp(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Target {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Target::Serial(port) => port.write(buf),
+            Target::Tcp(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Target::Serial(port) => port.flush(),
+            Target::Tcp(stream) => stream.flush(),
+        }
+    }
+}
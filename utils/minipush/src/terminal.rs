@@ -0,0 +1,194 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! A minimal raw-mode terminal to the target.
+
+use crate::{protocol, target::Target, Error};
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    terminal,
+};
+use std::{
+    io::{self, Read, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Why the terminal was left.
+#[derive(Debug, PartialEq)]
+pub enum Exit {
+    /// The user pressed `CTRL + C`.
+    Quit,
+
+    /// The target requested a binary again, e.g. after the payload handed control back to a
+    /// guarding `MiniLoad`.
+    Requested,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// How long the reader thread blocks on the target before it checks if the terminal was left.
+const READER_POLL: Duration = Duration::from_millis(100);
+
+/// Puts the host console into raw mode for as long as it lives.
+struct RawMode;
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Translate a key press into the bytes a serial terminal would send.
+fn key_to_bytes(key: KeyEvent) -> Vec<u8> {
+    match key.code {
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            let c = c.to_ascii_lowercase();
+            if c.is_ascii_lowercase() {
+                vec![c as u8 - b'a' + 1]
+            } else {
+                Vec::new()
+            }
+        }
+        KeyCode::Char(c) => c.to_string().into_bytes(),
+        KeyCode::Enter => vec![b'\r'],
+        KeyCode::Backspace => vec![0x7f],
+        KeyCode::Tab => vec![b'\t'],
+        KeyCode::Esc => vec![0x1b],
+        _ => Vec::new(),
+    }
+}
+
+/// Receive from the target and print on the host console until the connection breaks, the target
+/// requests a binary, or `quit` is set.
+fn target_to_host(mut target: Target, quit: &AtomicBool, exit: mpsc::Sender<Result<Exit, Error>>) {
+    let stdout = io::stdout();
+    let mut buf = [0u8; 256];
+    let mut tokens_seen = 0;
+
+    let reason = 'outer: loop {
+        if quit.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let n = match target.read(&mut buf) {
+            Ok(0) => break Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+            Err(e) => break Err(Error::Connection(e)),
+        };
+
+        let mut out = stdout.lock();
+        for &c in &buf[..n] {
+            // The request comes right before the target waits for the size, so nothing follows it.
+            if c == protocol::REQUEST_TOKEN[tokens_seen] {
+                tokens_seen += 1;
+                if tokens_seen == protocol::REQUEST_TOKEN.len() {
+                    let _ = out.flush();
+                    break 'outer Ok(Exit::Requested);
+                }
+                continue;
+            }
+            let _ = out.write_all(&protocol::REQUEST_TOKEN[..tokens_seen]);
+            tokens_seen = 0;
+
+            // onlcr
+            if c == b'\n' {
+                let _ = out.write_all(b"\r");
+            }
+            let _ = out.write_all(&[c]);
+        }
+        let _ = out.flush();
+    };
+
+    let _ = exit.send(reason);
+}
+
+/// Forward key presses to the target until the user quits or the reader thread reports an exit.
+fn host_to_target(
+    target: &mut Target,
+    exit: &mpsc::Receiver<Result<Exit, Error>>,
+) -> Result<Exit, Error> {
+    loop {
+        if let Ok(reason) = exit.try_recv() {
+            return reason;
+        }
+
+        if !event::poll(Duration::from_millis(50))? {
+            continue;
+        }
+
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Release {
+                continue;
+            }
+
+            // CTRL + C in raw mode was pressed.
+            if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                return Ok(Exit::Quit);
+            }
+
+            // A TCP target cannot get a break, and there is no good place to say so in raw mode.
+            if key.code == KeyCode::Char('b') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                let _ = target.send_break();
+                continue;
+            }
+
+            target.write_all(&key_to_bytes(key))?;
+            target.flush()?;
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Connect the host console to the target. `CTRL + B` sends a break.
+///
+/// Returns why the terminal was left, and an error if the target went away. The reader thread is
+/// stopped and joined before, so that nothing but the caller reads from the target afterwards.
+pub fn run(target: &mut Target) -> Result<Exit, Error> {
+    let _raw = RawMode::enable()?;
+
+    let mut reader = target.try_clone()?;
+    reader.set_timeout(Some(READER_POLL))?;
+    let quit = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = mpsc::channel();
+    let handle = {
+        let quit = Arc::clone(&quit);
+        thread::spawn(move || target_to_host(reader, &quit, tx))
+    };
+
+    let exit = host_to_target(target, &rx);
+
+    quit.store(true, Ordering::Relaxed);
+    let _ = handle.join();
+
+    // A TCP socket shares its timeout with the reader's handle.
+    target.set_timeout(None)?;
+
+    exit
+}
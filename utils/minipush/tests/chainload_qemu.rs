@@ -0,0 +1,147 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+//! End-to-end chainload test.
+//!
+//! Boots `kernel8.img` in QEMU with the PL011 UART on a TCP socket, pushes the demo payload through
+//! the real protocol and checks that the payload runs.
+//!
+//! Needs QEMU and a built kernel, so it is `#[ignore]`d by default. Run it with `make
+//! test_chainload`, or directly with `cargo test -- --ignored`. The following environment variables
+//! override the defaults:
+//!
+//! - `KERNEL_BIN`: The loader image. Default: `kernel8.img` in the repository root.
+//! - `CHAINBOOT_DEMO_PAYLOAD`: The payload. Default: `demo_payload_rpi3.img` in the repository root.
+//! - `QEMU_BINARY`: Default: `qemu-system-aarch64`.
+//! - `QEMU_MACHINE_TYPE`: Default: `raspi3`.
+
+use minipush::{protocol, target::TargetSpec};
+use std::{
+    env, fs,
+    io::Read,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Output the demo payload prints once it runs.
+const PAYLOAD_OUTPUT: &str = "Echoing input now";
+
+/// Kills QEMU when the test ends, whether it passed or not.
+struct Qemu(Child);
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl Drop for Qemu {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn repo_file(var: &str, default: &str) -> PathBuf {
+    env::var_os(var).map(PathBuf::from).unwrap_or_else(|| {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("../..")
+            .join(default)
+    })
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn spawn_qemu(kernel: &Path, port: u16) -> Qemu {
+    let qemu = env::var("QEMU_BINARY").unwrap_or_else(|_| "qemu-system-aarch64".into());
+    let machine = env::var("QEMU_MACHINE_TYPE").unwrap_or_else(|_| "raspi3".into());
+
+    let child = Command::new(&qemu)
+        .args(["-M", &machine, "-display", "none", "-kernel"])
+        .arg(kernel)
+        .arg("-serial")
+        .arg(format!("tcp:127.0.0.1:{},server=on,wait=on", port))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap_or_else(|e| panic!("Cannot start {}: {}", qemu, e));
+
+    Qemu(child)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[test]
+#[ignore]
+fn chainload_demo_payload() {
+    let kernel = repo_file("KERNEL_BIN", "kernel8.img");
+    let payload = repo_file("CHAINBOOT_DEMO_PAYLOAD", "demo_payload_rpi3.img");
+    let image =
+        fs::read(&payload).unwrap_or_else(|e| panic!("Cannot read {}: {}", payload.display(), e));
+    assert!(
+        kernel.exists(),
+        "{} not found, run `make` first",
+        kernel.display()
+    );
+
+    let port = free_port();
+    let _qemu = spawn_qemu(&kernel, port);
+
+    // QEMU needs a moment until it listens on the socket; `wait_and_open` retries until then.
+    let spec = TargetSpec::Tcp(format!("127.0.0.1:{}", port));
+    let mut tries = 0;
+    let mut target = spec
+        .wait_and_open(|| {
+            tries += 1;
+            assert!(tries < 10, "QEMU did not open {}", spec);
+        })
+        .unwrap();
+
+    let mut banner = Vec::new();
+    protocol::wait_for_request(&mut target, Duration::from_secs(10), &mut banner).unwrap();
+    assert!(
+        String::from_utf8_lossy(&banner).contains("[ML] Requesting binary"),
+        "Unexpected banner: {}",
+        String::from_utf8_lossy(&banner)
+    );
+
+    protocol::push(&mut target, &image, 0, |_| ()).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut output = String::new();
+    let mut buf = [0u8; 256];
+    target
+        .set_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+
+    while !output.contains(PAYLOAD_OUTPUT) {
+        assert!(
+            Instant::now() < deadline,
+            "Payload did not start. Output so far:\n{}",
+            output
+        );
+
+        match target.read(&mut buf) {
+            Ok(0) => panic!("QEMU closed the connection. Output so far:\n{}", output),
+            Ok(n) => output.push_str(&String::from_utf8_lossy(&buf[..n])),
+            Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => (),
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => (),
+            Err(e) => panic!("Lost connection to QEMU: {}", e),
+        }
+    }
+
+    assert!(output.contains("[ML] Loaded! Executing the payload now"));
+}
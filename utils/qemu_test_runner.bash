@@ -0,0 +1,26 @@
+#!/usr/bin/env bash
+
+# SPDX-License-Identifier: MIT OR Apache-2.0
+#
+# Copyright (c) 2020 Andre Richter <andre.o.richter@gmail.com>
+
+# Target runner for `cargo test`, see `make test`.
+#
+# Usage: qemu_test_runner.bash <QEMU_BINARY> <QEMU_MACHINE_TYPE> <TEST_ELF>
+#
+# Converts the test ELF into a raw image and boots it in QEMU with semihosting enabled. The test
+# exits QEMU through semihosting, so QEMU's exit code is the test result. A test that hangs is
+# killed after TEST_TIMEOUT seconds (default: 30) and counts as failed.
+
+set -e
+
+QEMU_BINARY=$1
+QEMU_MACHINE_TYPE=$2
+TEST_ELF=$3
+TEST_BINARY=${TEST_ELF}.img
+
+rust-objcopy --strip-all -O binary "$TEST_ELF" "$TEST_BINARY"
+
+timeout "${TEST_TIMEOUT:-30}" \
+    "$QEMU_BINARY" -M "$QEMU_MACHINE_TYPE" -display none -serial stdio -semihosting \
+    -kernel "$TEST_BINARY"